@@ -1,280 +1,1531 @@
-use crate::ai_memory::Memory;
+use crate::ai_memory::retrieval::{self, RetrievalPolicy};
+use crate::ai_memory::{
+    ChatTurn, Fact, Memory, MemoryBackend, MemoryWriter, Retention, Summary, TurnFilter, TurnMeta,
+};
+use crate::http;
+use crate::interrupt;
+use crate::logging;
+use crate::markdown::{self, Renderer};
+use crate::providers::{
+    self, ChatMessage, ChatRequest, Completion, ImageData, ModelInfo, Provider, Registry,
+    SamplingParams, Usage,
+};
+use crate::schema;
+use crate::spinner;
+use crate::theme::{self, Stream};
+use crate::tokens;
+use crate::tools::ToolRegistry;
 use anyhow::{anyhow, Result};
-use futures_util::StreamExt;
-use rand::{thread_rng, Rng};
-use reqwest::{header, Client};
-use reqwest_eventsource::EventSource;
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
 use std::env;
-use tokio::time::{sleep, Duration};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, info, warn};
 
-#[derive(Serialize, Deserialize, Clone)]
-struct OpenAIMessage {
-    role: String,   // "user" | "assistant" | "system"
-    content: String,
+/// A provider/model pair to send a turn to.
+#[derive(Clone)]
+pub struct Target {
+    pub provider: String, // key into the registry, e.g. "openai"
+    pub model: String,    // "gpt-5"
 }
 
-#[derive(Serialize, Deserialize)]
-struct OpenAIRequest {
-    model: String,
-    messages: Vec<OpenAIMessage>,
-    stream: bool,
+const SUMMARY_INSTRUCTION: &str = "You maintain the long-term memory of a conversation between a user and an AI assistant. \
+Rewrite the summary so far (if any) and the turns that follow into one updated summary. Keep decisions, facts about the \
+user and their project, open questions and anything the assistant promised; drop small talk. Write plain prose, at most \
+a few paragraphs, and nothing but the summary.";
+
+// a model stuck calling tools shouldn't loop (and bill) forever
+const MAX_TOOL_ROUNDS: usize = 8;
+
+// with a context budget, the newest turns considered for the history
+const CONTEXT_POOL: usize = 1000;
+// AI_CONTEXT_TOKENS=auto on a model missing from tokens::context_window
+const DEFAULT_CONTEXT_WINDOW: usize = 8_192;
+// room left for the answer in auto mode when AI_MAX_TOKENS is unset
+const ANSWER_RESERVE: usize = 4_096;
+
+/// `AI_CONTEXT_TOKENS`: a token count per request, or `auto` for the model's context window.
+#[derive(Clone, Copy)]
+enum ContextBudget {
+    Fixed(usize),
+    Auto,
 }
 
-#[derive(Deserialize)]
-struct StreamChunkChoiceDelta {
-    content: Option<String>,
-    // role is present in the schema but not needed; keep to avoid schema drift warnings
-    #[allow(dead_code)]
-    role: Option<String>,
+/// Where turns are embedded for semantic recall, and how many to recall per prompt.
+struct Recall {
+    target: Target,
+    k: usize,
 }
 
-#[derive(Deserialize)]
-struct StreamChunkChoice {
-    delta: StreamChunkChoiceDelta,
-    #[allow(dead_code)]
-    finish_reason: Option<String>,
+impl Recall {
+    /// Stored with each vector; vectors from different models can't be compared.
+    fn key(&self) -> String {
+        format!("{}:{}", self.target.provider, self.target.model)
+    }
+}
+
+/// What `replay` rebuilt.
+pub struct Replay {
+    pub session: String,
+    /// The session's system prompt as it is now; older versions are not kept.
+    pub system_prompt: Option<String>,
+    pub facts: Vec<Fact>,
+    pub summary: Option<Summary>,
+    pub turns: Vec<ChatTurn>,
 }
 
-#[derive(Deserialize)]
-struct StreamChunk {
-    choices: Vec<StreamChunkChoice>,
+/// One model's answer from `compare`.
+pub struct Comparison {
+    pub target: Target,
+    pub elapsed: Duration,
+    pub result: Result<Completion>,
 }
 
 pub struct AiAgent {
-    provider: String,     // "openai"
-    model: String,        // "gpt-5"
-    client: Client,
-    mem: Memory,
+    registry: Registry,
+    targets: Vec<Target>, // tried in order until one answers
+    params: SamplingParams,
+    mem: Box<dyn MemoryBackend>,
+    writer: Option<MemoryWriter>, // stores exchanges in the background, for local SQLite files
+    global: Option<Memory>,       // the user's global DB, read-only, when working in a project
+    global_turns: usize,
     memory_turns: usize,
+    cache_ttl: Option<i64>, // seconds; None when the response cache is off
+    prompt_cache: bool,
+    tools: Option<ToolRegistry>,        // None unless AI_TOOLS is on
+    recall: Option<Recall>,             // None unless AI_SEMANTIC_TURNS is set
+    cross_session_turns: usize,         // AI_CROSS_SESSION_TURNS; 0 keeps sessions apart
+    retrieval: Option<RetrievalPolicy>, // None keeps the newest AI_MEMORY_TURNS
+    history_budget: Option<usize>,      // tokens; None keeps the plain sliding window
+    retention: Retention,
+    dedup: bool, // AI_MEMORY_DEDUP: don't store an exchange the session already has
+    context_budget: Option<ContextBudget>, // None keeps the fixed AI_MEMORY_TURNS window
+    system_override: Option<String>, // --system, instead of the session's system prompt
+    default_system: Option<String>, // AI_SYSTEM_PROMPT, for sessions without one
+    json_output: bool, // --json: one object on stdout once the answer is complete
+    raw_output: bool, // --raw: the model's text and nothing else on stdout
+    context_start: Option<DateTime<Utc>>, // /clear in chat: earlier turns stay out of the history
+    markdown: bool, // AI_MARKDOWN: style answers for the terminal instead of echoing the source
+    wrap: Option<usize>, // AI_WIDTH, or the terminal's; None leaves lines as they come
+    assistant_color: Option<String>, // AI_ASSISTANT_COLOR, when colour goes to stdout
+    sink: Option<UnboundedSender<String>>, // `mindlink tui`: answers go here as they stream, not to stdout
     #[allow(dead_code)]
     project_mode: bool,
 }
 
+fn env_parse<T: std::str::FromStr>(var: &str) -> Option<T> {
+    env::var(var).ok().and_then(|s| s.parse().ok())
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b)).max(f32::EPSILON)
+}
+
+/// Recalled turns as a preamble to the prompt. It rides on the user message rather than
+/// a system message so the cacheable prefix is the same as without recall.
+fn recalled_context(turns: &[ChatTurn]) -> String {
+    let mut s = String::from("Possibly relevant earlier conversation:\n");
+    for t in turns {
+        s.push_str(&format!(
+            "[{}] {}: {}\n",
+            t.ts.format("%Y-%m-%d"),
+            t.role,
+            t.content
+        ));
+    }
+    s.push_str("---\n\n");
+    s
+}
+
+fn cross_session_context(turns: &[(String, ChatTurn)]) -> String {
+    let mut s = String::from("Possibly relevant conversation from other sessions:\n");
+    for (session, t) in turns {
+        s.push_str(&format!(
+            "[{}, from session {}] {}: {}\n",
+            t.ts.format("%Y-%m-%d"),
+            session,
+            t.role,
+            t.content
+        ));
+    }
+    s.push_str("---\n\n");
+    s
+}
+
+/// Store a question and its answer, unless `dedup` finds the session holds them already; the ids
+/// of the two new turns.
+fn store_exchange(
+    mem: &dyn MemoryBackend,
+    question: &str,
+    answer: &str,
+    meta: &TurnMeta,
+    dedup: bool,
+) -> Result<Option<(i64, i64)>> {
+    if dedup && mem.has_exchange(question, answer)? {
+        note!("(same question and answer already in memory; not stored again)");
+        return Ok(None);
+    }
+    let user_id = mem.append("user", question)?;
+    let answer_id = mem.append_meta("assistant", answer, meta)?;
+    Ok(Some((user_id, answer_id)))
+}
+
+fn default_model(registry: &Registry, provider: &str) -> String {
+    registry
+        .get(provider)
+        .map(|p| p.default_model().to_string())
+        .unwrap_or_else(|_| "gpt-5".into())
+}
+
+/// What `--json` prints: the answer and what is known about how it was produced.
+#[derive(Serialize)]
+struct JsonAnswer<'a> {
+    answer: &'a str,
+    provider: Option<&'a str>,
+    model: Option<&'a str>,
+    finish_reason: Option<&'a str>,
+    usage: Option<Usage>,
+    latency_ms: Option<i64>,
+    cached: bool,
+}
+
+impl JsonAnswer<'_> {
+    fn print(&self) -> Result<()> {
+        println!("{}", serde_json::to_string(self)?);
+        Ok(())
+    }
+}
+
+fn turn_meta(out: &Completion, started: Instant) -> TurnMeta {
+    TurnMeta {
+        prompt_tokens: out.usage.map(|u| u.prompt_tokens as i64),
+        completion_tokens: out.usage.map(|u| u.completion_tokens as i64),
+        fingerprint: out.fingerprint.clone(),
+        provider: out.provider.clone(),
+        model: out.model.clone(),
+        latency_ms: Some(started.elapsed().as_millis() as i64),
+    }
+}
+
+/// Provider a bare model name most likely belongs to, judged by its vendor prefix.
+fn guess_provider(model: &str) -> Option<&'static str> {
+    let provider = match model {
+        // o1, o3-mini, o4-mini...
+        m if m.starts_with("gpt-")
+            || m.starts_with("chatgpt")
+            || m.strip_prefix('o')
+                .is_some_and(|r| r.starts_with(|c: char| c.is_ascii_digit())) =>
+        {
+            "openai"
+        }
+        m if m.starts_with("claude") => "anthropic",
+        m if m.starts_with("mistral") || m.starts_with("codestral") => "mistral",
+        _ => return None,
+    };
+    Some(provider)
+}
+
+/// Parse `provider[:model],...`; the model may itself contain colons (e.g. `ollama:llama3:8b`).
+fn parse_chain(chain: &str, registry: &Registry) -> Vec<Target> {
+    chain
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|entry| match entry.split_once(':') {
+            Some((provider, model)) => Target {
+                provider: provider.to_string(),
+                model: model.to_string(),
+            },
+            None => Target {
+                provider: entry.to_string(),
+                model: default_model(registry, entry),
+            },
+        })
+        .collect()
+}
+
+/// The targets to try in turn: `AI_PROVIDERS`, else `AI_PROVIDER` (or the provider the
+/// credentials point to) with `AI_MODEL`.
+fn configured_targets(registry: &Registry) -> Vec<Target> {
+    match env::var("AI_PROVIDERS") {
+        Ok(chain) if !chain.trim().is_empty() => parse_chain(&chain, registry),
+        _ => {
+            let provider = match env::var("AI_PROVIDER") {
+                Ok(p) if !p.trim().is_empty() => p,
+                _ => match providers::detect_provider() {
+                    Some((provider, evidence)) => {
+                        note!(
+                            "(provider: {}, detected from {}; set AI_PROVIDER to choose)",
+                            provider,
+                            evidence
+                        );
+                        provider.to_string()
+                    }
+                    None => {
+                        note!("(no provider set up; `mindlink init` asks what it needs)");
+                        "openai".into()
+                    }
+                },
+            };
+            let model = env::var("AI_MODEL").unwrap_or_else(|_| default_model(registry, &provider));
+            vec![Target { provider, model }]
+        }
+    }
+}
+
+/// For `doctor`: ask every configured target's provider for its models, which takes a key it
+/// accepts and a server that answers within `timeout`. Each target comes with the models its
+/// provider offers, or with what went wrong.
+pub async fn check_targets(timeout: Duration) -> Result<Vec<(Target, Result<Vec<ModelInfo>>)>> {
+    let registry = Registry::with_defaults(http::client)?;
+    let mut results = Vec::new();
+    for target in configured_targets(&registry) {
+        let models = match registry.get(&target.provider) {
+            Ok(provider) => match tokio::time::timeout(timeout, provider.list_models()).await {
+                Ok(models) => models,
+                Err(elapsed) => Err(anyhow::Error::new(elapsed)
+                    .context(format!("no answer within {}s", timeout.as_secs()))),
+            },
+            Err(e) => Err(e),
+        };
+        results.push((target, models));
+    }
+    Ok(results)
+}
+
 impl AiAgent {
-    pub fn new(memory_path: &str, project_mode: bool) -> Result<Self> {
-        dotenvy::dotenv().ok();
-        let provider = env::var("AI_PROVIDER").unwrap_or_else(|_| "openai".into());
-        let model = env::var("AI_MODEL").unwrap_or_else(|_| "gpt-5".into());
-        let client = Client::builder().build()?;
-        let mem = Memory::open(memory_path)?;
-        let memory_turns = env::var("AI_MEMORY_TURNS")
+    pub fn new(
+        mem: Box<dyn MemoryBackend>,
+        project_mode: bool,
+        global_path: Option<&str>,
+    ) -> Result<Self> {
+        let registry = Registry::with_defaults(http::client)?;
+        let targets = configured_targets(&registry);
+        let params = SamplingParams {
+            temperature: env_parse("AI_TEMPERATURE"),
+            top_p: env_parse("AI_TOP_P"),
+            max_tokens: env_parse("AI_MAX_TOKENS"),
+            reasoning_effort: env::var("AI_REASONING_EFFORT")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            schema: match env::var("AI_SCHEMA") {
+                Ok(spec) if !spec.trim().is_empty() => Some(schema::load(&spec)?),
+                _ => None,
+            },
+            seed: env_parse("AI_SEED"),
+        };
+        let global = match global_path {
+            Some(_)
+                if matches!(
+                    env::var("AI_GLOBAL_MEMORY").as_deref(),
+                    Ok("0") | Ok("false")
+                ) =>
+            {
+                None
+            }
+            Some(path) => match Memory::open_readonly(path) {
+                Ok(m) => Some(m),
+                Err(e) => {
+                    warn!("global memory not read: {:#}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let global_turns = env_parse("AI_GLOBAL_TURNS").unwrap_or(4);
+        let memory_turns = env_parse("AI_MEMORY_TURNS").unwrap_or(6);
+        // opt-in: only worth it for scripts repeating identical one-off prompts
+        let cache_ttl = match env::var("AI_CACHE").as_deref() {
+            Ok("1") | Ok("true") => Some(env_parse("AI_CACHE_TTL").unwrap_or(86_400)),
+            _ => None,
+        };
+        let markdown = match env::var("AI_MARKDOWN").as_deref() {
+            Ok("always") => true,
+            Ok("never") => false,
+            _ => theme::enabled(Stream::Stdout),
+        };
+        let prompt_cache = !matches!(
+            env::var("AI_PROMPT_CACHE").as_deref(),
+            Ok("0") | Ok("false")
+        );
+        let tools = match env::var("AI_TOOLS").as_deref() {
+            Ok("1") | Ok("true") => Some(ToolRegistry::with_defaults()),
+            _ => None,
+        };
+        let history_budget = env_parse::<usize>("AI_HISTORY_BUDGET").filter(|&n| n > 0);
+        let context_budget = match env::var("AI_CONTEXT_TOKENS").as_deref() {
+            Ok("auto") => Some(ContextBudget::Auto),
+            Ok(n) => n.parse().ok().filter(|&n| n > 0).map(ContextBudget::Fixed),
+            Err(_) => None,
+        };
+        let dedup = matches!(env::var("AI_MEMORY_DEDUP").as_deref(), Ok("1") | Ok("true"));
+        let retention = Retention {
+            max_turns: env_parse("AI_MEMORY_MAX_TURNS"),
+            max_age_days: env_parse("AI_MEMORY_MAX_AGE_DAYS"),
+            max_size_mb: env_parse("AI_MEMORY_MAX_SIZE_MB"),
+        };
+        let recall = match env_parse::<usize>("AI_SEMANTIC_TURNS") {
+            Some(k) if k > 0 => {
+                let provider = env::var("AI_EMBED_PROVIDER")
+                    .ok()
+                    .filter(|p| !p.trim().is_empty())
+                    .or_else(|| targets.first().map(|t| t.provider.clone()))
+                    .unwrap_or_else(|| "openai".into());
+                let model = match env::var("AI_EMBED_MODEL") {
+                    Ok(m) if !m.trim().is_empty() => m,
+                    _ => registry
+                        .get(&provider)?
+                        .embedding_model()
+                        .map(str::to_string)
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "{} has no embeddings; set AI_EMBED_PROVIDER (and AI_EMBED_MODEL) for AI_SEMANTIC_TURNS",
+                                provider
+                            )
+                        })?,
+                };
+                Some(Recall {
+                    target: Target { provider, model },
+                    k,
+                })
+            }
+            _ => None,
+        };
+
+        let cross_session_turns = env_parse("AI_CROSS_SESSION_TURNS").unwrap_or(0);
+        let writer = match mem.as_local() {
+            Some(local) => MemoryWriter::for_memory(local)?,
+            None => None,
+        };
+        let retrieval = match env::var("AI_RETRIEVAL").as_deref() {
+            Err(_) | Ok("") | Ok("recent") => None,
+            Ok("scored") => {
+                let mut policy = RetrievalPolicy::default();
+                if let Some(h) =
+                    env_parse::<f64>("AI_RETRIEVAL_HALF_LIFE_HOURS").filter(|h| *h > 0.0)
+                {
+                    policy.half_life_hours = h;
+                }
+                Some(policy)
+            }
+            Ok(other) => {
+                return Err(anyhow!(
+                    "unknown AI_RETRIEVAL {} (expected recent or scored)",
+                    other
+                ))
+            }
+        };
+
+        let default_system = env::var("AI_SYSTEM_PROMPT")
             .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(6);
+            .filter(|p| !p.trim().is_empty());
 
         Ok(Self {
-            provider,
-            model,
-            client,
+            registry,
+            targets,
+            params,
             mem,
+            writer,
+            global,
+            global_turns,
             memory_turns,
+            cache_ttl,
+            prompt_cache,
+            tools,
+            recall,
+            cross_session_turns,
+            retrieval,
+            history_budget,
+            retention,
+            dedup,
+            context_budget,
+            system_override: None,
+            default_system,
+            json_output: false,
+            raw_output: false,
+            context_start: None,
+            markdown,
+            wrap: markdown::wrap_width(),
+            assistant_color: theme::assistant(),
+            sink: None,
             project_mode,
         })
     }
 
-    fn build_history(&self) -> Result<Vec<OpenAIMessage>> {
-        let history = self.mem.last_turns(self.memory_turns)?;
-        let mut msgs = Vec::with_capacity(history.len());
-        for h in history {
-            msgs.push(OpenAIMessage {
-                role: h.role,
-                content: h.content,
-            });
+    /// Send `prompt` as the system prompt instead of the session's own, for this run only.
+    pub fn set_system(&mut self, prompt: &str) {
+        self.system_override = Some(prompt.to_string());
+    }
+
+    /// Print the answer and its metadata as one JSON object instead of streaming it.
+    pub fn set_json_output(&mut self) {
+        self.json_output = true;
+    }
+
+    /// Print exactly the text the model sent, without the newline added after it.
+    pub fn set_raw_output(&mut self) {
+        self.raw_output = true;
+        self.wrap = None;
+        self.assistant_color = None;
+    }
+
+    /// Send answers, unstyled, piece by piece to `sink` instead of printing them.
+    pub fn set_sink(&mut self, sink: UnboundedSender<String>) {
+        self.sink = Some(sink);
+        self.markdown = false;
+        self.wrap = None;
+        self.assistant_color = None;
+    }
+
+    /// An answer's text in `AI_ASSISTANT_COLOR`.
+    fn paint(&self, text: &str) -> String {
+        theme::paint(text, self.assistant_color.as_deref())
+    }
+
+    /// What answers go through on the way to the terminal: the markdown styling, or just the
+    /// wrapping, or nothing.
+    fn renderer(&self) -> Option<Renderer> {
+        match (self.markdown, self.wrap) {
+            (true, width) => Some(Renderer::new(false, width)),
+            (false, Some(width)) => Some(Renderer::new(true, Some(width))),
+            (false, None) => None,
+        }
+    }
+
+    /// `answer` as it was printed: rendered, if it was, and in `AI_ASSISTANT_COLOR`.
+    pub fn shown(&self, answer: &str) -> String {
+        match self.renderer() {
+            Some(r) => self.paint(&r.render(answer)),
+            None => self.paint(answer),
+        }
+    }
+
+    /// Leave the session's turns from before `start` (and its summary) out of the history sent
+    /// from now on; pinned turns are still sent. `None` sends the whole history again.
+    pub fn set_context_start(&mut self, start: Option<DateTime<Utc>>) {
+        self.context_start = start;
+    }
+
+    /// The system prompt sent first: `--system`, else the session's, else `AI_SYSTEM_PROMPT`.
+    pub fn system_prompt(&self) -> Result<Option<String>> {
+        Ok(match &self.system_override {
+            Some(prompt) => Some(prompt.clone()),
+            None => self
+                .mem
+                .system_prompt()?
+                .or_else(|| self.default_system.clone()),
+        })
+    }
+
+    /// Roughly how many tokens a request sends before the prompt itself: system prompt, facts
+    /// and history.
+    pub fn context_estimate(&self) -> Result<usize> {
+        let (messages, _) = self.build_messages("", &[], true)?;
+        Ok(messages
+            .iter()
+            .map(|m| tokens::estimate(&m.content) + 4)
+            .sum())
+    }
+
+    /// Override the primary model; `provider:model` also switches provider.
+    pub fn set_model(&mut self, spec: &str) {
+        let target = self.target_for(spec);
+        match self.targets.first_mut() {
+            Some(first) => *first = target,
+            None => self.targets.push(target),
+        }
+    }
+
+    /// `provider:model`, or a bare model on the primary provider.
+    fn target_for(&self, spec: &str) -> Target {
+        match spec.split_once(':') {
+            Some((provider, model)) if self.registry.contains(provider) => Target {
+                provider: provider.to_string(),
+                model: model.to_string(),
+            },
+            _ => Target {
+                provider: self
+                    .targets
+                    .first()
+                    .map(|t| t.provider.clone())
+                    .unwrap_or_else(|| "openai".into()),
+                model: spec.to_string(),
+            },
+        }
+    }
+
+    /// The primary `provider:model`.
+    pub fn model(&self) -> String {
+        self.targets
+            .first()
+            .map(|t| format!("{}:{}", t.provider, t.model))
+            .unwrap_or_default()
+    }
+
+    fn provider(&self, target: &Target) -> Result<Arc<dyn Provider>> {
+        self.registry.get(&target.provider)
+    }
+
+    /// The turns sent verbatim, and whether they start where the previous turn's did.
+    /// With a history budget that is everything after the summary, which only moves on
+    /// compaction. Otherwise, once the window starts sliding, the oldest turn changes
+    /// every time and no prefix repeats.
+    fn history_turns(&self) -> Result<(Option<Summary>, Vec<ChatTurn>, bool)> {
+        if self.history_budget.is_some() {
+            let summary = self.mem.latest_summary()?;
+            let since = summary.as_ref().map_or(0, |s| s.through_id);
+            return Ok((summary, self.mem.turns_after(since)?, true));
         }
-        Ok(msgs)
+        let window = match self.context_budget {
+            Some(_) => CONTEXT_POOL,
+            None => self.memory_turns,
+        };
+        if let (Some(policy), None) = (&self.retrieval, self.context_budget) {
+            let pool = self.mem.last_turns(retrieval::POOL.max(window + 1))?;
+            let complete = pool.len() <= window;
+            let ids: Vec<i64> = pool.iter().map(|t| t.id).collect();
+            let references = self.mem.references(&ids)?;
+            let history = policy.select(pool, &references, window, Utc::now());
+            return Ok((None, history, complete));
+        }
+        let mut history = self.mem.last_turns(window + 1)?;
+        let complete = history.len() <= window;
+        if !complete {
+            history.remove(0);
+        }
+        Ok((None, history, complete))
+    }
+
+    /// Pinned turns that `window` leaves out, oldest first.
+    fn pinned_outside(&self, window: &[ChatTurn]) -> Result<Vec<ChatTurn>> {
+        let mut pinned = self.mem.pinned_turns()?;
+        pinned.retain(|p| !window.iter().any(|t| t.id == p.id));
+        Ok(pinned)
     }
 
-    pub async fn ask_streaming(&self, user_prompt: &str) -> Result<String> {
-        if self.provider != "openai" {
-            return Err(anyhow!("Only 'openai' provider is enabled in this build."));
+    /// Tokens a whole request may take under `AI_CONTEXT_TOKENS`; `auto` leaves room for the answer.
+    fn context_tokens(&self) -> Option<usize> {
+        match self.context_budget? {
+            ContextBudget::Fixed(n) => Some(n),
+            ContextBudget::Auto => {
+                let model = &self.targets.first()?.model;
+                let window = tokens::context_window(model).unwrap_or(DEFAULT_CONTEXT_WINDOW);
+                let reserve = self
+                    .params
+                    .max_tokens
+                    .map_or(ANSWER_RESERVE, |n| n as usize);
+                Some(window.saturating_sub(reserve))
+            }
         }
-        let api_key =
-            env::var("OPENAI_API_KEY").map_err(|_| anyhow!("OPENAI_API_KEY not set"))?;
+    }
 
-        let mut messages = self.build_history()?;
-        messages.push(OpenAIMessage {
-            role: "user".into(),
-            content: user_prompt.into(),
+    /// History as messages: the rolling summary, if any, then pinned turns that fell out
+    /// of the window, then the window itself. With a `budget` (tokens), turns longer than a
+    /// quarter of it are cut down and the window keeps only as many recent turns as fit
+    /// after the summary and all pinned turns.
+    fn build_history(&self, budget: Option<usize>) -> Result<(Vec<ChatMessage>, bool)> {
+        let (mut summary, mut history, mut complete) = self.history_turns()?;
+        if let Some(start) = self.context_start {
+            summary = None;
+            history.retain(|t| t.ts >= start || t.pinned);
+        }
+        let mut pinned = self.pinned_outside(&history)?;
+        let summary = summary.map(|s| {
+            ChatMessage::new(
+                "system",
+                format!("Summary of the earlier conversation:\n{}", s.content),
+            )
         });
+        if let Some(budget) = budget {
+            let cap = (budget / 4).max(1);
+            let cost = |t: &ChatTurn| tokens::estimate(&t.content) + 4;
+            for t in pinned.iter_mut().chain(history.iter_mut()) {
+                t.content = tokens::truncate(&t.content, cap);
+            }
+            let fixed: usize = summary
+                .iter()
+                .map(|m| tokens::estimate(&m.content) + 4)
+                .sum::<usize>()
+                + pinned.iter().map(cost).sum::<usize>()
+                + history.iter().filter(|t| t.pinned).map(cost).sum::<usize>();
+            let mut left = budget.saturating_sub(fixed);
+            // turns before `cut` don't fit, pinned ones excepted
+            let mut cut = 0;
+            for (i, t) in history.iter().enumerate().rev() {
+                if t.pinned {
+                    continue;
+                }
+                let n = cost(t);
+                if n > left {
+                    cut = i + 1;
+                    break;
+                }
+                left -= n;
+            }
+            if cut > 0 {
+                let mut i = 0;
+                history.retain(|t| {
+                    i += 1;
+                    i > cut || t.pinned
+                });
+                complete = false;
+            }
+        }
+        let mut msgs = Vec::with_capacity(pinned.len() + history.len() + 1);
+        msgs.extend(summary);
+        for h in pinned.into_iter().chain(history) {
+            msgs.push(ChatMessage::new(&h.role, h.content));
+        }
+        Ok((msgs, complete))
+    }
 
-        let req = OpenAIRequest {
-            model: self.model.clone(),
-            messages,
-            stream: true,
+    /// Once the verbatim history outgrows `AI_HISTORY_BUDGET`, fold all but the newest
+    /// `AI_MEMORY_TURNS` turns into the rolling summary. Best-effort, like recall.
+    async fn compact(&self) {
+        let Some(budget) = self.history_budget else {
+            return;
         };
+        if let Err(e) = self.try_compact(budget).await {
+            warn!("history not compacted: {:#}", e);
+        }
+    }
 
-        let max_retries: usize = env::var("AI_MAX_RETRIES")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(5);
-        let base_backoff_ms: u64 = env::var("AI_BACKOFF_MS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(300);
+    async fn try_compact(&self, budget: usize) -> Result<()> {
+        let target = self
+            .targets
+            .first()
+            .ok_or_else(|| anyhow!("No provider configured."))?;
+        let provider = self.provider(target)?;
+        let (summary, turns, _) = self.history_turns()?;
+        let verbatim: Vec<ChatMessage> = turns
+            .iter()
+            .map(|t| ChatMessage::new(&t.role, t.content.clone()))
+            .collect();
+        if provider.count_tokens(&verbatim) <= budget || turns.len() <= self.memory_turns {
+            return Ok(());
+        }
+        let old = &turns[..turns.len() - self.memory_turns];
+        let mut transcript = String::new();
+        if let Some(s) = &summary {
+            transcript.push_str(&format!("Summary so far:\n{}\n\n", s.content));
+        }
+        for t in old {
+            transcript.push_str(&format!("{}: {}\n\n", t.role, t.content));
+        }
+        // the user's sampling settings and schema are for answers, not for this
+        let req = ChatRequest {
+            model: target.model.clone(),
+            messages: vec![
+                ChatMessage::new("system", SUMMARY_INSTRUCTION),
+                ChatMessage::new("user", transcript),
+            ],
+            params: SamplingParams::default(),
+            cache_prefix: 0,
+            tools: Vec::new(),
+        };
+        let out = provider.complete(&req).await?;
+        let text = out.text.trim();
+        if text.is_empty() {
+            return Err(anyhow!("the model returned an empty summary"));
+        }
+        let through = old[old.len() - 1].id;
+        self.mem.put_summary(through, text)?;
+        note!(
+            "(compacted {} older turns into the conversation summary)",
+            old.len()
+        );
+        Ok(())
+    }
 
-        let mut attempts = 0usize;
-        let mut acc = String::new();
+    /// Standing instructions that precede the conversation.
+    fn system_messages(&self) -> Vec<ChatMessage> {
+        match &self.params.schema {
+            Some(schema) => vec![ChatMessage::new("system", schema::instruction(schema))],
+            None => Vec::new(),
+        }
+    }
 
-        loop {
-            attempts += 1;
+    async fn embed(&self, recall: &Recall, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let vectors = self
+            .provider(&recall.target)?
+            .embed(&recall.target.model, texts)
+            .await?;
+        if vectors.len() != texts.len() {
+            return Err(anyhow!(
+                "asked for {} embeddings, got {}",
+                texts.len(),
+                vectors.len()
+            ));
+        }
+        Ok(vectors)
+    }
 
-            let req_builder = self
-                .client
-                .post("https://api.openai.com/v1/chat/completions")
-                .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
-                .header(header::CONTENT_TYPE, "application/json")
-                .json(&req);
+    /// Earlier turns of the session most similar to the prompt, from outside the recent
+    /// window, oldest first; along with the prompt's vector, to store with it later.
+    /// Recall is best-effort, so failures only print a note.
+    async fn recall(&self, user_prompt: &str) -> (Option<Vec<f32>>, Vec<ChatTurn>) {
+        let Some(recall) = &self.recall else {
+            return (None, Vec::new());
+        };
+        match self.try_recall(recall, user_prompt).await {
+            Ok((query, turns)) => (Some(query), turns),
+            Err(e) => {
+                warn!("semantic recall skipped: {:#}", e);
+                (None, Vec::new())
+            }
+        }
+    }
 
-            let mut es = match EventSource::new(req_builder) {
-                Ok(es) => es,
-                Err(e) => {
-                    let msg = e.to_string();
-                    if (msg.contains("429") || msg.contains("Too Many Requests"))
-                        && attempts <= max_retries
-                    {
-                        let jitter: u64 = thread_rng().gen_range(0..250);
-                        let backoff =
-                            Duration::from_millis(base_backoff_ms * attempts as u64 + jitter);
-                        eprintln!("rate limited (429), retrying in {:?}...", backoff);
-                        sleep(backoff).await;
-                        continue;
-                    }
-                    if attempts > max_retries {
-                        eprintln!(
-                            "stream failed after {} attempts; falling back to non-stream.",
-                            attempts - 1
-                        );
-                        let out = self.ask_once(user_prompt).await?;
-                        println!("{}", out);
-                        return Ok(out);
-                    }
-                    return Err(anyhow!(e));
+    async fn try_recall(
+        &self,
+        recall: &Recall,
+        user_prompt: &str,
+    ) -> Result<(Vec<f32>, Vec<ChatTurn>)> {
+        let query = self
+            .embed(recall, &[user_prompt.to_string()])
+            .await?
+            .remove(0);
+        // turns that are sent anyway
+        let window = self.history_turns()?.1;
+        let recent: HashSet<i64> = window
+            .iter()
+            .chain(&self.pinned_outside(&window)?)
+            .map(|t| t.id)
+            .collect();
+        let mut scored: Vec<(f32, i64)> = self
+            .mem
+            .embeddings(&recall.key())?
+            .into_iter()
+            .filter(|(id, _)| !recent.contains(id))
+            .map(|(id, v)| (cosine(&query, &v), id))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let ids: Vec<i64> = scored.iter().take(recall.k).map(|(_, id)| *id).collect();
+        self.mem.note_references(&ids)?;
+        Ok((query, self.mem.turns_by_id(&ids)?))
+    }
+
+    /// Save a finished exchange, embedding both sides when semantic recall is on.
+    async fn remember(
+        &self,
+        user_prompt: &str,
+        answer: &str,
+        meta: &TurnMeta,
+        prompt_vector: Option<Vec<f32>>,
+    ) -> Result<()> {
+        // compaction and embedding need the stored turns right away, so they keep writes inline
+        if let (Some(writer), None, None) = (&self.writer, &self.recall, self.history_budget) {
+            let (question, answer, meta) =
+                (user_prompt.to_string(), answer.to_string(), meta.clone());
+            let dedup = self.dedup;
+            writer.send(move |mem| {
+                if let Err(e) = store_exchange(mem, &question, &answer, &meta, dedup) {
+                    warn!("exchange not saved to memory: {:#}", e);
                 }
-            };
+            });
+            return Ok(());
+        }
+        let Some((user_id, answer_id)) =
+            store_exchange(self.mem.as_ref(), user_prompt, answer, meta, self.dedup)?
+        else {
+            return Ok(());
+        };
+        self.compact().await;
+        let Some(recall) = &self.recall else {
+            return Ok(());
+        };
+        let (ids, texts) = match &prompt_vector {
+            Some(_) => (vec![answer_id], vec![answer.to_string()]),
+            None => (
+                vec![user_id, answer_id],
+                vec![user_prompt.to_string(), answer.to_string()],
+            ),
+        };
+        match self.embed(recall, &texts).await {
+            Ok(vectors) => {
+                let key = recall.key();
+                if let Some(v) = &prompt_vector {
+                    self.mem.put_embedding(user_id, &key, v)?;
+                }
+                for (id, v) in ids.into_iter().zip(vectors) {
+                    self.mem.put_embedding(id, &key, &v)?;
+                }
+            }
+            Err(e) => warn!("turn not embedded for semantic recall: {:#}", e),
+        }
+        Ok(())
+    }
+
+    /// Wait for exchanges still being stored in the background, before reading memory or exiting.
+    pub async fn flush_memory(&self) {
+        if let Some(writer) = &self.writer {
+            writer.flush().await;
+        }
+    }
+
+    /// Read from the global DB, if there is one; it may be older or busy, so failures only warn.
+    fn read_global<T: Default>(&self, read: impl Fn(&Memory) -> Result<T>) -> T {
+        let Some(global) = &self.global else {
+            return T::default();
+        };
+        read(global).unwrap_or_else(|e| {
+            warn!("global memory not read: {:#}", e);
+            T::default()
+        })
+    }
+
+    /// Remembered facts, from this DB and inside a project the global one, as a standing system
+    /// message if there are any.
+    fn facts_message(&self) -> Result<Option<ChatMessage>> {
+        let mut facts: Vec<String> = self.mem.facts()?.into_iter().map(|f| f.content).collect();
+        for f in self.read_global(|g| g.facts()) {
+            if !facts.contains(&f.content) {
+                facts.push(f.content);
+            }
+        }
+        if facts.is_empty() {
+            return Ok(None);
+        }
+        let mut text = String::from("Things the user asked you to remember:\n");
+        for f in facts {
+            text.push_str(&format!("- {}\n", f));
+        }
+        Ok(Some(ChatMessage::new("system", text)))
+    }
 
-            while let Some(event) = es.next().await {
-                match event {
-                    Ok(reqwest_eventsource::Event::Open) => {
-                        // connected; nothing to print
+    /// Turns of the other sessions that share words with the prompt, when AI_CROSS_SESSION_TURNS is
+    /// set; `dry` leaves the references they get uncounted. Best-effort like semantic recall, so
+    /// failures only print a note.
+    fn cross_session_turns(&self, user_prompt: &str, dry: bool) -> Vec<(String, ChatTurn)> {
+        // nothing to search for (`context_estimate`)
+        if self.cross_session_turns == 0 || user_prompt.trim().is_empty() {
+            return Vec::new();
+        }
+        let found = self
+            .mem
+            .other_session_turns(user_prompt, self.cross_session_turns)
+            .and_then(|turns| {
+                if !dry {
+                    let ids: Vec<i64> = turns.iter().map(|(_, t)| t.id).collect();
+                    self.mem.note_references(&ids)?;
+                }
+                Ok(turns)
+            });
+        found.unwrap_or_else(|e| {
+            warn!("cross-session recall skipped: {:#}", e);
+            Vec::new()
+        })
+    }
+
+    /// The latest turns of the global DB, as background for a project conversation.
+    fn global_turns_message(&self) -> Option<ChatMessage> {
+        if self.global_turns == 0 {
+            return None;
+        }
+        let turns = self.read_global(|g| g.last_turns(self.global_turns));
+        if turns.is_empty() {
+            return None;
+        }
+        let mut text =
+            String::from("Recent conversation with the user outside this project, for context:\n");
+        for t in turns {
+            text.push_str(&format!(
+                "[{}] {}: {}\n",
+                t.ts.format("%Y-%m-%d"),
+                t.role,
+                t.content
+            ));
+        }
+        Some(ChatMessage::new("system", text))
+    }
+
+    /// The messages for a turn, and how many leading ones repeat the previous turn's; `dry`
+    /// builds them without writing to memory.
+    fn build_messages(
+        &self,
+        user_prompt: &str,
+        recalled: &[ChatTurn],
+        dry: bool,
+    ) -> Result<(Vec<ChatMessage>, usize)> {
+        let mut messages: Vec<ChatMessage> = self
+            .system_prompt()?
+            .map(|p| ChatMessage::new("system", p))
+            .into_iter()
+            .collect();
+        messages.extend(self.system_messages());
+        messages.extend(self.facts_message()?);
+        messages.extend(self.global_turns_message());
+        // like recalled turns, these change with every prompt, so they go with it rather than
+        // ahead of the history, where they would break the cacheable prefix
+        let mut prompt = String::new();
+        if !recalled.is_empty() {
+            prompt.push_str(&recalled_context(recalled));
+        }
+        let elsewhere = self.cross_session_turns(user_prompt, dry);
+        if !elsewhere.is_empty() {
+            prompt.push_str(&cross_session_context(&elsewhere));
+        }
+        prompt.push_str(user_prompt);
+        let budget = self.context_tokens().map(|total| {
+            let fixed: usize = messages
+                .iter()
+                .map(|m| tokens::estimate(&m.content) + 4)
+                .sum();
+            total.saturating_sub(fixed + tokens::estimate(&prompt) + 4)
+        });
+        let (history, complete) = self.build_history(budget)?;
+        let stable = if complete {
+            messages.len() + history.len()
+        } else {
+            messages.len()
+        };
+        let cache_prefix = if self.prompt_cache { stable } else { 0 };
+        messages.extend(history);
+        messages.push(ChatMessage::new("user", prompt));
+        Ok((messages, cache_prefix))
+    }
+
+    /// Hash of everything that determines the answer: provider, model, messages and sampling.
+    /// Tool results change from run to run, so tool-using answers are never cached.
+    fn cache_key(&self, messages: &[ChatMessage]) -> Option<String> {
+        if self.tools.is_some() {
+            return None;
+        }
+        let target = self.targets.first()?;
+        let material =
+            serde_json::to_vec(&(&target.provider, &target.model, messages, &self.params)).ok()?;
+        Some(hex::encode(Sha256::digest(material)))
+    }
+
+    /// Answer a prompt, streaming it to stdout; `images` go along with the prompt only
+    /// and are not kept in memory.
+    pub async fn ask_streaming(&self, user_prompt: &str, images: &[ImageData]) -> Result<String> {
+        self.flush_memory().await;
+        let (prompt_vector, recalled) = self.recall(user_prompt).await;
+        let (mut messages, cache_prefix) = self.build_messages(user_prompt, &recalled, false)?;
+        if let Some(last) = messages.last_mut() {
+            last.images = images.to_vec();
+        }
+        let cache_key = self.cache_ttl.and_then(|_| self.cache_key(&messages));
+        if let (Some(ttl), Some(key)) = (self.cache_ttl, &cache_key) {
+            if let Some(hit) = self.mem.cache_get(key, ttl)? {
+                note!("(cached response)");
+                if self.json_output {
+                    JsonAnswer {
+                        answer: &hit,
+                        provider: None,
+                        model: None,
+                        finish_reason: None,
+                        usage: None,
+                        latency_ms: None,
+                        cached: true,
                     }
-                    Ok(reqwest_eventsource::Event::Message(msg)) => {
-                        let data = msg.data.trim();
-                        if data == "[DONE]" {
-                            es.close();
-                            break;
-                        }
-                        if let Ok(payload) = serde_json::from_str::<StreamChunk>(data) {
-                            if let Some(choice) = payload.choices.get(0) {
-                                if let Some(piece) = &choice.delta.content {
-                                    print!("{}", piece);
-                                    acc.push_str(piece);
-                                    use std::io::Write;
-                                    let _ = std::io::stdout().flush();
-                                }
-                            }
+                    .print()?;
+                } else if let Some(sink) = &self.sink {
+                    let _ = sink.send(hit.clone());
+                } else if self.raw_output {
+                    print!("{}", hit);
+                } else {
+                    println!("{}", self.shown(&hit));
+                }
+                self.remember(user_prompt, &hit, &TurnMeta::default(), prompt_vector)
+                    .await?;
+                return Ok(hit);
+            }
+        }
+
+        let started = Instant::now();
+        // until the first piece shows; scripts get nothing in between
+        let quiet = matches!(env::var("AI_QUIET").as_deref(), Ok("1") | Ok("true"));
+        let spinner = (!self.json_output && self.sink.is_none() && !quiet).then(spinner::start);
+        let out = match &self.params.schema {
+            Some(schema) => {
+                let out = self
+                    .answer_structured(schema, messages, cache_prefix)
+                    .await?;
+                drop(spinner);
+                if let Some(sink) = &self.sink {
+                    let _ = sink.send(out.text.clone());
+                } else if self.raw_output {
+                    print!("{}", out.text);
+                } else if !self.json_output {
+                    println!("{}", self.paint(&out.text));
+                }
+                if let Some(key) = &cache_key {
+                    self.mem.cache_put(key, &out.text)?;
+                }
+                out
+            }
+            None => {
+                let mut partial = String::new();
+                let outcome = self
+                    .stream_cancellable(&messages, cache_prefix, !self.json_output, &mut partial)
+                    .await;
+                drop(spinner);
+                let out = match outcome {
+                    Some(res) => {
+                        let out = res?;
+                        if let Some(key) = &cache_key {
+                            self.mem.cache_put(key, &out.text)?;
                         }
+                        out
                     }
-                    Err(e) => {
-                        let msg = e.to_string();
-                        es.close();
-                        if (msg.contains("429") || msg.contains("Too Many Requests"))
-                            && attempts <= max_retries
-                        {
-                            let jitter: u64 = thread_rng().gen_range(0..250);
-                            let backoff = Duration::from_millis(
-                                base_backoff_ms * attempts as u64 + jitter,
-                            );
-                            eprintln!("\nstream 429, retrying in {:?}...", backoff);
-                            sleep(backoff).await;
-                            acc.clear();
-                            continue;
+                    None => {
+                        // dropping the stream future closed the connection; keep what arrived
+                        if let (Some(r), false) = (self.renderer(), self.json_output) {
+                            // the renderer went with the future; show the line it was holding
+                            let held = partial.rsplit('\n').next().unwrap_or("");
+                            print!("{}", self.paint(&r.render(held)));
                         }
-                        if attempts > max_retries {
-                            eprintln!(
-                                "stream failed after {} attempts; falling back to non-stream.",
-                                attempts - 1
-                            );
-                            let out = self.ask_once(user_prompt).await?;
-                            println!("{}", out);
-                            self.mem.append("user", user_prompt)?;
-                            self.mem.append("assistant", &out)?;
-                            println!();
-                            return Ok(out);
+                        note!("\n[cancelled]");
+                        Completion {
+                            text: partial,
+                            finish_reason: Some("cancelled".into()),
+                            ..Default::default()
                         }
-                        return Err(anyhow!("stream error: {}", msg));
                     }
+                };
+                if !self.json_output && !self.raw_output && self.sink.is_none() {
+                    println!();
+                    logging::printed("\n");
                 }
+                out
             }
+        };
+        if let Some(u) = out.usage.filter(|u| u.cached_tokens > 0) {
+            note!(
+                "(prompt cache: {} of {} input tokens cached)",
+                u.cached_tokens,
+                u.prompt_tokens
+            );
+        }
+        if let Some(u) = out.usage.filter(|u| u.reasoning_tokens > 0) {
+            note!(
+                "({} of {} output tokens spent reasoning)",
+                u.reasoning_tokens,
+                u.completion_tokens
+            );
+        }
 
-            // success
-            break;
+        let meta = turn_meta(&out, started);
+        if self.json_output {
+            JsonAnswer {
+                answer: &out.text,
+                provider: out.provider.as_deref(),
+                model: out.model.as_deref(),
+                finish_reason: out.finish_reason.as_deref(),
+                usage: out.usage,
+                latency_ms: meta.latency_ms,
+                cached: false,
+            }
+            .print()?;
         }
+        self.remember(user_prompt, &out.text, &meta, prompt_vector)
+            .await?;
+        Ok(out.text)
+    }
 
-        self.mem.append("user", user_prompt)?;
-        self.mem.append("assistant", &acc)?;
-        println!();
-        Ok(acc)
+    /// `stream_answer` that Ctrl-C can stop; `None` means it was cancelled.
+    async fn stream_cancellable(
+        &self,
+        messages: &[ChatMessage],
+        cache_prefix: usize,
+        echo: bool,
+        partial: &mut String,
+    ) -> Option<Result<Completion>> {
+        let _streaming = interrupt::Streaming::begin();
+        tokio::select! {
+            res = self.converse(messages, cache_prefix, echo, partial) => Some(res),
+            _ = interrupt::cancelled() => None,
+        }
     }
 
-    // Non-stream fallback
-    pub async fn ask_once(&self, user_prompt: &str) -> Result<String> {
-        let api_key = env::var("OPENAI_API_KEY")?;
-        let mut messages = self.build_history()?;
-        messages.push(OpenAIMessage {
-            role: "user".into(),
-            content: user_prompt.into(),
-        });
+    /// `stream_answer`, running the tools the model calls and feeding their
+    /// results back until it answers.
+    async fn converse(
+        &self,
+        messages: &[ChatMessage],
+        cache_prefix: usize,
+        echo: bool,
+        partial: &mut String,
+    ) -> Result<Completion> {
+        let Some(tools) = &self.tools else {
+            return self
+                .stream_answer(messages, cache_prefix, echo, partial)
+                .await;
+        };
+        let mut messages = messages.to_vec();
+        let mut usage: Option<Usage> = None;
+        for _ in 0..MAX_TOOL_ROUNDS {
+            let mut out = self
+                .stream_answer(&messages, cache_prefix, echo, partial)
+                .await?;
+            if let Some(u) = out.usage {
+                *usage.get_or_insert_with(Usage::default) += u;
+            }
+            if out.tool_calls.is_empty() {
+                out.usage = usage;
+                return Ok(out);
+            }
+            let mut request = ChatMessage::new("assistant", out.text);
+            request.tool_calls = out.tool_calls.clone();
+            messages.push(request);
+            for call in out.tool_calls {
+                note!("[tool] {}({})", call.function.name, call.function.arguments);
+                let result = tools
+                    .dispatch(&call.function.name, &call.function.arguments)
+                    .await;
+                let mut reply = ChatMessage::new("tool", result);
+                reply.tool_call_id = Some(call.id);
+                messages.push(reply);
+            }
+        }
+        Err(anyhow!(
+            "no answer after {} rounds of tool calls",
+            MAX_TOOL_ROUNDS
+        ))
+    }
+
+    /// Collect an answer without echoing it, check it against the schema, and
+    /// ask once for a corrected answer before giving up.
+    async fn answer_structured(
+        &self,
+        schema: &Value,
+        mut messages: Vec<ChatMessage>,
+        cache_prefix: usize,
+    ) -> Result<Completion> {
+        let mut partial = String::new();
+        let mut retried = false;
+        loop {
+            let mut out = self
+                .stream_cancellable(&messages, cache_prefix, false, &mut partial)
+                .await
+                .ok_or_else(|| anyhow!("cancelled"))??;
+            let err = match schema::validate(schema, &out.text) {
+                Ok(()) => {
+                    out.text = schema::extract(&out.text).to_string();
+                    return Ok(out);
+                }
+                Err(e) => e,
+            };
+            if retried {
+                return Err(anyhow!(
+                    "answer did not match the schema: {}\n{}",
+                    err,
+                    out.text
+                ));
+            }
+            warn!("answer did not match the schema ({}); retrying", err);
+            retried = true;
+            messages.push(ChatMessage::new("assistant", out.text));
+            messages.push(ChatMessage::new(
+                "user",
+                format!(
+                    "That answer does not match the schema: {}. Reply with only the corrected JSON.",
+                    err
+                ),
+            ));
+        }
+    }
 
-        #[derive(Serialize)]
-        struct Req {
-            model: String,
-            messages: Vec<OpenAIMessage>,
-            stream: bool,
+    /// What an answer is asked of `target` with: the sampling settings and (with `--tools`) the tools.
+    fn chat_request(
+        &self,
+        target: &Target,
+        messages: &[ChatMessage],
+        cache_prefix: usize,
+    ) -> ChatRequest {
+        ChatRequest {
+            model: target.model.clone(),
+            messages: messages.to_vec(),
+            params: self.params.clone(),
+            cache_prefix,
+            tools: self
+                .tools
+                .as_ref()
+                .map(ToolRegistry::specs)
+                .unwrap_or_default(),
         }
-        #[derive(Deserialize)]
-        struct RespChoice {
-            message: OpenAIMessage,
+    }
+
+    /// Print what `ask_streaming` would send the first target for `user_prompt` instead of
+    /// sending it: the model, a token estimate and the request itself, with its keys left out.
+    /// Nothing is sent, remembered or cached; semantic recall, which would embed the prompt,
+    /// is left out.
+    pub async fn dry_run(&self, user_prompt: &str, images: &[ImageData]) -> Result<()> {
+        let target = self
+            .targets
+            .first()
+            .ok_or_else(|| anyhow!("No provider configured."))?;
+        let (mut messages, cache_prefix) = self.build_messages(user_prompt, &[], true)?;
+        if let Some(last) = messages.last_mut() {
+            last.images = images.to_vec();
         }
-        #[derive(Deserialize)]
-        struct Resp {
-            choices: Vec<RespChoice>,
+        let req = self.chat_request(target, &messages, cache_prefix);
+        let provider = self.provider(target)?;
+        let fallbacks: Vec<String> = self.targets[1..]
+            .iter()
+            .map(|t| format!("{}:{}", t.provider, t.model))
+            .collect();
+        if fallbacks.is_empty() {
+            println!("model: {}:{}", target.provider, target.model);
+        } else {
+            println!(
+                "model: {}:{} (then {}, if it fails)",
+                target.provider,
+                target.model,
+                fallbacks.join(", ")
+            );
+        }
+        println!(
+            "~{} tokens in {} message{}, {} tools\n",
+            provider.count_tokens(&req.messages),
+            req.messages.len(),
+            if req.messages.len() == 1 { "" } else { "s" },
+            req.tools.len()
+        );
+        match provider.chat_request(&req)? {
+            Some(builder) => providers::print_request(&builder),
+            // answered in-process, so there is no request to show
+            None => {
+                println!("{:#}", serde_json::to_value(&req.messages)?);
+                Ok(())
+            }
         }
+    }
 
-        let req = Req {
-            model: self.model.clone(),
-            messages,
-            stream: false,
-        };
+    /// Stream from each target in turn until one answers, mirroring pieces into `partial`
+    /// and, with `echo`, to stdout. Once pieces are echoed, a failure is not fallen back from.
+    async fn stream_answer(
+        &self,
+        messages: &[ChatMessage],
+        cache_prefix: usize,
+        echo: bool,
+        partial: &mut String,
+    ) -> Result<Completion> {
+        let mut last_err = None;
 
-        let res: Resp = self
-            .client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&req)
-            .send()
-            .await?
-            .json()
-            .await?;
+        for (i, target) in self.targets.iter().enumerate() {
+            if let Some(e) = &last_err {
+                warn!(
+                    "{} failed ({}); falling back to {}:{}",
+                    self.targets[i - 1].provider,
+                    e,
+                    target.provider,
+                    target.model
+                );
+            }
+            let req = self.chat_request(target, messages, cache_prefix);
+            info!("asking {}:{}", target.provider, target.model);
+            debug!(
+                "{} messages, ~{} tokens, {} tools",
+                req.messages.len(),
+                req.messages
+                    .iter()
+                    .map(|m| tokens::estimate(&m.content) + 4)
+                    .sum::<usize>(),
+                req.tools.len()
+            );
+            partial.clear();
+            let started = Instant::now();
+            let mut renderer = self.renderer();
+            let sink = &self.sink;
+            let color = self.assistant_color.as_deref();
+            let res = match self.provider(target) {
+                Ok(provider) => {
+                    provider
+                        .stream_chat(&req, &mut |piece| {
+                            if let (true, Some(sink)) = (echo, sink) {
+                                let _ = sink.send(piece.to_string());
+                            } else if echo {
+                                spinner::stop();
+                                let text = match &mut renderer {
+                                    Some(r) => theme::paint(&r.push(piece), color),
+                                    None => theme::paint(piece, color),
+                                };
+                                print!("{}", text);
+                                let _ = std::io::stdout().flush();
+                                logging::printed(&text);
+                            }
+                            partial.push_str(piece);
+                        })
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            if let (true, Some(r)) = (echo, &mut renderer) {
+                let text = self.paint(&r.finish());
+                print!("{}", text);
+                let _ = std::io::stdout().flush();
+                logging::printed(&text);
+            }
+            match res {
+                Ok(mut out) => {
+                    debug!(
+                        "answered in {:.2?}: {} chars, finish reason {}, {}",
+                        started.elapsed(),
+                        out.text.len(),
+                        out.finish_reason.as_deref().unwrap_or("none"),
+                        out.usage
+                            .map_or("no usage reported".to_string(), |u| format!(
+                                "{}+{} tokens",
+                                u.prompt_tokens, u.completion_tokens
+                            ))
+                    );
+                    out.provider = Some(target.provider.clone());
+                    out.model = Some(target.model.clone());
+                    return Ok(out);
+                }
+                // part of the answer is on screen already: another target's would follow it
+                Err(e) if echo && !partial.is_empty() => {
+                    return Err(e.context(format!(
+                        "{} failed partway through the answer",
+                        target.provider
+                    )));
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| anyhow!("No provider configured.")))
+    }
 
-        let out = res
-            .choices
-            .into_iter()
-            .next()
-            .map(|c| c.message.content)
-            .unwrap_or_default();
+    /// Ask every model in `specs` the same prompt concurrently. Bare model names
+    /// go to the provider their name suggests. History and memory are left out
+    /// so that every model sees exactly the same input.
+    pub async fn compare(&self, specs: &[String], user_prompt: &str) -> Vec<Comparison> {
+        let mut messages = self.system_messages();
+        messages.push(ChatMessage::new("user", user_prompt));
+        let runs = specs.iter().map(|spec| {
+            let mut target = self.target_for(spec);
+            if target.model == *spec {
+                if let Some(provider) = guess_provider(spec) {
+                    target.provider = provider.to_string();
+                }
+            }
+            let req = ChatRequest {
+                model: target.model.clone(),
+                messages: messages.clone(),
+                params: self.params.clone(),
+                cache_prefix: 0,
+                tools: Vec::new(),
+            };
+            async move {
+                let started = Instant::now();
+                let result = match self.provider(&target) {
+                    Ok(provider) => provider.complete(&req).await,
+                    Err(e) => Err(e),
+                };
+                Comparison {
+                    target,
+                    elapsed: started.elapsed(),
+                    result,
+                }
+            }
+        });
+        join_all(runs).await
+    }
+
+    /// Models offered by the primary provider, sorted by id.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let target = self
+            .targets
+            .first()
+            .ok_or_else(|| anyhow!("No provider configured."))?;
+        let mut models = self.provider(target)?.list_models().await?;
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(models)
+    }
 
-        self.mem.append("user", user_prompt)?;
-        self.mem.append("assistant", &out)?;
-        Ok(out)
+    /// Transcribe an audio file with the primary provider, for use as a prompt.
+    pub async fn transcribe(&self, path: &Path) -> Result<String> {
+        let target = self
+            .targets
+            .first()
+            .ok_or_else(|| anyhow!("No provider configured."))?;
+        self.provider(target)?.transcribe(path).await
     }
 
-    pub fn memory_show(&self, limit: usize) -> Result<Vec<crate::ai_memory::ChatTurn>> {
-        self.mem.last_turns(limit)
+    pub fn memory_show(&self, limit: usize, filter: &TurnFilter) -> Result<Vec<ChatTurn>> {
+        self.mem.last_turns_filtered(limit, filter)
+    }
+
+    /// A session (the current one unless named) as it stood at `until`: the turns and facts from
+    /// before then and the summary in effect. Only what is still stored can be shown, so later
+    /// edits and deletions show through.
+    pub fn replay(&self, until: DateTime<Utc>, session: Option<&str>) -> Result<Replay> {
+        let session = match session {
+            Some(s) => s.to_string(),
+            None => self.mem.current_session()?,
+        };
+        let filter = TurnFilter {
+            until: Some(until),
+            session: Some(session.clone()),
+            ..TurnFilter::default()
+        };
+        let turns = self.mem.last_turns_filtered(i64::MAX as usize, &filter)?;
+        let system_prompt = self
+            .mem
+            .sessions()?
+            .into_iter()
+            .find(|s| s.name == session)
+            .and_then(|s| s.system_prompt);
+        let facts = self
+            .mem
+            .facts()?
+            .into_iter()
+            .filter(|f| f.ts < until)
+            .collect();
+        let summary = self.mem.summary_as_of(&session, until)?;
+        Ok(Replay {
+            session,
+            system_prompt,
+            facts,
+            summary,
+            turns,
+        })
     }
 
     pub fn memory_clear(&self) -> Result<()> {
         self.mem.clear()
     }
-}
\ No newline at end of file
+
+    /// Apply the AI_MEMORY_MAX_* retention limits; returns how many turns were deleted.
+    pub fn prune_memory(&self) -> Result<usize> {
+        if self.retention.is_unlimited() {
+            return Ok(0);
+        }
+        self.mem.prune(&self.retention)
+    }
+
+    pub fn has_retention(&self) -> bool {
+        !self.retention.is_unlimited()
+    }
+
+    /// The memory store itself, for session management.
+    pub fn memory(&self) -> &dyn MemoryBackend {
+        &*self.mem
+    }
+
+    /// The local memory file, for commands that work on the file itself (snapshots, export, vacuum...).
+    pub fn local_memory(&self) -> Result<&Memory> {
+        self.mem.as_local().ok_or_else(|| {
+            anyhow!("this command needs a local memory file (AI_MEMORY_BACKEND=sqlite or jsonl)")
+        })
+    }
+
+    pub fn local_memory_mut(&mut self) -> Result<&mut Memory> {
+        self.mem.as_local_mut().ok_or_else(|| {
+            anyhow!("this command needs a local memory file (AI_MEMORY_BACKEND=sqlite or jsonl)")
+        })
+    }
+}
@@ -1,7 +1,19 @@
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OpenFlags, Row, Transaction, TransactionBehavior};
 use chrono::{Utc, DateTime};
 use serde::{Serialize, Deserialize};
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::archive::{Archive, ArchivedAttachment, ArchivedSession, ArchivedTurn, DeletedTurn, ARCHIVE_VERSION};
+use base64::Engine;
+use crate::redact::Redactor;
+
+pub mod retrieval;
+
+pub const DEFAULT_SESSION: &str = "default";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatTurn {
@@ -9,46 +21,1430 @@ pub struct ChatTurn {
     pub role: String,
     pub content: String,
     pub ts: DateTime<Utc>,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub fingerprint: Option<String>,
+    /// Always sent with the history, however old.
+    pub pinned: bool,
+    /// Who answered (assistant turns only) and how long the whole answer took.
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub latency_ms: Option<i64>,
+    /// Who added the turn, in memory shared by a team.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+}
+
+/// A file kept with a turn (a diff, a log, an image), so the record holds what the conversation was about.
+#[derive(Debug, Serialize, Clone)]
+pub struct Attachment {
+    pub id: i64,
+    pub turn_id: i64,
+    pub name: String,
+    pub media_type: String,
+    #[serde(skip)]
+    pub data: Vec<u8>,
+}
+
+/// A named conversation with its own stream of turns.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub name: String,
+    pub created: DateTime<Utc>,
+    pub turns: i64,
+    /// Sent as the first system message of every request in this session.
+    pub system_prompt: Option<String>,
+}
+
+/// Something to keep in mind in every conversation, e.g. "I prefer tabs".
+#[derive(Debug, Clone)]
+pub struct Fact {
+    pub id: i64,
+    pub content: String,
+    pub ts: DateTime<Utc>,
+}
+
+/// Rolling summary of a session's turns up to and including `through_id`.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub through_id: i64,
+    pub content: String,
+}
+
+/// A turn matching a full-text search, with the matched words marked in `snippet`.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub role: String,
+    pub ts: DateTime<Utc>,
+    pub session: String,
+    pub snippet: String,
+}
+
+/// Extra facts recorded alongside a turn when the provider reports them.
+#[derive(Debug, Default, Clone)]
+pub struct TurnMeta {
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub fingerprint: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub latency_ms: Option<i64>,
+}
+
+/// Which turns `memory-show` lists; the default is every turn of the current session.
+#[derive(Debug, Default, Clone)]
+pub struct TurnFilter {
+    pub tag: Option<String>,
+    pub role: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    /// Exclusive.
+    pub until: Option<DateTime<Utc>>,
+    /// Another session than the current one, by name.
+    pub session: Option<String>,
+}
+
+/// What a database holds, for `memory-stats`.
+#[derive(Debug, Clone)]
+pub struct Stats {
+    pub sessions: Vec<Session>,
+    pub file_bytes: u64,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    /// Estimated from the stored text.
+    pub content_tokens: usize,
+    /// Summed from what providers reported per answer.
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub facts: i64,
+    pub top_tags: Vec<(String, i64)>,
+}
+
+/// What `Memory::import` did with an archive's turns.
+#[derive(Debug, Default, Clone)]
+pub struct ImportStats {
+    pub sessions: usize,
+    pub added: usize,
+    /// Known turns replaced by a more recent edit from the archive.
+    pub updated: usize,
+    /// Turns the archive lists as deleted.
+    pub deleted: usize,
+    pub skipped: usize,
+}
+
+/// How much history a database keeps; unset limits don't apply, and pinned turns are never pruned.
+#[derive(Debug, Default, Clone)]
+pub struct Retention {
+    pub max_turns: Option<usize>,
+    pub max_age_days: Option<u32>,
+    pub max_size_mb: Option<u64>,
 }
 
-pub struct Memory { conn: Connection }
+impl Retention {
+    pub fn is_unlimited(&self) -> bool { self.max_turns.is_none() && self.max_age_days.is_none() && self.max_size_mb.is_none() }
+}
+
+/// What `memory-redact` found (or replaced), by where it was stored.
+#[derive(Debug, Default)]
+pub struct Redaction {
+    pub turns: Vec<i64>,
+    pub facts: usize,
+    pub summaries: usize,
+    pub attachments: usize,
+    pub system_prompts: usize,
+    /// Matches per pattern name.
+    pub hits: std::collections::BTreeMap<String, usize>,
+}
+
+/// What `memory-check` found: problems with the file itself, then rows mindlink can't read.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// What `PRAGMA integrity_check` reported; empty when the file is sound.
+    pub integrity: Vec<String>,
+    /// The full-text index has drifted from the turns it indexes (`--repair` rebuilds it).
+    pub index_stale: bool,
+    pub bad_rows: Vec<BadRow>,
+    /// Rows moved to the `quarantine` table (turns and facts) or patched (a session's creation time) by `--repair`.
+    pub repaired: usize,
+}
+
+#[derive(Debug)]
+pub struct BadRow {
+    pub table: &'static str,
+    pub id: i64,
+    pub problem: String,
+}
+
+/// A copy of the whole database saved by `memory-snapshot`.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub name: String,
+    pub taken: DateTime<Utc>,
+    pub bytes: u64,
+}
+
+/// Where conversations are kept. The agent only goes through this trait, so stores can be swapped without touching it.
+/// `Memory` implements it for a SQLite file, a `.jsonl` file and `:memory:` (a throwaway database, handy in tests);
+/// maintenance that only makes sense for a local file (snapshots, vacuum, export...) stays on `Memory`, reached through
+/// `as_local`.
+pub trait MemoryBackend {
+    fn open(path: &str) -> Result<Self> where Self: Sized;
+    fn append(&self, role: &str, content: &str) -> Result<i64> { self.append_meta(role, content, &TurnMeta::default()) }
+    /// Store a turn in the current session, returning its id.
+    fn append_meta(&self, role: &str, content: &str, meta: &TurnMeta) -> Result<i64>;
+    /// The newest `limit` turns of the current session, oldest first.
+    fn last_turns(&self, limit: usize) -> Result<Vec<ChatTurn>>;
+    fn last_turns_filtered(&self, limit: usize, filter: &TurnFilter) -> Result<Vec<ChatTurn>>;
+    fn turns_after(&self, id: i64) -> Result<Vec<ChatTurn>>;
+    fn turns_by_id(&self, ids: &[i64]) -> Result<Vec<ChatTurn>>;
+    fn note_references(&self, ids: &[i64]) -> Result<()>;
+    fn references(&self, ids: &[i64]) -> Result<HashMap<i64, i64>>;
+    fn pinned_turns(&self) -> Result<Vec<ChatTurn>>;
+    fn turn(&self, id: i64) -> Result<ChatTurn>;
+    fn search(&self, query: &str, all_sessions: bool, limit: usize, mark: (&str, &str)) -> Result<Vec<SearchHit>>;
+    fn other_session_turns(&self, text: &str, limit: usize) -> Result<Vec<(String, ChatTurn)>>;
+    /// Forget the current session's turns.
+    fn clear(&self) -> Result<()>;
+    fn has_exchange(&self, question: &str, answer: &str) -> Result<bool>;
+    fn prune(&self, policy: &Retention) -> Result<usize>;
+    fn set_pinned(&self, id: i64, pinned: bool) -> Result<()>;
+    fn tag(&self, id: i64, tag: &str) -> Result<()>;
+    fn untag(&self, id: i64, tag: &str) -> Result<()>;
+    fn set_content(&self, id: i64, content: &str) -> Result<()>;
+    fn delete(&self, id: i64) -> Result<()>;
+    fn delete_range(&self, from: Option<i64>, to: Option<i64>) -> Result<usize>;
+    fn latest_summary(&self) -> Result<Option<Summary>>;
+    fn summary_as_of(&self, session: &str, at: DateTime<Utc>) -> Result<Option<Summary>>;
+    fn put_summary(&self, through_id: i64, content: &str) -> Result<()>;
+    fn put_embedding(&self, turn_id: i64, model: &str, vector: &[f32]) -> Result<()>;
+    fn embeddings(&self, model: &str) -> Result<Vec<(i64, Vec<f32>)>>;
+    fn cache_get(&self, key: &str, ttl_secs: i64) -> Result<Option<String>>;
+    fn cache_put(&self, key: &str, response: &str) -> Result<()>;
+    fn facts(&self) -> Result<Vec<Fact>>;
+    fn add_fact(&self, content: &str) -> Result<i64>;
+    fn forget_fact(&self, id: i64) -> Result<()>;
+    fn current_session(&self) -> Result<String>;
+    fn system_prompt(&self) -> Result<Option<String>>;
+    fn set_system_prompt(&self, prompt: Option<&str>) -> Result<()>;
+    fn sessions(&self) -> Result<Vec<Session>>;
+    /// The session the newest turn went into (one of this user's, in shared memory), if any has turns.
+    fn latest_session(&self) -> Result<Option<String>>;
+    fn session_new(&self, name: &str) -> Result<()>;
+    fn session_switch(&self, name: &str) -> Result<()>;
+    fn session_delete(&self, name: &str) -> Result<()>;
+    /// The SQLite store behind this backend, for the file-level maintenance commands.
+    fn as_local(&self) -> Option<&Memory> { None }
+    fn as_local_mut(&mut self) -> Option<&mut Memory> { None }
+}
+
+pub struct Memory { conn: Connection, jsonl: Option<JsonlFile> }
+
+/// A `.jsonl` memory file, loaded into an in-memory database on open. New turns, facts, summaries and session changes
+/// are appended to it as they are made; anything else (an edit, a delete, a prune) rewrites it whole. `saved` is the
+/// database's `total_changes` when the file last held all of it.
+struct JsonlFile { path: PathBuf, writable: bool, saved: Cell<u64> }
+
+/// One line of a `.jsonl` memory file.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonlLine {
+    Current { session: String },
+    Fact { content: String, ts: DateTime<Utc> },
+    Session { name: String, created: DateTime<Utc>, #[serde(default, skip_serializing_if = "Option::is_none")] system_prompt: Option<String> },
+    Summary { session: String, through_id: i64, content: String, ts: DateTime<Utc> },
+    Turn { id: i64, session: String, #[serde(flatten)] turn: Box<ArchivedTurn> },
+    Deleted { uid: String, deleted: DateTime<Utc> },
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() { tracing::warn!("memory not saved: {:#}", e); }
+    }
+}
+
+type WriteJob = Box<dyn FnOnce(&Memory) + Send>;
+
+/// Runs writes on a connection of its own, on a blocking thread, so a long answer being stored doesn't hold up the async
+/// runtime. Jobs run in the order they were sent; `flush` waits for all of them.
+pub struct MemoryWriter { jobs: tokio::sync::mpsc::UnboundedSender<WriteJob> }
+
+impl MemoryWriter {
+    /// A writer for the database file behind `mem`, or `None` for a `.jsonl` file or an in-memory database, which only
+    /// `mem` can see.
+    pub fn for_memory(mem: &Memory) -> Result<Option<Self>> {
+        if mem.jsonl.is_some() { return Ok(None); }
+        let path = mem.path()?;
+        if path.as_os_str().is_empty() { return Ok(None); }
+        let writer = Memory::open(&path.to_string_lossy())?;
+        let (jobs, mut queue) = tokio::sync::mpsc::unbounded_channel::<WriteJob>();
+        // the runtime waits for blocking tasks when it shuts down, so jobs still queued on exit are not lost
+        tokio::task::spawn_blocking(move || { while let Some(job) = queue.blocking_recv() { job(&writer); } });
+        Ok(Some(Self { jobs }))
+    }
+    pub fn send(&self, job: impl FnOnce(&Memory) + Send + 'static) {
+        let _ = self.jobs.send(Box::new(job));
+    }
+    /// Wait until every job sent so far has run.
+    pub async fn flush(&self) {
+        let (done, finished) = tokio::sync::oneshot::channel();
+        self.send(move |_| { let _ = done.send(()); });
+        let _ = finished.await;
+    }
+}
+
+/// Schema upgrades in order: entry `i` takes a database from `user_version` i to i + 1. Append new ones; never change
+/// one that has shipped.
+const MIGRATIONS: &[fn(&Connection) -> Result<()>] = &[Memory::migrate_v1, Memory::migrate_v2, Memory::migrate_v3, Memory::migrate_v4, Memory::migrate_v5, Memory::migrate_v6, Memory::migrate_v7, Memory::migrate_v8, Memory::migrate_v9];
+
+const BUSY_TIMEOUT_MS: u64 = 5000;
+const OPEN_RETRIES: u64 = 3;
+
+// busy_timeout covers most contention, but SQLite returns SQLITE_BUSY without waiting when waiting could deadlock
+fn is_busy(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref::<rusqlite::Error>(), Some(rusqlite::Error::SqliteFailure(f, _)) if matches!(f.code, rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked))
+}
+
+pub fn content_hash(content: &str) -> String {
+    use sha2::{Digest, Sha256};
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+/// The words of a prompt worth looking up in other sessions; short ones match nearly everything.
+pub fn recall_words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for w in text.split(|c: char| !c.is_alphanumeric()).filter(|w| w.chars().count() >= 3).map(str::to_lowercase) {
+        if !words.contains(&w) { words.push(w); }
+    }
+    words
+}
+
+/// Column `col` as a time; a malformed one is a conversion error, so callers skip the row instead of panicking.
+fn ts_column(r: &Row, col: usize) -> rusqlite::Result<DateTime<Utc>> {
+    let s: String = r.get(col)?;
+    DateTime::parse_from_rfc3339(&s).map(|t| t.with_timezone(&Utc))
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(col, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+/// A `memory` row selected as `id, role, content, ts, prompt_tokens, completion_tokens, fingerprint, pinned, provider, model, latency_ms`.
+fn turn_row(r: &Row) -> rusqlite::Result<ChatTurn> {
+    Ok(ChatTurn { id: r.get(0)?, role: r.get(1)?, content: r.get(2)?, ts: ts_column(r, 3)?, prompt_tokens: r.get(4)?, completion_tokens: r.get(5)?, fingerprint: r.get(6)?, pinned: r.get(7)?,
+        provider: r.get(8)?, model: r.get(9)?, latency_ms: r.get(10)?, author: None })
+}
 
 impl Memory {
+    /// Open (creating or migrating) a database that other mindlink processes may be using at the same time.
     pub fn open(path: &str) -> Result<Self> {
+        if path.ends_with(".jsonl") { return Self::open_jsonl(path, true); }
+        let mut attempt = 0;
+        loop {
+            match Self::open_once(path) {
+                Err(e) if is_busy(&e) && attempt < OPEN_RETRIES => { attempt += 1; std::thread::sleep(Duration::from_millis(200 * attempt)); }
+                r => return r,
+            }
+        }
+    }
+    /// Open an existing database for reading only, e.g. the global one from inside a project; nothing is created or migrated.
+    pub fn open_readonly(path: &str) -> Result<Self> {
+        if path.ends_with(".jsonl") { return Self::open_jsonl(path, false); }
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        conn.busy_timeout(Duration::from_millis(BUSY_TIMEOUT_MS))?;
+        Ok(Self { conn, jsonl: None })
+    }
+    /// Load a `.jsonl` memory file (a missing one is an empty memory). Nothing on disk is locked: processes using the
+    /// same file at once each append their own turns, but a rewrite leaves out the ones others added since it loaded.
+    fn open_jsonl(path: &str, writable: bool) -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        Self::migrate(&conn)?;
+        let mut mem = Self { conn, jsonl: None };
+        match std::fs::read_to_string(path) {
+            Ok(text) => mem.load_jsonl(&text).with_context(|| format!("reading {}", path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(anyhow::Error::new(e).context(format!("reading {}", path))),
+        }
+        mem.jsonl = Some(JsonlFile { path: PathBuf::from(path), writable, saved: Cell::new(mem.conn.total_changes()) });
+        Ok(mem)
+    }
+    fn load_jsonl(&self, text: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let session_id = |name: &str, created: &DateTime<Utc>| -> Result<i64> {
+            tx.execute("INSERT OR IGNORE INTO sessions (name, created) VALUES (?1, ?2)", params![name, created.to_rfc3339()])?;
+            Ok(tx.query_row("SELECT id FROM sessions WHERE name = ?1", params![name], |r| r.get(0))?)
+        };
+        for (n, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let line: JsonlLine = serde_json::from_str(line).with_context(|| format!("line {}", n + 1))?;
+            match line {
+                JsonlLine::Current { session } => { tx.execute("INSERT OR REPLACE INTO settings (key, value) VALUES ('session', ?1)", params![session])?; }
+                JsonlLine::Fact { content, ts } => { tx.execute("INSERT INTO facts (content, ts) VALUES (?1, ?2)", params![content, ts.to_rfc3339()])?; }
+                JsonlLine::Session { name, created, system_prompt } => {
+                    let id = session_id(&name, &created)?;
+                    tx.execute("UPDATE sessions SET created = ?1, system_prompt = ?2 WHERE id = ?3", params![created.to_rfc3339(), system_prompt, id])?;
+                }
+                JsonlLine::Summary { session, through_id, content, ts } => {
+                    let id = session_id(&session, &ts)?;
+                    tx.execute("INSERT INTO summaries (session_id, through_id, content, ts) VALUES (?1, ?2, ?3, ?4)", params![id, through_id, content, ts.to_rfc3339()])?;
+                }
+                JsonlLine::Turn { id, session, turn: t } => {
+                    let session_id = session_id(&session, &t.ts)?;
+                    // ids are kept, so the ones memory-show printed last time still work; two processes appending at
+                    // once can both take the same one, and then the later turn gets a new id
+                    let taken = tx.query_row("SELECT COUNT(*) FROM memory WHERE id = ?1", params![id], |r| r.get::<_, i64>(0))? > 0;
+                    tx.execute(
+                        "INSERT INTO memory (id, role, content, ts, prompt_tokens, completion_tokens, fingerprint, session_id, pinned, provider, model, latency_ms, uid, updated, content_hash)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, COALESCE(?13, lower(hex(randomblob(16)))), ?14, ?15)",
+                        params![(!taken).then_some(id), t.role, t.content, t.ts.to_rfc3339(), t.prompt_tokens, t.completion_tokens, t.fingerprint, session_id, t.pinned, t.provider, t.model, t.latency_ms,
+                                t.uid, t.updated.map(|u| u.to_rfc3339()), content_hash(&t.content)],
+                    )?;
+                    let id = tx.last_insert_rowid();
+                    for tag in &t.tags { tx.execute("INSERT OR IGNORE INTO turn_tags (turn_id, tag) VALUES (?1, ?2)", params![id, tag])?; }
+                    for a in &t.attachments {
+                        let data = base64::engine::general_purpose::STANDARD.decode(&a.data).map_err(|e| anyhow!("attachment {} is not valid base64: {}", a.name, e))?;
+                        tx.execute("INSERT INTO attachments (turn_id, name, media_type, data, ts) VALUES (?1, ?2, ?3, ?4, ?5)", params![id, a.name, a.media_type, data, t.ts.to_rfc3339()])?;
+                    }
+                }
+                JsonlLine::Deleted { uid, deleted } => { tx.execute("INSERT OR IGNORE INTO deleted_turns (uid, deleted) VALUES (?1, ?2)", params![uid, deleted.to_rfc3339()])?; }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+    /// The whole memory as `.jsonl`: current session, facts, sessions, summaries and deleted turns first, then every turn
+    /// in order.
+    /// Embeddings and the response cache are not kept.
+    fn render_jsonl(&self) -> Result<String> {
+        let mut lines = vec![JsonlLine::Current { session: self.current_session()? }];
+        lines.extend(self.facts()?.into_iter().map(|f| JsonlLine::Fact { content: f.content, ts: f.ts }));
+        lines.extend(self.sessions()?.into_iter().map(|s| JsonlLine::Session { name: s.name, created: s.created, system_prompt: s.system_prompt }));
+        let summaries: Vec<(String, i64, String, String)> = self.conn.prepare("SELECT s.name, m.through_id, m.content, m.ts FROM summaries m JOIN sessions s ON s.id = m.session_id ORDER BY m.id")?
+            .query_map(params![], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))?.filter_map(|r| r.ok()).collect();
+        for (session, through_id, content, ts) in summaries {
+            let ts = DateTime::parse_from_rfc3339(&ts)?.with_timezone(&Utc);
+            lines.push(JsonlLine::Summary { session, through_id, content, ts });
+        }
+        lines.extend(self.deleted_turns()?.into_iter().map(|d| JsonlLine::Deleted { uid: d.uid, deleted: d.deleted }));
+        lines.extend(self.archived_turns(None)?.into_iter().map(|(id, session, turn)| JsonlLine::Turn { id, session, turn: Box::new(turn) }));
+        let mut out = String::new();
+        for line in &lines { out.push_str(&serde_json::to_string(line)?); out.push('\n'); }
+        Ok(out)
+    }
+    /// Whether a `.jsonl` memory file holds everything in the database; always so for SQLite files.
+    fn saved(&self) -> bool {
+        self.jsonl.as_ref().is_none_or(|f| !f.writable || f.saved.get() == self.conn.total_changes())
+    }
+    /// Rewrite a `.jsonl` memory if it is missing changes; a no-op for SQLite files, which are always up to date, and
+    /// inside a transaction, whose commit is saved instead.
+    pub fn flush(&self) -> Result<()> {
+        if self.saved() || !self.conn.is_autocommit() { return Ok(()); }
+        self.rewrite_jsonl()
+    }
+    fn rewrite_jsonl(&self) -> Result<()> {
+        let Some(file) = self.jsonl.as_ref().filter(|f| f.writable) else { return Ok(()) };
+        let text = self.render_jsonl()?;
+        // write the new copy next to the old one first, so a crash leaves one of them whole
+        let tmp = file.path.with_extension("jsonl.tmp");
+        std::fs::write(&tmp, text).with_context(|| format!("writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, &file.path).with_context(|| format!("writing {}", file.path.display()))?;
+        file.saved.set(self.conn.total_changes());
+        Ok(())
+    }
+    /// Add `line`, which describes the write just made, at the end of a `.jsonl` memory; if changes from before it
+    /// (`was_saved` false) are missing too, or there is no file yet to start from, the file is written whole instead.
+    fn append_jsonl(&self, was_saved: bool, line: impl FnOnce() -> Result<JsonlLine>) -> Result<()> {
+        let Some(file) = self.jsonl.as_ref().filter(|f| f.writable) else { return Ok(()) };
+        if !was_saved || std::fs::metadata(&file.path).map_or(true, |m| m.len() == 0) { return self.rewrite_jsonl(); }
+        let mut text = serde_json::to_string(&line()?)?;
+        text.push('\n');
+        // a single write, so lines appended by processes sharing the file don't interleave
+        let mut out = std::fs::OpenOptions::new().create(true).append(true).open(&file.path).with_context(|| format!("writing {}", file.path.display()))?;
+        out.write_all(text.as_bytes()).with_context(|| format!("writing {}", file.path.display()))?;
+        file.saved.set(self.conn.total_changes());
+        Ok(())
+    }
+    /// After a write a `.jsonl` memory doesn't keep (embeddings, cached answers, reference counts): nothing to save.
+    fn unkept(&self, was_saved: bool) {
+        if let (true, Some(file)) = (was_saved, &self.jsonl) { file.saved.set(self.conn.total_changes()); }
+    }
+    fn open_once(path: &str) -> Result<Self> {
         let conn = Connection::open(path)?;
+        // wait for another process's write instead of failing with `database is locked`
+        conn.busy_timeout(Duration::from_millis(BUSY_TIMEOUT_MS))?;
+        // WAL lets readers carry on while someone writes; the setting is stored in the file
+        conn.query_row("PRAGMA journal_mode = WAL", params![], |_| Ok(()))?;
+        Self::migrate(&conn)?;
+        Ok(Self { conn, jsonl: None })
+    }
+    /// Bring the schema up to date, one migration at a time, recording progress in `PRAGMA user_version`.
+    fn migrate(conn: &Connection) -> Result<()> {
+        let current = |c: &Connection| -> Result<usize> { Ok(c.query_row("PRAGMA user_version", params![], |r| r.get::<_, i64>(0))? as usize) };
+        if current(conn)? == MIGRATIONS.len() { return Ok(()); }
+        // another process may be migrating too; the write lock makes it one after the other and the version is read again
+        let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+        let version = current(&tx)?;
+        if version > MIGRATIONS.len() {
+            return Err(anyhow!("memory DB is at schema version {}, newer than this mindlink understands ({}); upgrade mindlink", version, MIGRATIONS.len()));
+        }
+        for (i, step) in MIGRATIONS.iter().enumerate().skip(version) {
+            step(&tx)?;
+            tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+    /// Version 1: everything up to sessions, tags, facts, summaries, embeddings and full-text search. Databases from
+    /// before versioning are at 0 in any state in between, so every step here checks before it changes anything.
+    fn migrate_v1(conn: &Connection) -> Result<()> {
         conn.execute_batch(
-            "BEGIN;
-             CREATE TABLE IF NOT EXISTS memory(
+            "CREATE TABLE IF NOT EXISTS memory(
                  id INTEGER PRIMARY KEY AUTOINCREMENT,
                  role TEXT NOT NULL,
                  content TEXT NOT NULL,
-                 ts TEXT NOT NULL
+                 ts TEXT NOT NULL,
+                 prompt_tokens INTEGER,
+                 completion_tokens INTEGER,
+                 fingerprint TEXT,
+                 session_id INTEGER,
+                 pinned INTEGER NOT NULL DEFAULT 0
              );
              CREATE INDEX IF NOT EXISTS idx_memory_ts ON memory(ts);
-             COMMIT;",
+             CREATE TABLE IF NOT EXISTS sessions(
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 name TEXT NOT NULL UNIQUE,
+                 created TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS settings(
+                 key TEXT PRIMARY KEY,
+                 value TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS turn_tags(
+                 turn_id INTEGER NOT NULL,
+                 tag TEXT NOT NULL,
+                 PRIMARY KEY (turn_id, tag)
+             );
+             CREATE INDEX IF NOT EXISTS idx_turn_tags_tag ON turn_tags(tag);
+             CREATE TABLE IF NOT EXISTS facts(
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 content TEXT NOT NULL,
+                 ts TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS summaries(
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 session_id INTEGER NOT NULL,
+                 through_id INTEGER NOT NULL,
+                 content TEXT NOT NULL,
+                 ts TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS embeddings(
+                 turn_id INTEGER PRIMARY KEY,
+                 model TEXT NOT NULL,
+                 vector BLOB NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS response_cache(
+                 key TEXT PRIMARY KEY,
+                 response TEXT NOT NULL,
+                 ts TEXT NOT NULL
+             );",
         )?;
-        Ok(Self { conn })
+        // databases from older versions lack the turn metadata columns
+        for (col, ty) in [("prompt_tokens", "INTEGER"), ("completion_tokens", "INTEGER"), ("fingerprint", "TEXT"), ("session_id", "INTEGER"), ("pinned", "INTEGER NOT NULL DEFAULT 0")] {
+            if !Self::has_column(conn, "memory", col)? {
+                conn.execute_batch(&format!("ALTER TABLE memory ADD COLUMN {} {}", col, ty))?;
+            }
+        }
+        // the index is kept in sync by triggers; a database from before it existed needs one full build
+        let indexed = conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE name = 'memory_fts'", params![], |r| r.get::<_, i64>(0))? > 0;
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS memory_fts USING fts5(content, content='memory', content_rowid='id');
+             CREATE TRIGGER IF NOT EXISTS memory_fts_insert AFTER INSERT ON memory BEGIN
+                 INSERT INTO memory_fts(rowid, content) VALUES (new.id, new.content);
+             END;
+             CREATE TRIGGER IF NOT EXISTS memory_fts_delete AFTER DELETE ON memory BEGIN
+                 INSERT INTO memory_fts(memory_fts, rowid, content) VALUES ('delete', old.id, old.content);
+             END;
+             CREATE TRIGGER IF NOT EXISTS memory_embeddings_delete AFTER DELETE ON memory BEGIN
+                 DELETE FROM embeddings WHERE turn_id = old.id;
+             END;
+             CREATE TRIGGER IF NOT EXISTS memory_tags_delete AFTER DELETE ON memory BEGIN
+                 DELETE FROM turn_tags WHERE turn_id = old.id;
+             END;
+             CREATE TRIGGER IF NOT EXISTS memory_fts_update AFTER UPDATE OF content ON memory BEGIN
+                 INSERT INTO memory_fts(memory_fts, rowid, content) VALUES ('delete', old.id, old.content);
+                 INSERT INTO memory_fts(rowid, content) VALUES (new.id, new.content);
+             END;",
+        )?;
+        if !indexed { conn.execute_batch("INSERT INTO memory_fts(memory_fts) VALUES ('rebuild')")?; }
+        // turns from before sessions existed belong to the default one
+        conn.execute("INSERT OR IGNORE INTO sessions (name, created) VALUES (?1, ?2)", params![DEFAULT_SESSION, Utc::now().to_rfc3339()])?;
+        conn.execute("UPDATE memory SET session_id = (SELECT id FROM sessions WHERE name = ?1) WHERE session_id IS NULL", params![DEFAULT_SESSION])?;
+        Ok(())
+    }
+    /// Version 2: who answered each turn and how long it took.
+    fn migrate_v2(conn: &Connection) -> Result<()> {
+        conn.execute_batch("ALTER TABLE memory ADD COLUMN provider TEXT; ALTER TABLE memory ADD COLUMN model TEXT; ALTER TABLE memory ADD COLUMN latency_ms INTEGER;")?;
+        Ok(())
+    }
+    /// Version 3: an id that stays the same across machines, and when the turn last changed, for `sync`.
+    fn migrate_v3(conn: &Connection) -> Result<()> {
+        conn.execute_batch(
+            "ALTER TABLE memory ADD COLUMN uid TEXT;
+             ALTER TABLE memory ADD COLUMN updated TEXT;
+             UPDATE memory SET uid = lower(hex(randomblob(16)));
+             CREATE UNIQUE INDEX idx_memory_uid ON memory(uid);",
+        )?;
+        Ok(())
+    }
+    /// Version 4: a hash of each turn's text, to find duplicates without comparing whole turns.
+    fn migrate_v4(conn: &Connection) -> Result<()> {
+        conn.execute_batch("ALTER TABLE memory ADD COLUMN content_hash TEXT;")?;
+        let rows: Vec<(i64, String)> = conn.prepare("SELECT id, content FROM memory")?
+            .query_map(params![], |r| Ok((r.get(0)?, r.get(1)?)))?.filter_map(|r| r.ok()).collect();
+        let mut update = conn.prepare("UPDATE memory SET content_hash = ?1 WHERE id = ?2")?;
+        for (id, content) in rows { update.execute(params![content_hash(&content), id])?; }
+        conn.execute_batch("CREATE INDEX idx_memory_hash ON memory(session_id, content_hash);")?;
+        Ok(())
     }
-    pub fn append(&self, role: &str, content: &str) -> Result<()> {
-        let ts = Utc::now().to_rfc3339();
+    /// Version 5: an optional system prompt per session.
+    fn migrate_v5(conn: &Connection) -> Result<()> {
+        conn.execute_batch("ALTER TABLE sessions ADD COLUMN system_prompt TEXT;")?;
+        Ok(())
+    }
+    /// Version 6: files attached to turns.
+    fn migrate_v6(conn: &Connection) -> Result<()> {
+        conn.execute_batch(
+            "CREATE TABLE attachments(
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 turn_id INTEGER NOT NULL,
+                 name TEXT NOT NULL,
+                 media_type TEXT NOT NULL,
+                 data BLOB NOT NULL,
+                 ts TEXT NOT NULL
+             );
+             CREATE INDEX idx_attachments_turn ON attachments(turn_id);
+             CREATE TRIGGER memory_attachments_delete AFTER DELETE ON memory BEGIN
+                 DELETE FROM attachments WHERE turn_id = old.id;
+             END;",
+        )?;
+        Ok(())
+    }
+    /// Version 7: how often each turn was recalled into a later prompt, for scored retrieval.
+    fn migrate_v7(conn: &Connection) -> Result<()> {
+        conn.execute_batch("ALTER TABLE memory ADD COLUMN refs INTEGER NOT NULL DEFAULT 0;")?;
+        Ok(())
+    }
+    /// Version 8: where `memory-check --repair` puts rows it can't read, as JSON, instead of deleting them.
+    fn migrate_v8(conn: &Connection) -> Result<()> {
+        conn.execute_batch(
+            "CREATE TABLE quarantine(
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 source TEXT NOT NULL,
+                 row_id INTEGER NOT NULL,
+                 data TEXT NOT NULL,
+                 problem TEXT NOT NULL,
+                 ts TEXT NOT NULL
+             );",
+        )?;
+        Ok(())
+    }
+    /// Version 9: the uids of deleted turns, so `sync` deletes them on other machines instead of bringing them back.
+    fn migrate_v9(conn: &Connection) -> Result<()> {
+        conn.execute_batch(
+            "CREATE TABLE deleted_turns(
+                 uid TEXT PRIMARY KEY,
+                 deleted TEXT NOT NULL
+             );
+             CREATE TRIGGER memory_deleted AFTER DELETE ON memory WHEN old.uid IS NOT NULL BEGIN
+                 INSERT OR IGNORE INTO deleted_turns (uid, deleted) VALUES (old.uid, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'));
+             END;",
+        )?;
+        Ok(())
+    }
+    fn has_column(conn: &Connection, table: &str, col: &str) -> Result<bool> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let names = stmt.query_map(params![], |r| r.get::<_, String>(1))?;
+        let found = names.filter_map(|n| n.ok()).any(|n| n == col);
+        Ok(found)
+    }
+    /// Store a turn in the current session, returning its id.
+    pub fn append_meta(&self, role: &str, content: &str, meta: &TurnMeta) -> Result<i64> {
+        let was_saved = self.saved();
+        let ts = Utc::now();
         self.conn.execute(
-            "INSERT INTO memory (role, content, ts) VALUES (?1, ?2, ?3)",
-            params![role, content, ts],
+            "INSERT INTO memory (role, content, ts, prompt_tokens, completion_tokens, fingerprint, session_id, provider, model, latency_ms, uid, content_hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, lower(hex(randomblob(16))), ?11)",
+            params![role, content, ts.to_rfc3339(), meta.prompt_tokens, meta.completion_tokens, meta.fingerprint, self.session_id()?, meta.provider, meta.model, meta.latency_ms, content_hash(content)],
+        )?;
+        let id = self.conn.last_insert_rowid();
+        self.append_jsonl(was_saved, || {
+            let uid = self.conn.query_row("SELECT uid FROM memory WHERE id = ?1", params![id], |r| r.get(0))?;
+            Ok(JsonlLine::Turn { id, session: self.current_session()?, turn: Box::new(ArchivedTurn {
+                uid, role: role.to_string(), content: content.to_string(), ts, prompt_tokens: meta.prompt_tokens, completion_tokens: meta.completion_tokens,
+                fingerprint: meta.fingerprint.clone(), provider: meta.provider.clone(), model: meta.model.clone(), latency_ms: meta.latency_ms, pinned: false, updated: None,
+                tags: Vec::new(), attachments: Vec::new() }) })
+        })?;
+        Ok(id)
+    }
+    /// Facts apply to every session, unlike turns.
+    pub fn add_fact(&self, content: &str) -> Result<i64> {
+        if content.trim().is_empty() { return Err(anyhow!("nothing to remember")); }
+        let (was_saved, ts) = (self.saved(), Utc::now());
+        self.conn.execute("INSERT INTO facts (content, ts) VALUES (?1, ?2)", params![content.trim(), ts.to_rfc3339()])?;
+        let id = self.conn.last_insert_rowid();
+        self.append_jsonl(was_saved, || Ok(JsonlLine::Fact { content: content.trim().to_string(), ts }))?;
+        Ok(id)
+    }
+    pub fn facts(&self) -> Result<Vec<Fact>> {
+        let mut stmt = self.conn.prepare("SELECT id, content, ts FROM facts ORDER BY id")?;
+        let rows = stmt.query_map(params![], |r| {
+            Ok(Fact { id: r.get(0)?, content: r.get(1)?, ts: ts_column(r, 2)? })
+        })?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    }
+    pub fn forget_fact(&self, id: i64) -> Result<()> {
+        if self.conn.execute("DELETE FROM facts WHERE id = ?1", params![id])? == 0 { return Err(anyhow!("no fact #{} (see `mindlink facts list`)", id)); }
+        self.flush()
+    }
+    /// Turns of the current session newer than `id`, oldest first.
+    pub fn turns_after(&self, id: i64) -> Result<Vec<ChatTurn>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, role, content, ts, prompt_tokens, completion_tokens, fingerprint, pinned, provider, model, latency_ms FROM memory WHERE session_id = ?1 AND id > ?2 ORDER BY id"
         )?;
+        let rows = stmt.query_map(params![self.session_id()?, id], turn_row)?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    }
+    /// The current session's newest summary; older ones are kept for the record.
+    pub fn latest_summary(&self) -> Result<Option<Summary>> {
+        let mut stmt = self.conn.prepare("SELECT through_id, content FROM summaries WHERE session_id = ?1 ORDER BY id DESC LIMIT 1")?;
+        let mut rows = stmt.query(params![self.session_id()?])?;
+        Ok(match rows.next()? { Some(r) => Some(Summary { through_id: r.get(0)?, content: r.get(1)? }), None => None })
+    }
+    /// The summary a session had at `at`, i.e. the last one written before then.
+    pub fn summary_as_of(&self, session: &str, at: DateTime<Utc>) -> Result<Option<Summary>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT through_id, content FROM summaries WHERE session_id = (SELECT id FROM sessions WHERE name = ?1) AND ts < ?2 ORDER BY id DESC LIMIT 1"
+        )?;
+        let mut rows = stmt.query(params![session, at.to_rfc3339()])?;
+        Ok(match rows.next()? { Some(r) => Some(Summary { through_id: r.get(0)?, content: r.get(1)? }), None => None })
+    }
+    pub fn put_summary(&self, through_id: i64, content: &str) -> Result<()> {
+        let (was_saved, ts) = (self.saved(), Utc::now());
+        self.conn.execute("INSERT INTO summaries (session_id, through_id, content, ts) VALUES (?1, ?2, ?3, ?4)", params![self.session_id()?, through_id, content, ts.to_rfc3339()])?;
+        self.append_jsonl(was_saved, || Ok(JsonlLine::Summary { session: self.current_session()?, through_id, content: content.to_string(), ts }))
+    }
+    /// Attach an embedding to a turn; `model` keeps vectors from different models apart.
+    pub fn put_embedding(&self, turn_id: i64, model: &str, vector: &[f32]) -> Result<()> {
+        let (was_saved, bytes): (bool, Vec<u8>) = (self.saved(), vector.iter().flat_map(|x| x.to_le_bytes()).collect());
+        self.conn.execute("INSERT OR REPLACE INTO embeddings (turn_id, model, vector) VALUES (?1, ?2, ?3)", params![turn_id, model, bytes])?;
+        self.unkept(was_saved);
+        Ok(())
+    }
+    /// Every embedded turn of the current session made with `model`, as (turn id, vector).
+    pub fn embeddings(&self, model: &str) -> Result<Vec<(i64, Vec<f32>)>> {
+        let mut stmt = self.conn.prepare("SELECT e.turn_id, e.vector FROM embeddings e JOIN memory m ON m.id = e.turn_id WHERE e.model = ?1 AND m.session_id = ?2")?;
+        let rows = stmt.query_map(params![model, self.session_id()?], |r| {
+            let bytes: Vec<u8> = r.get(1)?;
+            Ok((r.get(0)?, bytes.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect()))
+        })?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    }
+    /// Pinned turns of the current session, oldest first.
+    pub fn pinned_turns(&self) -> Result<Vec<ChatTurn>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, role, content, ts, prompt_tokens, completion_tokens, fingerprint, pinned, provider, model, latency_ms FROM memory WHERE session_id = ?1 AND pinned = 1 ORDER BY id"
+        )?;
+        let rows = stmt.query_map(params![self.session_id()?], turn_row)?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    }
+    pub fn set_pinned(&self, id: i64, pinned: bool) -> Result<()> {
+        if self.conn.execute("UPDATE memory SET pinned = ?1, updated = ?2 WHERE id = ?3", params![pinned, Utc::now().to_rfc3339(), id])? == 0 { return Err(anyhow!("no turn #{} (see `mindlink memory-show`)", id)); }
+        self.flush()
+    }
+    pub fn tag(&self, id: i64, tag: &str) -> Result<()> {
+        if tag.is_empty() || tag.contains(char::is_whitespace) { return Err(anyhow!("tags are single words, e.g. bug-hunt")); }
+        if self.conn.query_row("SELECT COUNT(*) FROM memory WHERE id = ?1", params![id], |r| r.get::<_, i64>(0))? == 0 {
+            return Err(anyhow!("no turn #{} (see `mindlink memory-show`)", id));
+        }
+        self.conn.execute("INSERT OR IGNORE INTO turn_tags (turn_id, tag) VALUES (?1, ?2)", params![id, tag])?;
+        self.flush()
+    }
+    pub fn attach(&self, turn_id: i64, name: &str, media_type: &str, data: &[u8]) -> Result<i64> {
+        if self.conn.query_row("SELECT COUNT(*) FROM memory WHERE id = ?1", params![turn_id], |r| r.get::<_, i64>(0))? == 0 {
+            return Err(anyhow!("no turn #{} (see `mindlink memory-show`)", turn_id));
+        }
+        self.conn.execute("INSERT INTO attachments (turn_id, name, media_type, data, ts) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![turn_id, name, media_type, data, Utc::now().to_rfc3339()])?;
+        let id = self.conn.last_insert_rowid();
+        self.flush()?;
+        Ok(id)
+    }
+    pub fn attachments(&self, turn_id: i64) -> Result<Vec<Attachment>> {
+        let mut stmt = self.conn.prepare("SELECT id, turn_id, name, media_type, data FROM attachments WHERE turn_id = ?1 ORDER BY id")?;
+        let rows = stmt.query_map(params![turn_id], |r| Ok(Attachment { id: r.get(0)?, turn_id: r.get(1)?, name: r.get(2)?, media_type: r.get(3)?, data: r.get(4)? }))?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    }
+    pub fn untag(&self, id: i64, tag: &str) -> Result<()> {
+        if self.conn.execute("DELETE FROM turn_tags WHERE turn_id = ?1 AND tag = ?2", params![id, tag])? == 0 { return Err(anyhow!("turn #{} is not tagged {}", id, tag)); }
+        self.flush()
+    }
+    /// The latest `limit` turns passing `filter`, oldest first.
+    pub fn last_turns_filtered(&self, limit: usize, filter: &TurnFilter) -> Result<Vec<ChatTurn>> {
+        let session = match &filter.session {
+            Some(name) if !self.session_exists(name)? => return Err(anyhow!("no session named '{}' (see `mindlink session list`)", name)),
+            Some(name) => self.conn.query_row("SELECT id FROM sessions WHERE name = ?1", params![name], |r| r.get(0))?,
+            None => self.session_id()?,
+        };
+        let mut stmt = self.conn.prepare(
+            "SELECT id, role, content, ts, prompt_tokens, completion_tokens, fingerprint, pinned, provider, model, latency_ms FROM memory
+             WHERE session_id = ?1 AND (?2 IS NULL OR id IN (SELECT turn_id FROM turn_tags WHERE tag = ?2)) AND (?3 IS NULL OR role = ?3)
+               AND (?4 IS NULL OR ts >= ?4) AND (?5 IS NULL OR ts < ?5) ORDER BY id DESC LIMIT ?6"
+        )?;
+        let (since, until) = (filter.since.map(|t| t.to_rfc3339()), filter.until.map(|t| t.to_rfc3339()));
+        let rows = stmt.query_map(params![session, filter.tag, filter.role, since, until, limit as i64], turn_row)?;
+        let mut v: Vec<ChatTurn> = rows.filter_map(|r| r.ok()).collect();
+        v.reverse();
+        Ok(v)
+    }
+    /// The turns with these ids, oldest first.
+    pub fn turns_by_id(&self, ids: &[i64]) -> Result<Vec<ChatTurn>> {
+        let mut stmt = self.conn.prepare("SELECT id, role, content, ts, prompt_tokens, completion_tokens, fingerprint, pinned, provider, model, latency_ms FROM memory WHERE id = ?1")?;
+        let mut v = Vec::with_capacity(ids.len());
+        for id in ids {
+            v.push(stmt.query_row(params![id], turn_row)?);
+        }
+        v.sort_by_key(|t| t.id);
+        Ok(v)
+    }
+    /// Count one more reference to each of these turns.
+    pub fn note_references(&self, ids: &[i64]) -> Result<()> {
+        let was_saved = self.saved();
+        let mut stmt = self.conn.prepare("UPDATE memory SET refs = refs + 1 WHERE id = ?1")?;
+        for id in ids { stmt.execute(params![id])?; }
+        self.unkept(was_saved);
         Ok(())
     }
+    /// How often each of these turns was referenced; those never referenced are left out.
+    pub fn references(&self, ids: &[i64]) -> Result<HashMap<i64, i64>> {
+        let mut stmt = self.conn.prepare("SELECT refs FROM memory WHERE id = ?1 AND refs > 0")?;
+        let mut refs = HashMap::new();
+        for id in ids {
+            if let Ok(n) = stmt.query_row(params![id], |r| r.get(0)) { refs.insert(*id, n); }
+        }
+        Ok(refs)
+    }
     pub fn last_turns(&self, limit: usize) -> Result<Vec<ChatTurn>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, role, content, ts FROM memory ORDER BY id DESC LIMIT ?"
+            "SELECT id, role, content, ts, prompt_tokens, completion_tokens, fingerprint, pinned, provider, model, latency_ms FROM memory WHERE session_id = ?1 ORDER BY id DESC LIMIT ?2"
         )?;
-        let rows = stmt.query_map(params![limit as i64], |r| {
-            let ts_str: String = r.get(3)?;
-            let ts = DateTime::parse_from_rfc3339(&ts_str).unwrap().with_timezone(&Utc);
-            Ok(ChatTurn { id: r.get(0)?, role: r.get(1)?, content: r.get(2)?, ts })
-        })?;
+        let rows = stmt.query_map(params![self.session_id()?, limit as i64], turn_row)?;
         let mut v: Vec<ChatTurn> = rows.filter_map(|r| r.ok()).collect();
         v.reverse();
         Ok(v)
     }
-    pub fn clear(&self) -> Result<()> { self.conn.execute("DELETE FROM memory", params![])?; Ok(()) }
-}
\ No newline at end of file
+    /// Clear the turns of the current session.
+    pub fn clear(&self) -> Result<()> {
+        let session = self.session_id()?;
+        self.conn.execute("DELETE FROM memory WHERE session_id = ?1", params![session])?;
+        self.conn.execute("DELETE FROM summaries WHERE session_id = ?1", params![session])?;
+        self.flush()
+    }
+    pub fn turn(&self, id: i64) -> Result<ChatTurn> {
+        let mut stmt = self.conn.prepare("SELECT id, role, content, ts, prompt_tokens, completion_tokens, fingerprint, pinned, provider, model, latency_ms FROM memory WHERE id = ?1")?;
+        let mut rows = stmt.query_map(params![id], turn_row)?;
+        rows.next().transpose()?.ok_or_else(|| anyhow!("no turn #{} (see `mindlink memory-show`)", id))
+    }
+    /// Rewrite a turn's text; its embedding and any summary covering it are dropped so neither keeps the old text.
+    pub fn set_content(&self, id: i64, content: &str) -> Result<()> {
+        let session: i64 = self.conn.query_row("SELECT session_id FROM memory WHERE id = ?1", params![id], |r| r.get(0))
+            .map_err(|_| anyhow!("no turn #{} (see `mindlink memory-show`)", id))?;
+        self.conn.execute("UPDATE memory SET content = ?1, updated = ?2, content_hash = ?3 WHERE id = ?4", params![content, Utc::now().to_rfc3339(), content_hash(content), id])?;
+        self.conn.execute("DELETE FROM embeddings WHERE turn_id = ?1", params![id])?;
+        self.drop_summaries_from(session, id)
+    }
+    /// Delete one turn, in whichever session it is.
+    pub fn delete(&self, id: i64) -> Result<()> {
+        let mut stmt = self.conn.prepare("SELECT session_id FROM memory WHERE id = ?1")?;
+        let mut rows = stmt.query(params![id])?;
+        let Some(r) = rows.next()? else { return Err(anyhow!("no turn #{} (see `mindlink memory-show`)", id)); };
+        let session: i64 = r.get(0)?;
+        self.conn.execute("DELETE FROM memory WHERE id = ?1", params![id])?;
+        self.drop_summaries_from(session, id)
+    }
+    /// Delete the current session's turns with ids in `from..=to` (open-ended when missing); returns how many went.
+    pub fn delete_range(&self, from: Option<i64>, to: Option<i64>) -> Result<usize> {
+        let (from, to) = (from.unwrap_or(i64::MIN), to.unwrap_or(i64::MAX));
+        if from > to { return Err(anyhow!("--from {} is after --to {}", from, to)); }
+        let session = self.session_id()?;
+        let n = self.conn.execute("DELETE FROM memory WHERE session_id = ?1 AND id BETWEEN ?2 AND ?3", params![session, from, to])?;
+        if n > 0 { self.drop_summaries_from(session, from)?; }
+        self.flush()?;
+        Ok(n)
+    }
+    // a summary covering a deleted turn would keep repeating it; older ones stay and compaction catches up again
+    fn drop_summaries_from(&self, session: i64, id: i64) -> Result<()> {
+        self.conn.execute("DELETE FROM summaries WHERE session_id = ?1 AND through_id >= ?2", params![session, id])?;
+        self.flush()
+    }
+    pub fn stats(&self) -> Result<Stats> {
+        let time = |v: Option<String>| v.and_then(|s| DateTime::parse_from_rfc3339(&s).ok()).map(|t| t.with_timezone(&Utc));
+        let (first, last, prompt_tokens, completion_tokens): (Option<String>, Option<String>, i64, i64) = self.conn.query_row(
+            "SELECT MIN(ts), MAX(ts), COALESCE(SUM(prompt_tokens), 0), COALESCE(SUM(completion_tokens), 0) FROM memory",
+            params![], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))?;
+        let mut content_tokens = 0;
+        let mut stmt = self.conn.prepare("SELECT content FROM memory")?;
+        for c in stmt.query_map(params![], |r| r.get::<_, String>(0))? { content_tokens += crate::tokens::estimate(&c?); }
+        let top_tags = self.conn.prepare("SELECT tag, COUNT(*) AS n FROM turn_tags GROUP BY tag ORDER BY n DESC, tag LIMIT 5")?
+            .query_map(params![], |r| Ok((r.get(0)?, r.get(1)?)))?.filter_map(|r| r.ok()).collect();
+        let file_bytes = self.file_bytes()?;
+        Ok(Stats {
+            sessions: self.sessions()?, file_bytes, first: time(first), last: time(last), content_tokens, prompt_tokens, completion_tokens,
+            facts: self.conn.query_row("SELECT COUNT(*) FROM facts", params![], |r| r.get(0))?, top_tags,
+        })
+    }
+    /// Size on disk: the main file plus a write-ahead log that hasn't been checkpointed yet.
+    fn file_bytes(&self) -> Result<u64> {
+        let path = self.path()?.to_string_lossy().into_owned();
+        let size = |p: &str| std::fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+        Ok(size(&path) + size(&format!("{}-wal", path)))
+    }
+    /// Rebuild indexes, refresh the query planner's statistics and rewrite the file without the pages freed by
+    /// deletes. Returns the size on disk before and after.
+    pub fn vacuum(&self) -> Result<(u64, u64)> {
+        let (was_saved, before) = (self.saved(), self.file_bytes()?);
+        self.conn.execute_batch(
+            "INSERT INTO memory_fts(memory_fts) VALUES ('optimize');
+             REINDEX;
+             ANALYZE;
+             VACUUM;",
+        )?;
+        // VACUUM goes through the write-ahead log; fold it back into the main file and empty it
+        self.conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", params![], |_| Ok(()))?;
+        self.unkept(was_saved);
+        Ok((before, self.file_bytes()?))
+    }
+    /// The schema version of the file, and the one this build brings it to.
+    pub fn schema_version(&self) -> Result<(usize, usize)> { Ok((self.conn.query_row("PRAGMA user_version", params![], |r| r.get::<_, i64>(0))? as usize, MIGRATIONS.len())) }
+    /// What `PRAGMA quick_check` finds wrong with the file, quicker than `check` and fine on a read-only connection.
+    pub fn quick_check(&self) -> Result<Vec<String>> {
+        Ok(self.conn.prepare("PRAGMA quick_check")?.query_map(params![], |r| r.get::<_, String>(0))?.filter_map(|r| r.ok()).filter(|m| m != "ok").collect())
+    }
+    /// Look for damage: a corrupt file or full-text index, and turns, facts and sessions whose time or role mindlink
+    /// can't read (they are skipped everywhere else). With `repair` the bad turns and facts move to the `quarantine`
+    /// table, a session's unreadable creation time becomes that of its first turn, and the full-text index is rebuilt;
+    /// a corrupt file can't be mended here, only restored from a snapshot.
+    pub fn check(&self, repair: bool) -> Result<CheckReport> {
+        if repair && self.jsonl.is_some() { return Err(anyhow!("--repair works on SQLite memory files; fix the .jsonl by hand")); }
+        let integrity = self.conn.prepare("PRAGMA integrity_check")?.query_map(params![], |r| r.get::<_, String>(0))?
+            .filter_map(|r| r.ok()).filter(|m| m != "ok").collect();
+        let index_stale = self.conn.execute_batch("INSERT INTO memory_fts(memory_fts) VALUES ('integrity-check')").is_err();
+        let mut report = CheckReport { integrity, index_stale, ..Default::default() };
+        let bad_ts = |ts: &str| DateTime::parse_from_rfc3339(ts).err().map(|_| format!("unreadable time {:?}", ts));
+        for (table, sql) in [("memory", "SELECT id, ts, role FROM memory ORDER BY id"), ("facts", "SELECT id, ts, NULL FROM facts ORDER BY id"), ("sessions", "SELECT id, created, NULL FROM sessions ORDER BY id")] {
+            let rows: Vec<(i64, String, Option<String>)> = self.conn.prepare(sql)?.query_map(params![], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?.collect::<rusqlite::Result<_>>()?;
+            for (id, ts, role) in rows {
+                let bad_role = role.filter(|r| r != "user" && r != "assistant").map(|r| format!("unknown role {:?}", r));
+                if let Some(problem) = bad_ts(&ts).or(bad_role) { report.bad_rows.push(BadRow { table, id, problem }); }
+            }
+        }
+        if !repair { return Ok(report); }
+        let tx = self.conn.unchecked_transaction()?;
+        let now = Utc::now().to_rfc3339();
+        for bad in &report.bad_rows {
+            match bad.table {
+                "memory" => {
+                    tx.execute(
+                        "INSERT INTO quarantine (source, row_id, data, problem, ts)
+                         SELECT 'memory', id, json_object('role', role, 'content', content, 'ts', ts, 'session_id', session_id, 'uid', uid, 'pinned', pinned,
+                             'provider', provider, 'model', model, 'prompt_tokens', prompt_tokens, 'completion_tokens', completion_tokens), ?2, ?3
+                         FROM memory WHERE id = ?1",
+                        params![bad.id, bad.problem, now],
+                    )?;
+                    let session: i64 = tx.query_row("SELECT session_id FROM memory WHERE id = ?1", params![bad.id], |r| r.get(0))?;
+                    tx.execute("DELETE FROM memory WHERE id = ?1", params![bad.id])?;
+                    tx.execute("DELETE FROM summaries WHERE session_id = ?1 AND through_id >= ?2", params![session, bad.id])?;
+                }
+                "facts" => {
+                    tx.execute(
+                        "INSERT INTO quarantine (source, row_id, data, problem, ts) SELECT 'facts', id, json_object('content', content, 'ts', ts), ?2, ?3 FROM facts WHERE id = ?1",
+                        params![bad.id, bad.problem, now],
+                    )?;
+                    tx.execute("DELETE FROM facts WHERE id = ?1", params![bad.id])?;
+                }
+                _ => {
+                    let first: Option<String> = tx.query_row("SELECT MIN(ts) FROM memory WHERE session_id = ?1", params![bad.id], |r| r.get(0))?;
+                    let created = first.filter(|ts| bad_ts(ts).is_none()).unwrap_or_else(|| now.clone());
+                    tx.execute("UPDATE sessions SET created = ?1 WHERE id = ?2", params![created, bad.id])?;
+                }
+            }
+            report.repaired += 1;
+        }
+        if report.index_stale { tx.execute_batch("INSERT INTO memory_fts(memory_fts) VALUES ('rebuild')")?; }
+        tx.commit()?;
+        Ok(report)
+    }
+    fn path(&self) -> Result<PathBuf> {
+        if let Some(file) = &self.jsonl { return Ok(file.path.clone()); }
+        Ok(PathBuf::from(self.conn.query_row("SELECT file FROM pragma_database_list WHERE name = 'main'", params![], |r| r.get::<_, String>(0))?))
+    }
+    /// Snapshots live next to the database, in `snapshots/<name>.db`.
+    fn snapshot_path(&self, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) || name.starts_with('.') {
+            return Err(anyhow!("snapshot name {:?} may only use letters, digits and - _ .", name));
+        }
+        Ok(self.path()?.with_file_name("snapshots").join(format!("{}.db", name)))
+    }
+    /// Save a consistent copy of the whole database under `name`, replacing an older snapshot of that name.
+    pub fn snapshot(&self, name: &str) -> Result<PathBuf> {
+        let path = self.snapshot_path(name)?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        // VACUUM INTO refuses to overwrite, and the copy it writes is compact and needs no WAL
+        if path.exists() { std::fs::remove_file(&path)?; }
+        self.conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])?;
+        Ok(path)
+    }
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>> {
+        let dir = self.path()?.with_file_name("snapshots");
+        let mut out = Vec::new();
+        let entries = match std::fs::read_dir(&dir) { Ok(e) => e, Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(out), Err(e) => return Err(e.into()) };
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let (Some(name), Some("db")) = (path.file_stem().and_then(|s| s.to_str()), path.extension().and_then(|s| s.to_str())) else { continue };
+            let meta = entry.metadata()?;
+            out.push(Snapshot { name: name.to_string(), taken: meta.modified().map(DateTime::<Utc>::from).unwrap_or_else(|_| Utc::now()), bytes: meta.len() });
+        }
+        out.sort_by_key(|s| s.taken);
+        Ok(out)
+    }
+    /// Replace everything in the database with snapshot `name`. The current contents are saved first as the
+    /// `before-restore` snapshot, so a restore can itself be undone.
+    pub fn restore(&mut self, name: &str) -> Result<()> {
+        let path = self.snapshot_path(name)?;
+        if !path.exists() { return Err(anyhow!("no snapshot named {} (see memory-snapshot --list)", name)); }
+        if name != "before-restore" { self.snapshot("before-restore")?; }
+        self.conn.restore(rusqlite::DatabaseName::Main, &path, None::<fn(rusqlite::backup::Progress)>)?;
+        // a snapshot taken by an older mindlink is upgraded like any other database
+        Self::migrate(&self.conn)?;
+        // a restore isn't counted as changes, so the file is written whether or not it looks saved
+        self.rewrite_jsonl()
+    }
+    /// Run `redactor` over every stored text in all sessions: turns, facts, summaries, text attachments and
+    /// system prompts. Redacted turns count as edited, so sync carries the redaction to other machines. Unless this
+    /// is a dry run the file is vacuumed afterwards, so the old text doesn't linger in free pages.
+    pub fn redact(&self, redactor: &Redactor, dry_run: bool) -> Result<Redaction> {
+        let mut r = Redaction::default();
+        let tx = self.conn.unchecked_transaction()?;
+        let mut scan = |table: &str, column: &str, key: &str| -> Result<Vec<i64>> {
+            let rows: Vec<(i64, String)> = tx.prepare(&format!("SELECT {}, {} FROM {} WHERE {} IS NOT NULL", key, column, table, column))?
+                .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))?.filter_map(|row| row.ok()).collect();
+            let mut changed = Vec::new();
+            for (id, text) in rows {
+                let Some((clean, hits)) = redactor.redact(&text) else { continue };
+                for h in hits { *r.hits.entry(h).or_default() += 1; }
+                if !dry_run {
+                    if table == "memory" {
+                        tx.execute("UPDATE memory SET content = ?1, content_hash = ?2, updated = ?3 WHERE id = ?4", params![clean, content_hash(&clean), Utc::now().to_rfc3339(), id])?;
+                    } else {
+                        tx.execute(&format!("UPDATE {} SET {} = ?1 WHERE {} = ?2", table, column, key), params![clean, id])?;
+                    }
+                }
+                changed.push(id);
+            }
+            Ok(changed)
+        };
+        let turns = scan("memory", "content", "id")?;
+        let facts = scan("facts", "content", "id")?.len();
+        let summaries = scan("summaries", "content", "id")?.len();
+        let system_prompts = scan("sessions", "system_prompt", "id")?.len();
+        // binary attachments are left alone; only text ones can hold a pasted key
+        let text_attachments: Vec<(i64, Vec<u8>)> = tx.prepare("SELECT id, data FROM attachments")?
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))?.filter_map(|row| row.ok()).collect();
+        for (id, data) in text_attachments {
+            let Ok(text) = std::str::from_utf8(&data) else { continue };
+            let Some((clean, hits)) = redactor.redact(text) else { continue };
+            for h in hits { *r.hits.entry(h).or_default() += 1; }
+            if !dry_run { tx.execute("UPDATE attachments SET data = ?1 WHERE id = ?2", params![clean.as_bytes(), id])?; }
+            r.attachments += 1;
+        }
+        if dry_run || r.hits.is_empty() { return Ok(Redaction { turns, facts, summaries, system_prompts, ..r }); }
+        // cached answers may repeat the secret, and are cheap to lose
+        tx.execute("DELETE FROM response_cache", params![])?;
+        tx.commit()?;
+        self.vacuum()?;
+        self.flush()?;
+        Ok(Redaction { turns, facts, summaries, system_prompts, ..r })
+    }
+    /// Whether the current session already has this question with this answer right after it.
+    pub fn has_exchange(&self, question: &str, answer: &str) -> Result<bool> {
+        Ok(self.conn.query_row(
+            "SELECT COUNT(*) FROM memory u JOIN memory a ON a.id = (SELECT MIN(id) FROM memory WHERE session_id = u.session_id AND id > u.id)
+             WHERE u.session_id = ?1 AND u.role = 'user' AND u.content_hash = ?2 AND a.role = 'assistant' AND a.content_hash = ?3",
+            params![self.session_id()?, content_hash(question), content_hash(answer)], |r| r.get::<_, i64>(0))? > 0)
+    }
+    /// Collapse exact repeats of an exchange (a question and the answer after it) within each session, keeping the
+    /// first; pinned turns always stay. Returns how many exchanges were removed.
+    pub fn dedup(&self) -> Result<usize> {
+        let rows: Vec<(i64, i64, String, Option<String>, bool)> = self.conn.prepare("SELECT id, session_id, role, content_hash, pinned FROM memory ORDER BY session_id, id")?
+            .query_map(params![], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)))?.filter_map(|r| r.ok()).collect();
+        let mut seen = std::collections::HashSet::new();
+        let mut doomed = Vec::new();
+        for pair in rows.windows(2) {
+            let ((q_id, q_session, q_role, q_hash, q_pinned), (a_id, a_session, a_role, a_hash, a_pinned)) = (&pair[0], &pair[1]);
+            if q_session != a_session || q_role != "user" || a_role != "assistant" { continue; }
+            if !seen.insert((*q_session, q_hash.clone(), a_hash.clone())) && !q_pinned && !a_pinned { doomed.push((*q_id, *a_id)); }
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        for (q, a) in &doomed { tx.execute("DELETE FROM memory WHERE id IN (?1, ?2)", params![q, a])?; }
+        tx.commit()?;
+        self.flush()?;
+        Ok(doomed.len())
+    }
+    /// Delete the oldest unpinned turns, across all sessions, until the database is within `policy`; returns how many went.
+    pub fn prune(&self, policy: &Retention) -> Result<usize> {
+        let mut n = 0;
+        if let Some(days) = policy.max_age_days {
+            let cutoff = (Utc::now() - chrono::Duration::days(days as i64)).to_rfc3339();
+            n += self.conn.execute("DELETE FROM memory WHERE pinned = 0 AND ts < ?1", params![cutoff])?;
+        }
+        if let Some(max) = policy.max_turns {
+            n += self.conn.execute(
+                "DELETE FROM memory WHERE pinned = 0 AND id NOT IN (SELECT id FROM memory WHERE pinned = 0 ORDER BY id DESC LIMIT ?1)",
+                params![max as i64],
+            )?;
+        }
+        if let Some(mb) = policy.max_size_mb {
+            let limit = mb * 1024 * 1024;
+            if self.used_bytes()? > limit {
+                // cached responses are the cheapest thing to lose
+                self.conn.execute("DELETE FROM response_cache", params![])?;
+                while self.used_bytes()? > limit {
+                    let k = self.conn.execute("DELETE FROM memory WHERE id IN (SELECT id FROM memory WHERE pinned = 0 ORDER BY id LIMIT 100)", params![])?;
+                    if k == 0 { break; }
+                    n += k;
+                }
+                // freed pages only shrink the file once vacuumed
+                self.conn.execute_batch("VACUUM")?;
+            }
+        }
+        self.flush()?;
+        Ok(n)
+    }
+    fn used_bytes(&self) -> Result<u64> {
+        let pages: i64 = self.conn.query_row("SELECT (SELECT page_count FROM pragma_page_count()) - (SELECT freelist_count FROM pragma_freelist_count())", params![], |r| r.get(0))?;
+        let size: i64 = self.conn.query_row("SELECT page_size FROM pragma_page_size()", params![], |r| r.get(0))?;
+        Ok((pages * size) as u64)
+    }
+    /// Name of the session new turns go to; falls back to the default one if it was deleted.
+    pub fn current_session(&self) -> Result<String> {
+        let mut stmt = self.conn.prepare("SELECT s.name FROM settings JOIN sessions s ON s.name = settings.value WHERE settings.key = 'session'")?;
+        let mut rows = stmt.query(params![])?;
+        Ok(match rows.next()? { Some(r) => r.get(0)?, None => DEFAULT_SESSION.to_string() })
+    }
+    fn session_id(&self) -> Result<i64> {
+        let name = self.current_session()?;
+        Ok(self.conn.query_row("SELECT id FROM sessions WHERE name = ?1", params![name], |r| r.get(0))?)
+    }
+    fn session_exists(&self, name: &str) -> Result<bool> {
+        Ok(self.conn.query_row("SELECT COUNT(*) FROM sessions WHERE name = ?1", params![name], |r| r.get::<_, i64>(0))? > 0)
+    }
+    /// The current session's system prompt, if one was set.
+    pub fn system_prompt(&self) -> Result<Option<String>> {
+        Ok(self.conn.query_row("SELECT system_prompt FROM sessions WHERE id = ?1", params![self.session_id()?], |r| r.get(0))?)
+    }
+    /// Set (or with `None`, remove) the current session's system prompt.
+    pub fn set_system_prompt(&self, prompt: Option<&str>) -> Result<()> {
+        let was_saved = self.saved();
+        self.conn.execute("UPDATE sessions SET system_prompt = ?1 WHERE id = ?2", params![prompt, self.session_id()?])?;
+        self.append_jsonl(was_saved, || self.session_line(&self.current_session()?))
+    }
+    pub fn latest_session(&self) -> Result<Option<String>> {
+        let mut stmt = self.conn.prepare("SELECT s.name FROM memory m JOIN sessions s ON s.id = m.session_id ORDER BY m.id DESC LIMIT 1")?;
+        let mut rows = stmt.query(params![])?;
+        Ok(match rows.next()? { Some(r) => Some(r.get(0)?), None => None })
+    }
+    pub fn sessions(&self) -> Result<Vec<Session>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT s.name, s.created, COUNT(m.id), s.system_prompt FROM sessions s LEFT JOIN memory m ON m.session_id = s.id GROUP BY s.id ORDER BY s.name"
+        )?;
+        let rows = stmt.query_map(params![], |r| {
+            Ok(Session { name: r.get(0)?, created: ts_column(r, 1)?, turns: r.get(2)?, system_prompt: r.get(3)? })
+        })?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    }
+    /// The `.jsonl` line that records session `name` as it is now.
+    fn session_line(&self, name: &str) -> Result<JsonlLine> {
+        let (created, system_prompt): (String, Option<String>) = self.conn.query_row("SELECT created, system_prompt FROM sessions WHERE name = ?1", params![name], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        Ok(JsonlLine::Session { name: name.to_string(), created: DateTime::parse_from_rfc3339(&created)?.with_timezone(&Utc), system_prompt })
+    }
+    /// Every session (or just `only`) with all its turns and tags, plus the facts.
+    pub fn export(&self, only: Option<&str>) -> Result<Archive> {
+        if let Some(name) = only { if !self.session_exists(name)? { return Err(anyhow!("no session named '{}'", name)); } }
+        let mut sessions = Vec::new();
+        for s in self.sessions()?.into_iter().filter(|s| only.is_none_or(|n| n == s.name)) {
+            let turns = self.archived_turns(Some(&s.name))?.into_iter().map(|(_, _, t)| t).collect();
+            sessions.push(ArchivedSession { name: s.name, created: s.created, system_prompt: s.system_prompt, turns });
+        }
+        let facts = if only.is_some() { Vec::new() } else { self.facts()?.into_iter().map(|f| f.content).collect() };
+        let deleted = if only.is_some() { Vec::new() } else { self.deleted_turns()? };
+        Ok(Archive { version: ARCHIVE_VERSION, exported: Utc::now(), facts, sessions, deleted })
+    }
+    /// Turns deleted from this database, by uid, in the order they went.
+    fn deleted_turns(&self) -> Result<Vec<DeletedTurn>> {
+        let rows: Vec<(String, String)> = self.conn.prepare("SELECT uid, deleted FROM deleted_turns ORDER BY rowid")?
+            .query_map(params![], |r| Ok((r.get(0)?, r.get(1)?)))?.filter_map(|r| r.ok()).collect();
+        rows.into_iter().map(|(uid, deleted)| Ok(DeletedTurn { uid, deleted: DateTime::parse_from_rfc3339(&deleted)?.with_timezone(&Utc) })).collect()
+    }
+    /// Turns of one session (or all, in id order) with their row id, session name, tags and attachments.
+    fn archived_turns(&self, session: Option<&str>) -> Result<Vec<(i64, String, ArchivedTurn)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT m.id, m.role, m.content, m.ts, m.prompt_tokens, m.completion_tokens, m.fingerprint, m.pinned, m.provider, m.model, m.latency_ms, m.uid, m.updated, s.name
+             FROM memory m JOIN sessions s ON s.id = m.session_id WHERE ?1 IS NULL OR s.name = ?1 ORDER BY m.id"
+        )?;
+        let rows: Vec<(ChatTurn, Option<String>, Option<String>, String)> = stmt.query_map(params![session], |r| Ok((turn_row(r)?, r.get(11)?, r.get(12)?, r.get(13)?)))?
+            .filter_map(|r| r.ok()).collect();
+        let mut tag_stmt = self.conn.prepare("SELECT tag FROM turn_tags WHERE turn_id = ?1 ORDER BY tag")?;
+        let mut turns = Vec::with_capacity(rows.len());
+        for (t, uid, updated, session) in rows {
+            let updated = updated.and_then(|u| DateTime::parse_from_rfc3339(&u).ok()).map(|u| u.with_timezone(&Utc));
+            let tags = tag_stmt.query_map(params![t.id], |r| r.get(0))?.filter_map(|r| r.ok()).collect();
+            let attachments = self.attachments(t.id)?.into_iter()
+                .map(|a| ArchivedAttachment { name: a.name, media_type: a.media_type, data: base64::engine::general_purpose::STANDARD.encode(a.data) }).collect();
+            turns.push((t.id, session, ArchivedTurn { role: t.role, content: t.content, ts: t.ts, prompt_tokens: t.prompt_tokens, completion_tokens: t.completion_tokens, fingerprint: t.fingerprint,
+                pinned: t.pinned, provider: t.provider, model: t.model, latency_ms: t.latency_ms, uid, updated, tags, attachments }));
+        }
+        Ok(turns)
+    }
+    /// Merge an archive into this database. A turn already here (same uid, or for archives without uids the same
+    /// session, role, time and text) is skipped, unless the archive's copy was edited more recently, in which case it
+    /// wins; importing the same file twice is harmless. Tags are merged. Turns the archive lists as deleted are deleted
+    /// here too, unless edited since, and a turn deleted here is not brought back.
+    pub fn import(&self, archive: &Archive) -> Result<ImportStats> {
+        let mut stats = ImportStats { sessions: archive.sessions.len(), ..Default::default() };
+        let tx = self.conn.unchecked_transaction()?;
+        for s in &archive.sessions {
+            if s.name.trim().is_empty() { return Err(anyhow!("archive has a session without a name")); }
+            tx.execute("INSERT OR IGNORE INTO sessions (name, created) VALUES (?1, ?2)", params![s.name, s.created.to_rfc3339()])?;
+            let session_id: i64 = tx.query_row("SELECT id FROM sessions WHERE name = ?1", params![s.name], |r| r.get(0))?;
+            // a prompt set here is kept; the archive's only fills a gap
+            if let Some(prompt) = &s.system_prompt { tx.execute("UPDATE sessions SET system_prompt = ?1 WHERE id = ?2 AND system_prompt IS NULL", params![prompt, session_id])?; }
+            for t in &s.turns {
+                let ts = t.ts.to_rfc3339();
+                let mut stmt = tx.prepare("SELECT deleted FROM deleted_turns WHERE uid = ?1")?;
+                let deleted: Option<String> = stmt.query_map(params![t.uid], |r| r.get(0))?.next().transpose()?;
+                // deleted here after the archive's copy last changed
+                if deleted.and_then(|d| DateTime::parse_from_rfc3339(&d).ok()).is_some_and(|d| d >= t.updated.unwrap_or(t.ts)) {
+                    stats.skipped += 1;
+                    continue;
+                }
+                let mut stmt = tx.prepare("SELECT id, COALESCE(updated, ts) FROM memory WHERE uid = ?1")?;
+                let known: Option<(i64, String)> = stmt.query_map(params![t.uid], |r| Ok((r.get(0)?, r.get(1)?)))?.next().transpose()?;
+                let known = match known {
+                    Some(k) => Some(k),
+                    None => {
+                        let mut stmt = tx.prepare("SELECT id, COALESCE(updated, ts) FROM memory WHERE session_id = ?1 AND role = ?2 AND ts = ?3 AND content = ?4")?;
+                        let k: Option<(i64, String)> = stmt.query_map(params![session_id, t.role, ts, t.content], |r| Ok((r.get(0)?, r.get(1)?)))?.next().transpose()?;
+                        // the same turn imported before uids existed; adopt the archive's so later syncs match it directly
+                        if let (Some((id, _)), Some(uid)) = (&k, &t.uid) { tx.execute("UPDATE memory SET uid = ?1 WHERE id = ?2", params![uid, id])?; }
+                        k
+                    }
+                };
+                let id = match known {
+                    Some((id, local)) => {
+                        let local = DateTime::parse_from_rfc3339(&local).map(|l| l.with_timezone(&Utc)).unwrap_or(t.ts);
+                        let theirs = t.updated.unwrap_or(t.ts);
+                        if theirs > local {
+                            self.set_content(id, &t.content)?;
+                            tx.execute("UPDATE memory SET pinned = ?1, updated = ?2 WHERE id = ?3", params![t.pinned, theirs.to_rfc3339(), id])?;
+                            stats.updated += 1;
+                        } else {
+                            stats.skipped += 1;
+                        }
+                        id
+                    }
+                    None => {
+                        tx.execute(
+                            "INSERT INTO memory (role, content, ts, prompt_tokens, completion_tokens, fingerprint, session_id, pinned, provider, model, latency_ms, uid, updated, content_hash)
+                             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, COALESCE(?12, lower(hex(randomblob(16)))), ?13, ?14)",
+                            params![t.role, t.content, ts, t.prompt_tokens, t.completion_tokens, t.fingerprint, session_id, t.pinned, t.provider, t.model, t.latency_ms,
+                                    t.uid, t.updated.map(|u| u.to_rfc3339()), content_hash(&t.content)],
+                        )?;
+                        stats.added += 1;
+                        tx.last_insert_rowid()
+                    }
+                };
+                for tag in &t.tags { tx.execute("INSERT OR IGNORE INTO turn_tags (turn_id, tag) VALUES (?1, ?2)", params![id, tag])?; }
+                for a in &t.attachments {
+                    let data = base64::engine::general_purpose::STANDARD.decode(&a.data).map_err(|e| anyhow!("attachment {} is not valid base64: {}", a.name, e))?;
+                    tx.execute("INSERT INTO attachments (turn_id, name, media_type, data, ts) SELECT ?1, ?2, ?3, ?4, ?5
+                                WHERE NOT EXISTS (SELECT 1 FROM attachments WHERE turn_id = ?1 AND name = ?2 AND data = ?4)",
+                        params![id, a.name, a.media_type, data, Utc::now().to_rfc3339()])?;
+                }
+            }
+        }
+        for d in &archive.deleted {
+            let mut stmt = tx.prepare("SELECT id, session_id, COALESCE(updated, ts) FROM memory WHERE uid = ?1")?;
+            let known: Option<(i64, i64, String)> = stmt.query_map(params![d.uid], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?.next().transpose()?;
+            // edited here after it was deleted there: the later change wins, as between two copies
+            if known.as_ref().is_some_and(|(_, _, local)| DateTime::parse_from_rfc3339(local).is_ok_and(|l| l > d.deleted)) { continue; }
+            // recorded first, with the time it was deleted there, so the delete below doesn't stamp it with now
+            tx.execute("INSERT OR IGNORE INTO deleted_turns (uid, deleted) VALUES (?1, ?2)", params![d.uid, d.deleted.to_rfc3339()])?;
+            if let Some((id, session, _)) = known {
+                tx.execute("DELETE FROM memory WHERE id = ?1", params![id])?;
+                self.drop_summaries_from(session, id)?;
+                stats.deleted += 1;
+            }
+        }
+        for f in &archive.facts {
+            let exists: i64 = tx.query_row("SELECT COUNT(*) FROM facts WHERE content = ?1", params![f], |r| r.get(0))?;
+            if exists == 0 { tx.execute("INSERT INTO facts (content, ts) VALUES (?1, ?2)", params![f, Utc::now().to_rfc3339()])?; }
+        }
+        tx.commit()?;
+        self.flush()?;
+        Ok(stats)
+    }
+    /// Best matches for `query` (all words must appear), in the current session or in all of them.
+    /// `mark` wraps each matched word in the snippet.
+    pub fn search(&self, query: &str, all_sessions: bool, limit: usize, mark: (&str, &str)) -> Result<Vec<SearchHit>> {
+        // quote every word so punctuation in a plain query isn't taken for FTS5 syntax
+        let fts_query = query.split_whitespace().map(|w| format!("\"{}\"", w.replace('"', "\"\""))).collect::<Vec<_>>().join(" ");
+        if fts_query.is_empty() { return Ok(Vec::new()); }
+        let session = if all_sessions { None } else { Some(self.session_id()?) };
+        let mut stmt = self.conn.prepare(
+            "SELECT m.role, m.ts, s.name, snippet(memory_fts, 0, ?1, ?2, '...', 24)
+             FROM memory_fts JOIN memory m ON m.id = memory_fts.rowid JOIN sessions s ON s.id = m.session_id
+             WHERE memory_fts MATCH ?3 AND (?4 IS NULL OR m.session_id = ?4)
+             ORDER BY rank LIMIT ?5"
+        )?;
+        let rows = stmt.query_map(params![mark.0, mark.1, fts_query, session, limit as i64], |r| {
+            Ok(SearchHit { role: r.get(0)?, ts: ts_column(r, 1)?, session: r.get(2)?, snippet: r.get(3)? })
+        })?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    }
+    /// Turns of the other sessions sharing words with `text`, best match first, each with the name of its session.
+    pub fn other_session_turns(&self, text: &str, limit: usize) -> Result<Vec<(String, ChatTurn)>> {
+        let words = recall_words(text);
+        if words.is_empty() || limit == 0 { return Ok(Vec::new()); }
+        let fts_query = words.iter().map(|w| format!("\"{}\"", w)).collect::<Vec<_>>().join(" OR ");
+        let mut stmt = self.conn.prepare(
+            "SELECT m.id, m.role, m.content, m.ts, m.prompt_tokens, m.completion_tokens, m.fingerprint, m.pinned, m.provider, m.model, m.latency_ms, s.name
+             FROM memory_fts JOIN memory m ON m.id = memory_fts.rowid JOIN sessions s ON s.id = m.session_id
+             WHERE memory_fts MATCH ?1 AND m.session_id != ?2 ORDER BY rank LIMIT ?3"
+        )?;
+        let rows = stmt.query_map(params![fts_query, self.session_id()?, limit as i64], |r| Ok((r.get(11)?, turn_row(r)?)))?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    }
+    /// Create a session and make it the current one.
+    pub fn session_new(&self, name: &str) -> Result<()> {
+        if name.trim().is_empty() { return Err(anyhow!("session name must not be empty")); }
+        if self.session_exists(name)? { return Err(anyhow!("session '{}' already exists", name)); }
+        let was_saved = self.saved();
+        self.conn.execute("INSERT INTO sessions (name, created) VALUES (?1, ?2)", params![name, Utc::now().to_rfc3339()])?;
+        self.append_jsonl(was_saved, || self.session_line(name))?;
+        self.session_switch(name)
+    }
+    /// Copy the current session, turns, tags, embeddings and summaries included, into a new session and switch to it;
+    /// the original stays as it was. Returns how many turns were copied.
+    pub fn session_branch(&self, name: &str) -> Result<usize> {
+        if name.trim().is_empty() { return Err(anyhow!("session name must not be empty")); }
+        if self.session_exists(name)? { return Err(anyhow!("session '{}' already exists", name)); }
+        let from = self.session_id()?;
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("INSERT INTO sessions (name, created, system_prompt) SELECT ?1, ?2, system_prompt FROM sessions WHERE id = ?3", params![name, Utc::now().to_rfc3339(), from])?;
+        let to = tx.last_insert_rowid();
+        let ids: Vec<i64> = tx.prepare("SELECT id FROM memory WHERE session_id = ?1 ORDER BY id")?
+            .query_map(params![from], |r| r.get(0))?.filter_map(|r| r.ok()).collect();
+        // summaries point at the turn they run through, so their ids have to follow the copies
+        let mut new_ids = std::collections::BTreeMap::new();
+        for id in &ids {
+            tx.execute(
+                "INSERT INTO memory (role, content, ts, prompt_tokens, completion_tokens, fingerprint, session_id, pinned, provider, model, latency_ms, uid, content_hash)
+                 SELECT role, content, ts, prompt_tokens, completion_tokens, fingerprint, ?1, pinned, provider, model, latency_ms, lower(hex(randomblob(16))), content_hash
+                 FROM memory WHERE id = ?2",
+                params![to, id],
+            )?;
+            let new_id = tx.last_insert_rowid();
+            tx.execute("INSERT INTO turn_tags (turn_id, tag) SELECT ?1, tag FROM turn_tags WHERE turn_id = ?2", params![new_id, id])?;
+            tx.execute("INSERT INTO embeddings (turn_id, model, vector) SELECT ?1, model, vector FROM embeddings WHERE turn_id = ?2", params![new_id, id])?;
+            tx.execute("INSERT INTO attachments (turn_id, name, media_type, data, ts) SELECT ?1, name, media_type, data, ts FROM attachments WHERE turn_id = ?2 ORDER BY id", params![new_id, id])?;
+            new_ids.insert(*id, new_id);
+        }
+        let summaries: Vec<(i64, String, String)> = tx.prepare("SELECT through_id, content, ts FROM summaries WHERE session_id = ?1 ORDER BY id")?
+            .query_map(params![from], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?.filter_map(|r| r.ok()).collect();
+        for (through, content, ts) in summaries {
+            // a summary through a since-deleted turn runs through the newest copy before it
+            let Some((_, &through)) = new_ids.range(..=through).next_back() else { continue };
+            tx.execute("INSERT INTO summaries (session_id, through_id, content, ts) VALUES (?1, ?2, ?3, ?4)", params![to, through, content, ts])?;
+        }
+        tx.execute("INSERT OR REPLACE INTO settings (key, value) VALUES ('session', ?1)", params![name])?;
+        tx.commit()?;
+        self.flush()?;
+        Ok(ids.len())
+    }
+    pub fn session_switch(&self, name: &str) -> Result<()> {
+        if !self.session_exists(name)? { return Err(anyhow!("no session named '{}' (see `mindlink session list`)", name)); }
+        let was_saved = self.saved();
+        self.conn.execute("INSERT OR REPLACE INTO settings (key, value) VALUES ('session', ?1)", params![name])?;
+        self.append_jsonl(was_saved, || Ok(JsonlLine::Current { session: name.to_string() }))
+    }
+    /// Delete a session with all its turns; deleting the current one switches back to the default.
+    pub fn session_delete(&self, name: &str) -> Result<()> {
+        if name == DEFAULT_SESSION { return Err(anyhow!("the default session cannot be deleted (use memory-clear to empty it)")); }
+        if !self.session_exists(name)? { return Err(anyhow!("no session named '{}'", name)); }
+        self.conn.execute("DELETE FROM memory WHERE session_id = (SELECT id FROM sessions WHERE name = ?1)", params![name])?;
+        self.conn.execute("DELETE FROM summaries WHERE session_id = (SELECT id FROM sessions WHERE name = ?1)", params![name])?;
+        self.conn.execute("DELETE FROM sessions WHERE name = ?1", params![name])?;
+        self.flush()
+    }
+    /// Cached response for `key` if it is younger than `ttl_secs`.
+    pub fn cache_get(&self, key: &str, ttl_secs: i64) -> Result<Option<String>> {
+        let cutoff = (Utc::now() - chrono::Duration::seconds(ttl_secs)).to_rfc3339();
+        let mut stmt = self.conn.prepare("SELECT response FROM response_cache WHERE key = ?1 AND ts >= ?2")?;
+        let mut rows = stmt.query(params![key, cutoff])?;
+        Ok(match rows.next()? { Some(r) => Some(r.get(0)?), None => None })
+    }
+    pub fn cache_put(&self, key: &str, response: &str) -> Result<()> {
+        let (was_saved, ts) = (self.saved(), Utc::now().to_rfc3339());
+        self.conn.execute("INSERT OR REPLACE INTO response_cache (key, response, ts) VALUES (?1, ?2, ?3)", params![key, response, ts])?;
+        self.unkept(was_saved);
+        Ok(())
+    }
+}
+
+impl MemoryBackend for Memory {
+    fn open(path: &str) -> Result<Self> { Memory::open(path) }
+    fn append_meta(&self, role: &str, content: &str, meta: &TurnMeta) -> Result<i64> { Memory::append_meta(self, role, content, meta) }
+    fn last_turns(&self, limit: usize) -> Result<Vec<ChatTurn>> { Memory::last_turns(self, limit) }
+    fn last_turns_filtered(&self, limit: usize, filter: &TurnFilter) -> Result<Vec<ChatTurn>> { Memory::last_turns_filtered(self, limit, filter) }
+    fn turns_after(&self, id: i64) -> Result<Vec<ChatTurn>> { Memory::turns_after(self, id) }
+    fn turns_by_id(&self, ids: &[i64]) -> Result<Vec<ChatTurn>> { Memory::turns_by_id(self, ids) }
+    fn note_references(&self, ids: &[i64]) -> Result<()> { Memory::note_references(self, ids) }
+    fn references(&self, ids: &[i64]) -> Result<HashMap<i64, i64>> { Memory::references(self, ids) }
+    fn pinned_turns(&self) -> Result<Vec<ChatTurn>> { Memory::pinned_turns(self) }
+    fn turn(&self, id: i64) -> Result<ChatTurn> { Memory::turn(self, id) }
+    fn search(&self, query: &str, all_sessions: bool, limit: usize, mark: (&str, &str)) -> Result<Vec<SearchHit>> { Memory::search(self, query, all_sessions, limit, mark) }
+    fn other_session_turns(&self, text: &str, limit: usize) -> Result<Vec<(String, ChatTurn)>> { Memory::other_session_turns(self, text, limit) }
+    fn clear(&self) -> Result<()> { Memory::clear(self) }
+    fn has_exchange(&self, question: &str, answer: &str) -> Result<bool> { Memory::has_exchange(self, question, answer) }
+    fn prune(&self, policy: &Retention) -> Result<usize> { Memory::prune(self, policy) }
+    fn set_pinned(&self, id: i64, pinned: bool) -> Result<()> { Memory::set_pinned(self, id, pinned) }
+    fn tag(&self, id: i64, tag: &str) -> Result<()> { Memory::tag(self, id, tag) }
+    fn untag(&self, id: i64, tag: &str) -> Result<()> { Memory::untag(self, id, tag) }
+    fn set_content(&self, id: i64, content: &str) -> Result<()> { Memory::set_content(self, id, content) }
+    fn delete(&self, id: i64) -> Result<()> { Memory::delete(self, id) }
+    fn delete_range(&self, from: Option<i64>, to: Option<i64>) -> Result<usize> { Memory::delete_range(self, from, to) }
+    fn latest_summary(&self) -> Result<Option<Summary>> { Memory::latest_summary(self) }
+    fn summary_as_of(&self, session: &str, at: DateTime<Utc>) -> Result<Option<Summary>> { Memory::summary_as_of(self, session, at) }
+    fn put_summary(&self, through_id: i64, content: &str) -> Result<()> { Memory::put_summary(self, through_id, content) }
+    fn put_embedding(&self, turn_id: i64, model: &str, vector: &[f32]) -> Result<()> { Memory::put_embedding(self, turn_id, model, vector) }
+    fn embeddings(&self, model: &str) -> Result<Vec<(i64, Vec<f32>)>> { Memory::embeddings(self, model) }
+    fn cache_get(&self, key: &str, ttl_secs: i64) -> Result<Option<String>> { Memory::cache_get(self, key, ttl_secs) }
+    fn cache_put(&self, key: &str, response: &str) -> Result<()> { Memory::cache_put(self, key, response) }
+    fn facts(&self) -> Result<Vec<Fact>> { Memory::facts(self) }
+    fn add_fact(&self, content: &str) -> Result<i64> { Memory::add_fact(self, content) }
+    fn forget_fact(&self, id: i64) -> Result<()> { Memory::forget_fact(self, id) }
+    fn current_session(&self) -> Result<String> { Memory::current_session(self) }
+    fn system_prompt(&self) -> Result<Option<String>> { Memory::system_prompt(self) }
+    fn set_system_prompt(&self, prompt: Option<&str>) -> Result<()> { Memory::set_system_prompt(self, prompt) }
+    fn sessions(&self) -> Result<Vec<Session>> { Memory::sessions(self) }
+    fn latest_session(&self) -> Result<Option<String>> { Memory::latest_session(self) }
+    fn session_new(&self, name: &str) -> Result<()> { Memory::session_new(self, name) }
+    fn session_switch(&self, name: &str) -> Result<()> { Memory::session_switch(self, name) }
+    fn session_delete(&self, name: &str) -> Result<()> { Memory::session_delete(self, name) }
+    fn as_local(&self) -> Option<&Memory> { Some(self) }
+    fn as_local_mut(&mut self) -> Option<&mut Memory> { Some(self) }
+}
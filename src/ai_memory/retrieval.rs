@@ -0,0 +1,94 @@
+use super::ChatTurn;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// How many of a session's latest turns are scored; anything older is left to semantic recall.
+pub const POOL: usize = 200;
+
+/// Picks the turns worth sending when more are remembered than fit (`AI_RETRIEVAL=scored`):
+/// recent ones, ones that later prompts kept recalling, and pinned ones, instead of simply the
+/// newest.
+pub struct RetrievalPolicy {
+    /// Hours after which a turn's recency counts half.
+    pub half_life_hours: f64,
+    /// Weight of `ln(1 + references)` against recency, which is 1 for a brand-new turn.
+    pub reference_weight: f64,
+}
+
+impl Default for RetrievalPolicy {
+    fn default() -> Self {
+        Self {
+            half_life_hours: 24.0,
+            reference_weight: 0.5,
+        }
+    }
+}
+
+impl RetrievalPolicy {
+    /// Pinned turns score infinitely high: they are always sent.
+    pub fn score(&self, turn: &ChatTurn, references: i64, now: DateTime<Utc>) -> f64 {
+        if turn.pinned {
+            return f64::INFINITY;
+        }
+        let age_hours = (now - turn.ts).num_seconds().max(0) as f64 / 3600.0;
+        0.5f64.powf(age_hours / self.half_life_hours)
+            + self.reference_weight * (references.max(0) as f64).ln_1p()
+    }
+
+    /// The best-scoring `keep` of `turns` (oldest first), in their original order. A question
+    /// and its answer go together, scored by the better of the two; the latest exchange is
+    /// always kept, and pinned turns come on top of `keep`.
+    pub fn select(
+        &self,
+        turns: Vec<ChatTurn>,
+        references: &HashMap<i64, i64>,
+        keep: usize,
+        now: DateTime<Utc>,
+    ) -> Vec<ChatTurn> {
+        if turns.len() <= keep {
+            return turns;
+        }
+        let mut groups: Vec<Vec<ChatTurn>> = Vec::new();
+        for t in turns {
+            match groups.last_mut() {
+                Some(g) if t.role == "assistant" && g.len() == 1 && g[0].role == "user" => {
+                    g.push(t)
+                }
+                _ => groups.push(vec![t]),
+            }
+        }
+        let scores: Vec<f64> = groups
+            .iter()
+            .map(|g| {
+                g.iter()
+                    .map(|t| self.score(t, references.get(&t.id).copied().unwrap_or(0), now))
+                    .fold(f64::MIN, f64::max)
+            })
+            .collect();
+        let latest = groups.len() - 1;
+        let mut order: Vec<usize> = (0..groups.len()).collect();
+        // ties go to the newer exchange
+        order.sort_by(|&a, &b| {
+            (b == latest)
+                .cmp(&(a == latest))
+                .then(scores[b].total_cmp(&scores[a]))
+                .then(b.cmp(&a))
+        });
+        let mut chosen = vec![false; groups.len()];
+        let mut used = 0;
+        for i in order {
+            let cost = groups[i].iter().filter(|t| !t.pinned).count();
+            if i != latest && used + cost > keep {
+                continue;
+            }
+            chosen[i] = true;
+            used += cost;
+        }
+        groups
+            .into_iter()
+            .zip(chosen)
+            .filter(|(_, chosen)| *chosen)
+            .flat_map(|(g, _)| g)
+            .collect()
+    }
+}
@@ -0,0 +1,305 @@
+use crate::redact::Anonymizer;
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Bumped whenever the layout changes in a way older readers would misread.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Everything in a memory database, in a form that outlives it (`memory-export`).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Archive {
+    pub version: u32,
+    pub exported: DateTime<Utc>,
+    #[serde(default)]
+    pub facts: Vec<String>,
+    pub sessions: Vec<ArchivedSession>,
+    /// Turns deleted from the exporting database, so a merge deletes its copies instead of keeping them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted: Vec<DeletedTurn>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletedTurn {
+    pub uid: String,
+    pub deleted: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedSession {
+    pub name: String,
+    pub created: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    pub turns: Vec<ArchivedTurn>,
+}
+
+/// A turn without its row id, which only means something inside one database; `uid` is the same everywhere.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedTurn {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<String>,
+    pub role: String,
+    pub content: String,
+    pub ts: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<i64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Last edit of the content or pin, if any; the newer copy wins when archives are merged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ArchivedAttachment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedAttachment {
+    pub name: String,
+    pub media_type: String,
+    /// The file's bytes, base64-encoded.
+    pub data: String,
+}
+
+/// Run every text in the archive through `anonymizer`, session names and file names included, to
+/// share it in a bug report. Attachments that aren't text can't be checked, so they are dropped.
+pub fn anonymize(archive: &mut Archive, anonymizer: &mut Anonymizer) {
+    let b64 = base64::engine::general_purpose::STANDARD;
+    for fact in &mut archive.facts {
+        *fact = anonymizer.anonymize(fact);
+    }
+    for session in &mut archive.sessions {
+        session.name = anonymizer.anonymize(&session.name);
+        if let Some(prompt) = &mut session.system_prompt {
+            *prompt = anonymizer.anonymize(prompt);
+        }
+        for turn in &mut session.turns {
+            turn.content = anonymizer.anonymize(&turn.content);
+            turn.tags = turn.tags.iter().map(|t| anonymizer.anonymize(t)).collect();
+            turn.attachments.retain_mut(|a| {
+                let text = b64
+                    .decode(&a.data)
+                    .ok()
+                    .and_then(|data| String::from_utf8(data).ok());
+                match text {
+                    Some(text) if a.media_type.starts_with("text/") => {
+                        a.name = anonymizer.anonymize(&a.name);
+                        a.data = b64.encode(anonymizer.anonymize(&text));
+                        true
+                    }
+                    _ => false,
+                }
+            });
+        }
+    }
+}
+
+/// A readable transcript, one section per session.
+pub fn to_markdown(archive: &Archive) -> String {
+    let time = |ts: &DateTime<Utc>| ts.format("%Y-%m-%d %H:%M:%S UTC").to_string();
+    let mut out = format!(
+        "# Mindlink memory\n\nExported {}.\n",
+        time(&archive.exported)
+    );
+    if !archive.facts.is_empty() {
+        out.push_str("\n## Facts\n\n");
+        for fact in &archive.facts {
+            out.push_str(&format!("- {}\n", fact));
+        }
+    }
+    for session in &archive.sessions {
+        out.push_str(&format!(
+            "\n## Session: {}\n\nStarted {}, {} turns.\n",
+            session.name,
+            time(&session.created),
+            session.turns.len()
+        ));
+        if let Some(prompt) = &session.system_prompt {
+            out.push_str(&format!("\nSystem prompt: {}\n", prompt.trim_end()));
+        }
+        for turn in &session.turns {
+            let mut meta = vec![time(&turn.ts)];
+            if let Some(model) = &turn.model {
+                meta.push(model.clone());
+            }
+            if turn.pinned {
+                meta.push("pinned".into());
+            }
+            if !turn.tags.is_empty() {
+                meta.push(format!("tags: {}", turn.tags.join(", ")));
+            }
+            if !turn.attachments.is_empty() {
+                let names: Vec<&str> = turn.attachments.iter().map(|a| a.name.as_str()).collect();
+                meta.push(format!("attached: {}", names.join(", ")));
+            }
+            out.push_str(&format!(
+                "\n### {} ({})\n\n{}\n",
+                turn.role,
+                meta.join(", "),
+                turn.content.trim_end()
+            ));
+        }
+    }
+    out
+}
+
+/// A Mindlink export, or the `conversations.json` from a ChatGPT data export.
+pub fn load(text: &str) -> Result<Archive> {
+    let value: Value = serde_json::from_str(text).context("not valid JSON")?;
+    if value.is_array() {
+        let conversations: Vec<ChatGptConversation> = serde_json::from_value(value)
+            .context("not a ChatGPT conversations.json (expected a list of conversations)")?;
+        return Ok(from_chatgpt(conversations));
+    }
+    let archive: Archive =
+        serde_json::from_value(value).context("not a Mindlink export or ChatGPT export")?;
+    if archive.version > ARCHIVE_VERSION {
+        return Err(anyhow!(
+            "archive version {} is newer than this mindlink understands ({})",
+            archive.version,
+            ARCHIVE_VERSION
+        ));
+    }
+    Ok(archive)
+}
+
+#[derive(Deserialize)]
+struct ChatGptConversation {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    create_time: Option<f64>,
+    mapping: HashMap<String, ChatGptNode>,
+    current_node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptNode {
+    message: Option<ChatGptMessage>,
+    parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMessage {
+    author: ChatGptAuthor,
+    create_time: Option<f64>,
+    content: ChatGptContent,
+}
+
+#[derive(Deserialize)]
+struct ChatGptAuthor {
+    role: String,
+}
+
+#[derive(Deserialize)]
+struct ChatGptContent {
+    #[serde(default)]
+    parts: Vec<Value>,
+}
+
+fn epoch(secs: Option<f64>) -> Option<DateTime<Utc>> {
+    let secs = secs?;
+    DateTime::from_timestamp(secs.trunc() as i64, (secs.fract() * 1e9) as u32)
+}
+
+/// `Fix the build!` with id `6650d1c4-...` becomes `chatgpt-fix-the-build-6650d1c4`, so
+/// conversations sharing a title stay apart and importing twice lands in the same session.
+fn session_name(conv: &ChatGptConversation) -> String {
+    let title = conv.title.as_deref().unwrap_or("untitled");
+    let mut slug = String::new();
+    for c in title.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.chars().count() >= 40 {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    let slug = if slug.is_empty() { "untitled" } else { slug };
+    match conv.id.as_deref() {
+        Some(id) => format!(
+            "chatgpt-{}-{}",
+            slug,
+            id.chars().take(8).collect::<String>()
+        ),
+        None => format!("chatgpt-{}", slug),
+    }
+}
+
+fn from_chatgpt(conversations: Vec<ChatGptConversation>) -> Archive {
+    let sessions = conversations
+        .into_iter()
+        .map(|conv| {
+            let created = epoch(conv.create_time).unwrap_or_else(Utc::now);
+            // the mapping is a tree of edits and regenerations; the shown thread is the path
+            // from the current node back to the root
+            let mut turns = Vec::new();
+            let mut node = conv.current_node.as_deref();
+            while let Some((id, n)) = node.and_then(|id| conv.mapping.get(id).map(|n| (id, n))) {
+                if let Some(msg) = &n.message {
+                    let text = msg
+                        .content
+                        .parts
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    let role = msg.author.role.as_str();
+                    if (role == "user" || role == "assistant") && !text.trim().is_empty() {
+                        turns.push(ArchivedTurn {
+                            uid: Some(format!("chatgpt-{}", id)),
+                            role: role.to_string(),
+                            content: text,
+                            ts: epoch(msg.create_time).unwrap_or(created),
+                            prompt_tokens: None,
+                            completion_tokens: None,
+                            fingerprint: None,
+                            provider: None,
+                            model: None,
+                            latency_ms: None,
+                            pinned: false,
+                            updated: None,
+                            tags: Vec::new(),
+                            attachments: Vec::new(),
+                        });
+                    }
+                }
+                node = n.parent.as_deref();
+            }
+            turns.reverse();
+            ArchivedSession {
+                name: session_name(&conv),
+                created,
+                system_prompt: None,
+                turns,
+            }
+        })
+        .filter(|s| !s.turns.is_empty())
+        .collect();
+    Archive {
+        version: ARCHIVE_VERSION,
+        exported: Utc::now(),
+        facts: Vec::new(),
+        sessions,
+        deleted: Vec::new(),
+    }
+}
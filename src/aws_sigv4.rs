@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::env;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    /// Resolve credentials the way the AWS CLI does for the common cases:
+    /// environment variables first, then the shared credentials file for `AWS_PROFILE`.
+    pub fn from_env() -> Result<Self> {
+        if let (Ok(id), Ok(secret)) = (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(Self {
+                access_key_id: id,
+                secret_access_key: secret,
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            });
+        }
+        Self::from_shared_file()
+    }
+
+    fn from_shared_file() -> Result<Self> {
+        let path = match env::var("AWS_SHARED_CREDENTIALS_FILE") {
+            Ok(p) => p.into(),
+            Err(_) => dirs::home_dir()
+                .ok_or_else(|| anyhow!("AWS credentials not found"))?
+                .join(".aws")
+                .join("credentials"),
+        };
+        let profile = env::var("AWS_PROFILE").unwrap_or_else(|_| "default".into());
+        let text = std::fs::read_to_string(&path)
+            .map_err(|_| anyhow!("AWS credentials not found (env or {})", path.display()))?;
+
+        let mut in_profile = false;
+        let (mut id, mut secret, mut token) = (None, None, None);
+        for line in text.lines() {
+            let line = line.trim();
+            if line.starts_with('[') && line.ends_with(']') {
+                in_profile = line[1..line.len() - 1].trim() == profile;
+                continue;
+            }
+            if !in_profile {
+                continue;
+            }
+            if let Some((k, v)) = line.split_once('=') {
+                let v = v.trim().to_string();
+                match k.trim() {
+                    "aws_access_key_id" => id = Some(v),
+                    "aws_secret_access_key" => secret = Some(v),
+                    "aws_session_token" => token = Some(v),
+                    _ => {}
+                }
+            }
+        }
+        match (id, secret) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(Self {
+                access_key_id,
+                secret_access_key,
+                session_token: token,
+            }),
+            _ => Err(anyhow!("AWS profile '{}' has no credentials", profile)),
+        }
+    }
+}
+
+pub fn region() -> String {
+    env::var("AWS_REGION")
+        .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+        .unwrap_or_else(|_| "us-east-1".into())
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters.
+pub fn uri_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+pub struct Signer {
+    pub creds: AwsCredentials,
+    pub service: String,
+    pub region: String,
+}
+
+impl Signer {
+    /// Sign a request with AWS Signature Version 4 and return the headers to attach.
+    ///
+    /// `path` must already be percent-encoded as it will be sent; it is signed
+    /// encoded a second time, as every service except S3 expects.
+    /// `headers` are extra headers to include in the signature.
+    pub fn sign(
+        &self,
+        method: &str,
+        host: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        payload: &[u8],
+    ) -> Vec<(String, String)> {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let mut signed: Vec<(String, String)> = headers
+            .iter()
+            .map(|(k, v)| (k.to_ascii_lowercase(), v.trim().to_string()))
+            .collect();
+        signed.push(("host".into(), host.into()));
+        signed.push(("x-amz-date".into(), amz_date.clone()));
+        if let Some(token) = &self.creds.session_token {
+            signed.push(("x-amz-security-token".into(), token.clone()));
+        }
+        signed.sort();
+
+        let canonical_uri = path
+            .split('/')
+            .map(uri_encode)
+            .collect::<Vec<_>>()
+            .join("/");
+        let canonical_headers: String = signed
+            .iter()
+            .map(|(k, v)| format!("{}:{}\n", k, v))
+            .collect();
+        let signed_headers = signed
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n\n{}\n{}\n{}",
+            method,
+            canonical_uri,
+            canonical_headers,
+            signed_headers,
+            sha256_hex(payload)
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let k_date = hmac(
+            format!("AWS4{}", self.creds.secret_access_key).as_bytes(),
+            &date,
+        );
+        let k_region = hmac(&k_date, &self.region);
+        let k_service = hmac(&k_region, &self.service);
+        let k_signing = hmac(&k_service, "aws4_request");
+        let signature = hex::encode(hmac(&k_signing, &string_to_sign));
+
+        let mut out = vec![
+            (
+                "authorization".to_string(),
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.creds.access_key_id, scope, signed_headers, signature
+                ),
+            ),
+            ("x-amz-date".to_string(), amz_date),
+        ];
+        if let Some(token) = &self.creds.session_token {
+            out.push(("x-amz-security-token".into(), token.clone()));
+        }
+        out
+    }
+}
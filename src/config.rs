@@ -0,0 +1,602 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// What a setting's value has to look like, for `config set`.
+#[derive(Clone, Copy)]
+enum Kind {
+    Text,
+    Integer,
+    Number,
+    Flag,
+    /// `auto` or a number of tokens.
+    Tokens,
+    OneOf(&'static [&'static str]),
+}
+
+pub const PROVIDERS: &[&str] = &[
+    "openai",
+    "azure",
+    "openrouter",
+    "mistral",
+    "groq",
+    "anthropic",
+    "ollama",
+    "bedrock",
+    "local",
+];
+
+/// Every variable mindlink reads, so a misspelt key in a file (or given to `config set`) doesn't
+/// go unnoticed. Add new settings here too.
+const SETTINGS: &[(&str, Kind)] = &[
+    ("AI_PROFILE", Kind::Text),
+    ("AI_PERSONA", Kind::Text),
+    ("AI_PROVIDER", Kind::OneOf(PROVIDERS)),
+    ("AI_PROVIDERS", Kind::Text),
+    ("AI_MODEL", Kind::Text),
+    ("AI_TEMPERATURE", Kind::Number),
+    ("AI_TOP_P", Kind::Number),
+    ("AI_MAX_TOKENS", Kind::Integer),
+    ("AI_SEED", Kind::Integer),
+    (
+        "AI_REASONING_EFFORT",
+        Kind::OneOf(&["minimal", "low", "medium", "high"]),
+    ),
+    ("AI_SHOW_REASONING", Kind::Flag),
+    ("AI_MARKDOWN", Kind::OneOf(&["auto", "always", "never"])),
+    ("AI_CODE_THEME", Kind::Text),
+    ("AI_PROMPT_COLOR", Kind::Text),
+    ("AI_ASSISTANT_COLOR", Kind::Text),
+    ("AI_ERROR_COLOR", Kind::Text),
+    ("AI_QUIET", Kind::Flag),
+    ("AI_EDIT_MODE", Kind::OneOf(&["emacs", "vi"])),
+    ("AI_HISTORY_SIZE", Kind::Integer),
+    ("AI_SYSTEM_PROMPT", Kind::Text),
+    ("AI_SCHEMA", Kind::Text),
+    ("AI_FILE_MAX_KB", Kind::Integer),
+    ("AI_FILES_MAX_KB", Kind::Integer),
+    ("AI_TOOLS", Kind::Flag),
+    ("AI_PROXY", Kind::Text),
+    ("AI_KEYCHAIN", Kind::Flag),
+    ("AI_PAGER", Kind::Flag),
+    ("AI_WIDTH", Kind::Integer),
+    ("AI_CA_CERT", Kind::Text),
+    ("AI_MAX_RETRIES", Kind::Integer),
+    ("AI_BACKOFF_MS", Kind::Integer),
+    ("AI_BACKOFF_CAP_MS", Kind::Integer),
+    ("AI_BACKOFF_JITTER", Kind::OneOf(&["full", "none"])),
+    ("AI_CACHE", Kind::Flag),
+    ("AI_CACHE_TTL", Kind::Integer),
+    ("AI_PROMPT_CACHE", Kind::Flag),
+    (
+        "AI_MEMORY_BACKEND",
+        Kind::OneOf(&["sqlite", "jsonl", "postgres"]),
+    ),
+    ("AI_MEMORY_DIR", Kind::Text),
+    ("AI_MEMORY_URL", Kind::Text),
+    ("DATABASE_URL", Kind::Text),
+    ("AI_MEMORY_USER", Kind::Text),
+    ("AI_MEMORY_TURNS", Kind::Integer),
+    ("AI_GLOBAL_MEMORY", Kind::Flag),
+    ("AI_GLOBAL_TURNS", Kind::Integer),
+    ("AI_HISTORY_BUDGET", Kind::Integer),
+    ("AI_CONTEXT_TOKENS", Kind::Tokens),
+    ("AI_MEMORY_DEDUP", Kind::Flag),
+    ("AI_MEMORY_MAX_TURNS", Kind::Integer),
+    ("AI_MEMORY_MAX_AGE_DAYS", Kind::Integer),
+    ("AI_MEMORY_MAX_SIZE_MB", Kind::Integer),
+    ("AI_SEMANTIC_TURNS", Kind::Integer),
+    ("AI_EMBED_PROVIDER", Kind::OneOf(PROVIDERS)),
+    ("AI_EMBED_MODEL", Kind::Text),
+    ("AI_CROSS_SESSION_TURNS", Kind::Integer),
+    ("AI_RETRIEVAL", Kind::OneOf(&["recent", "scored"])),
+    ("AI_RETRIEVAL_HALF_LIFE_HOURS", Kind::Number),
+    ("AI_TRANSCRIBE_MODEL", Kind::Text),
+    ("AI_REDACT_PATTERNS", Kind::Text),
+    ("AI_ANONYMIZE_PATTERNS", Kind::Text),
+    ("AI_ANONYMIZE_NAMES", Kind::Text),
+    ("AI_SYNC_REMOTE", Kind::Text),
+    ("AI_SYNC_USER", Kind::Text),
+    ("AI_SYNC_PASSWORD", Kind::Text),
+    ("OPENAI_API_KEY", Kind::Text),
+    ("OPENAI_BASE_URL", Kind::Text),
+    ("OPENAI_ORG_ID", Kind::Text),
+    ("OPENAI_PROJECT_ID", Kind::Text),
+    ("AZURE_OPENAI_API_KEY", Kind::Text),
+    ("AZURE_OPENAI_ENDPOINT", Kind::Text),
+    ("AZURE_OPENAI_DEPLOYMENT", Kind::Text),
+    ("AZURE_OPENAI_API_VERSION", Kind::Text),
+    ("ANTHROPIC_API_KEY", Kind::Text),
+    ("OPENROUTER_API_KEY", Kind::Text),
+    ("OPENROUTER_REFERER", Kind::Text),
+    ("OPENROUTER_TITLE", Kind::Text),
+    ("MISTRAL_API_KEY", Kind::Text),
+    ("GROQ_API_KEY", Kind::Text),
+    ("OLLAMA_HOST", Kind::Text),
+    ("AWS_ACCESS_KEY_ID", Kind::Text),
+    ("AWS_SECRET_ACCESS_KEY", Kind::Text),
+    ("AWS_SESSION_TOKEN", Kind::Text),
+    ("AWS_REGION", Kind::Text),
+    ("AWS_DEFAULT_REGION", Kind::Text),
+    ("AWS_PROFILE", Kind::Text),
+    ("AWS_SHARED_CREDENTIALS_FILE", Kind::Text),
+    ("AWS_ENDPOINT_URL", Kind::Text),
+    ("LOCAL_TOKENIZER", Kind::Text),
+];
+
+fn kind(var: &str) -> Option<Kind> {
+    SETTINGS.iter().find(|(v, _)| *v == var).map(|(_, k)| *k)
+}
+
+/// Keys, values and the like that shouldn't be printed by `config list`.
+pub fn is_secret(var: &str) -> bool {
+    ["_KEY", "_TOKEN", "_PASSWORD"]
+        .iter()
+        .any(|end| var.ends_with(end))
+        || var.contains("SECRET")
+}
+
+/// `~/.config/mindlink` (or under `$XDG_CONFIG_HOME`); `None` without a home directory.
+pub fn global_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| dirs::home_dir().map(|h| h.join(".config")))
+        .map(|c| c.join("mindlink"))
+}
+
+/// `kind` (e.g. `templates`) in `global_dir`, where things the user names are kept one per file.
+pub fn named_dir(kind: &str) -> Result<PathBuf> {
+    global_dir()
+        .map(|d| d.join(kind))
+        .ok_or_else(|| anyhow!("no home directory for the {}", kind))
+}
+
+/// `<name>.<extension>` in `named_dir(kind)`.
+pub fn named_file(kind: &str, name: &str, extension: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "{:?} can't be a name: use letters, digits, - and _",
+            name
+        ));
+    }
+    Ok(named_dir(kind)?.join(format!("{}.{}", name, extension)))
+}
+
+/// The config files in the order they apply: `config.toml` in `global_dir`, then
+/// `./.mindlink/config.toml`, whose settings win.
+pub fn paths() -> Vec<PathBuf> {
+    let project = env::current_dir()
+        .unwrap_or_else(|_| PathBuf::from("."))
+        .join(".mindlink");
+    global_dir()
+        .into_iter()
+        .chain([project])
+        .map(|dir| dir.join("config.toml"))
+        .collect()
+}
+
+/// Set the variables of the dotenv files that aren't set already: `file` (from `--env-file`),
+/// which has to exist, then `.env` in the current directory or the nearest one above it, then
+/// `./.mindlink/.env` and `.env` in `global_dir`, so a key needn't be copied into every
+/// repository. The first file to set a variable wins. Returns the files that were read.
+pub fn load_env(file: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let mut read = Vec::new();
+    if let Some(file) = file {
+        // dotenvy's own message already names the cause, which `Caused by` would repeat
+        dotenvy::from_path(file).map_err(|e| anyhow!("can't read {}: {}", file.display(), e))?;
+        read.push(file.to_path_buf());
+    }
+    let project = env::current_dir()
+        .unwrap_or_else(|_| PathBuf::from("."))
+        .join(".mindlink");
+    let found = dotenvy::dotenv().map_err(|e| (PathBuf::from(".env"), e));
+    let others = [Some(project), global_dir()]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(".env"))
+        .map(|path| {
+            dotenvy::from_path(&path)
+                .map(|_| path.clone())
+                .map_err(|e| (path, e))
+        });
+    for result in std::iter::once(found).chain(others) {
+        match result {
+            Ok(path) => read.push(path),
+            Err((_, e)) if e.not_found() => {}
+            Err((path, e)) => note!("({} not read: {})", path.display(), e),
+        }
+    }
+    Ok(read)
+}
+
+/// A setting as written in a file, under its environment variable's name.
+struct Entry {
+    key: String,
+    value: String,
+    file: PathBuf,
+}
+
+/// What the config files set, as environment variables: the plain settings, and those of each
+/// `[profiles.<name>]` table.
+#[derive(Default)]
+struct Settings {
+    vars: BTreeMap<String, Entry>,
+    profiles: BTreeMap<String, BTreeMap<String, Entry>>,
+}
+
+impl Settings {
+    /// Both files merged, and the ones that exist.
+    fn read() -> Result<(Self, Vec<PathBuf>)> {
+        let mut settings = Self::default();
+        let mut read = Vec::new();
+        for path in paths() {
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+            };
+            let file = parse(&text, &path).with_context(|| format!("in {}", path.display()))?;
+            settings.vars.extend(file.vars);
+            for (name, vars) in file.profiles {
+                settings.profiles.entry(name).or_default().extend(vars);
+            }
+            read.push(path);
+        }
+        Ok((settings, read))
+    }
+
+    /// The profile asked for by `flag`, else by `AI_PROFILE`, else by the files themselves.
+    fn profile(&self, flag: Option<&str>) -> Result<Option<(String, &BTreeMap<String, Entry>)>> {
+        let name = flag
+            .map(str::to_string)
+            .or_else(|| env::var("AI_PROFILE").ok())
+            .or_else(|| self.vars.get("AI_PROFILE").map(|e| e.value.clone()))
+            .filter(|p| !p.trim().is_empty());
+        let Some(name) = name else { return Ok(None) };
+        let vars = self.profiles.get(&name).ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            anyhow!(
+                "no profile '{}' in the config files (profiles: {})",
+                name,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )
+        })?;
+        Ok(Some((name, vars)))
+    }
+}
+
+/// The names of the `[profiles.<name>]` tables in the config files.
+pub fn profiles() -> Result<Vec<String>> {
+    Ok(Settings::read()?.0.profiles.into_keys().collect())
+}
+
+/// Set every variable the config files mention that isn't set already. Settings keep being read
+/// from the environment, so an exported variable or a `.env` entry still beats the files, and the
+/// flags, set after this, beat everything. A profile (`profile`, or else `AI_PROFILE`, which the
+/// files can set too) was asked for by name, so its settings replace whatever is set. A key kept
+/// in the system keychain is only looked up once its provider is used. Returns the files that
+/// were read.
+pub fn load(profile: Option<&str>) -> Result<Vec<PathBuf>> {
+    let (settings, read) = Settings::read()?;
+    let unknown = settings
+        .vars
+        .values()
+        .chain(settings.profiles.values().flat_map(|p| p.values()))
+        .filter(|e| kind(&var_name(&e.key).unwrap_or_default()).is_none());
+    for e in unknown {
+        note!(
+            "(config: {} in {} isn't a setting mindlink knows)",
+            e.key,
+            e.file.display()
+        );
+    }
+    let profile = settings.profile(profile)?;
+    for (var, e) in &settings.vars {
+        if env::var_os(var).is_none() {
+            set_var(var, &e.value);
+        }
+    }
+    if let Some((name, vars)) = profile {
+        for (var, e) in vars {
+            set_var(var, &e.value);
+        }
+        env::set_var("AI_PROFILE", name);
+    }
+    Ok(read)
+}
+
+/// A value of the form `${NAME}` is a reference to another variable, so a key can stay wherever
+/// it is kept already; when that variable isn't set, neither is this one.
+fn set_var(var: &str, value: &str) {
+    let value = match value.strip_prefix("${").and_then(|v| v.strip_suffix('}')) {
+        Some(reference) => match env::var(reference) {
+            Ok(v) => v,
+            Err(_) => return,
+        },
+        None => value.to_string(),
+    };
+    env::set_var(var, value);
+}
+
+/// A setting as `config list` and `config get` show it: the value in use and where it comes from.
+pub struct Setting {
+    pub key: String,
+    pub var: String,
+    pub value: String,
+    pub source: String,
+}
+
+/// Every setting the config files make (with `profile` applied), replaced by the environment
+/// where that wins, as the files are applied on startup.
+pub fn list(profile: Option<&str>) -> Result<Vec<Setting>> {
+    let (settings, _) = Settings::read()?;
+    let mut shown = BTreeMap::new();
+    for (var, e) in &settings.vars {
+        let (value, source) = match env::var(var) {
+            Ok(value) => (value, "environment".to_string()),
+            Err(_) => (e.value.clone(), e.file.display().to_string()),
+        };
+        let key = e.key.clone();
+        let var = var.clone();
+        shown.insert(
+            var.clone(),
+            Setting {
+                key,
+                var,
+                value,
+                source,
+            },
+        );
+    }
+    if let Some((name, vars)) = settings.profile(profile)? {
+        for (var, e) in vars {
+            let setting = Setting {
+                key: e.key.clone(),
+                var: var.clone(),
+                value: e.value.clone(),
+                source: format!("profile {}, {}", name, e.file.display()),
+            };
+            shown.insert(var.clone(), setting);
+        }
+    }
+    Ok(shown.into_values().collect())
+}
+
+/// The value in use for `key` (`model`, `openai.base_url`...), from the files or the environment.
+pub fn get(key: &str, profile: Option<&str>) -> Result<Setting> {
+    let var = var_name(key)?;
+    if let Some(setting) = list(profile)?.into_iter().find(|s| s.var == var) {
+        return Ok(setting);
+    }
+    match env::var(&var) {
+        Ok(value) => Ok(Setting {
+            key: key.to_string(),
+            var,
+            value,
+            source: "environment".to_string(),
+        }),
+        Err(_) => Err(anyhow!("{} ({}) is not set", key, var)),
+    }
+}
+
+/// Write `key = value` to the global config file, or the project's, under `[profiles.<profile>]`
+/// when given; comments and the rest of the file stay as they were. The value has to suit the
+/// setting: a number for `memory_turns`, a known provider for `provider`, and so on. A file that
+/// gets a key or a password is made readable by its owner only. Returns the file written.
+pub fn set(key: &str, value: &str, project: bool, profile: Option<&str>) -> Result<PathBuf> {
+    let var = var_name(key)?;
+    let kind = kind(&var).ok_or_else(|| {
+        anyhow!(
+            "{} ({}) isn't a setting mindlink knows (see the README)",
+            key,
+            var
+        )
+    })?;
+    let value = typed(key, kind, value)?;
+    let mut path: Vec<String> = key.split('.').map(str::to_string).collect();
+    if let (Some(profile), false) = (profile, key.starts_with("profiles.")) {
+        path.splice(0..0, ["profiles".to_string(), profile.to_string()]);
+    }
+    // the project's file always comes last; the global one is missing without a home directory
+    let files = paths();
+    let file = if project {
+        files.last()
+    } else {
+        files.first().filter(|_| files.len() > 1)
+    }
+    .cloned()
+    .ok_or_else(|| anyhow!("no home directory for the config file; use --project"))?;
+    let text = match std::fs::read_to_string(&file) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", file.display())),
+    };
+    let mut doc: toml_edit::DocumentMut = text
+        .parse()
+        .with_context(|| format!("in {}", file.display()))?;
+    let (leaf, tables) = path.split_last().expect("a key has at least one part");
+    let mut table = doc.as_table_mut();
+    for name in tables {
+        let item = table.entry(name).or_insert_with(|| {
+            let mut t = toml_edit::Table::new();
+            t.set_implicit(true);
+            toml_edit::Item::Table(t)
+        });
+        table = item
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("{} in {} is a value, not a table", name, file.display()))?;
+    }
+    table.insert(leaf, toml_edit::value(value));
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // before the secret is in it
+    #[cfg(unix)]
+    if is_secret(&var) {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(&file)
+            .and_then(|_| std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o600)))
+            .with_context(|| format!("making {} private", file.display()))?;
+    }
+    std::fs::write(&file, doc.to_string())
+        .with_context(|| format!("writing {}", file.display()))?;
+    Ok(file)
+}
+
+/// Values in the config files that don't suit their setting, such as `memory_turns = "lots"`,
+/// each with its file. Keys mindlink doesn't know are left to `load` to point out.
+pub fn invalid() -> Result<Vec<String>> {
+    let (settings, _) = Settings::read()?;
+    let entries = settings
+        .vars
+        .values()
+        .chain(settings.profiles.values().flat_map(|p| p.values()));
+    let mut problems = Vec::new();
+    for e in entries {
+        let Some(kind) = var_name(&e.key).ok().and_then(|v| kind(&v)) else {
+            continue;
+        };
+        if let Err(err) = typed(&e.key, kind, &e.value) {
+            problems.push(format!("{} in {}", err, e.file.display()));
+        }
+    }
+    Ok(problems)
+}
+
+/// The key that sets `var` in a config file: `AI_MODEL` is `model` and `OPENAI_API_KEY` is
+/// `openai.api_key`.
+pub fn key_name(var: &str) -> String {
+    let var = var.to_ascii_lowercase();
+    match var.strip_prefix("ai_") {
+        Some(name) => name.to_string(),
+        None => var.replacen('_', ".", 1),
+    }
+}
+
+/// `model` is `AI_MODEL`, `openai.base_url` is `OPENAI_BASE_URL`, and `profiles.work.model` is
+/// `AI_MODEL` too, as written in a file.
+fn var_name(key: &str) -> Result<String> {
+    let parts: Vec<&str> = key.split('.').collect();
+    let parts = match parts.as_slice() {
+        ["profiles", _, rest @ ..] => rest,
+        parts => parts,
+    };
+    match parts {
+        [name] if !name.is_empty() => Ok(format!("AI_{}", name).to_uppercase()),
+        [table, name] if !table.is_empty() && !name.is_empty() => {
+            Ok(format!("{}_{}", table, name).to_uppercase())
+        }
+        _ => Err(anyhow!(
+            "{:?} is not a setting: use a name like model or a table and a name like openai.base_url",
+            key
+        )),
+    }
+}
+
+fn typed(key: &str, kind: Kind, value: &str) -> Result<toml_edit::Value> {
+    if value.starts_with("${") && value.ends_with('}') {
+        return Ok(value.into());
+    }
+    let bad = |takes: &str| anyhow!("{} takes {}, not {:?}", key, takes, value);
+    Ok(match kind {
+        Kind::Text => value.into(),
+        Kind::Integer => value
+            .parse::<i64>()
+            .map_err(|_| bad("a whole number"))?
+            .into(),
+        Kind::Number => value.parse::<f64>().map_err(|_| bad("a number"))?.into(),
+        Kind::Flag => match value {
+            "1" | "true" => true.into(),
+            "0" | "false" => false.into(),
+            _ => return Err(bad("true or false")),
+        },
+        Kind::Tokens if value == "auto" => value.into(),
+        Kind::Tokens => value
+            .parse::<i64>()
+            .map_err(|_| bad("auto or a number of tokens"))?
+            .into(),
+        Kind::OneOf(choices) if choices.contains(&value) => value.into(),
+        Kind::OneOf(choices) => return Err(bad(&format!("one of {}", choices.join(", ")))),
+    })
+}
+
+/// Keys are the variable names in lower case: `provider = "anthropic"` is `AI_PROVIDER` and
+/// `memory_turns = 20` is `AI_MEMORY_TURNS`. A table supplies the prefix instead of `AI_`, for
+/// the variables that belong to a provider: `[openai] api_key = "..."` is `OPENAI_API_KEY`.
+/// `[profiles.work]` holds settings of the same form.
+fn parse(text: &str, file: &Path) -> Result<Settings> {
+    let mut table: toml::Table = text.parse()?;
+    let mut settings = Settings::default();
+    if let Some(profiles) = table.remove("profiles") {
+        let toml::Value::Table(profiles) = profiles else {
+            return Err(anyhow!(
+                "profiles must be a table of [profiles.<name>] tables"
+            ));
+        };
+        for (name, profile) in profiles {
+            let toml::Value::Table(profile) = profile else {
+                return Err(anyhow!("profiles.{} must be a table", name));
+            };
+            let prefix = format!("profiles.{}.", name);
+            let vars =
+                entries(profile, &prefix, file).with_context(|| format!("in profile {}", name))?;
+            settings.profiles.insert(name, vars);
+        }
+    }
+    settings.vars = entries(table, "", file)?;
+    Ok(settings)
+}
+
+fn entries(table: toml::Table, prefix: &str, file: &Path) -> Result<BTreeMap<String, Entry>> {
+    let mut vars = BTreeMap::new();
+    let mut add = |key: String, value: toml::Value| -> Result<()> {
+        let value = scalar(&key, value)?;
+        let key = format!("{}{}", prefix, key);
+        let entry = Entry {
+            value,
+            file: file.to_path_buf(),
+            key: key.clone(),
+        };
+        vars.insert(var_name(&key)?, entry);
+        Ok(())
+    };
+    for (key, value) in table {
+        match value {
+            toml::Value::Table(section) => {
+                for (name, value) in section {
+                    add(format!("{}.{}", key, name), value)?;
+                }
+            }
+            value => add(key, value)?,
+        }
+    }
+    Ok(vars)
+}
+
+fn scalar(key: &str, value: toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(x) => Ok(x.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        other => Err(anyhow!(
+            "{}: a setting is a string, number or boolean, as its environment variable would be, not a TOML {}",
+            key,
+            other.type_str()
+        )),
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::{Certificate, Client, Proxy};
+use std::env;
+
+/// HTTP client for one provider. `AI_PROXY_<PROVIDER>` (e.g. `AI_PROXY_OLLAMA`) overrides
+/// `AI_PROXY`; `none` connects directly. Without either, reqwest still honors
+/// `HTTPS_PROXY`/`HTTP_PROXY`/`NO_PROXY`.
+pub fn client(provider: &str) -> Result<Client> {
+    let mut builder = Client::builder();
+    let proxy = env::var(format!("AI_PROXY_{}", provider.to_ascii_uppercase()))
+        .or_else(|_| env::var("AI_PROXY"))
+        .ok()
+        .filter(|p| !p.trim().is_empty());
+    match proxy.as_deref().map(str::trim) {
+        None => {}
+        Some("none") => builder = builder.no_proxy(),
+        // http://, https://, socks5:// and socks5h:// (DNS through the proxy)
+        Some(url) => {
+            let proxy = Proxy::all(url).with_context(|| format!("invalid proxy URL {}", url))?;
+            builder = builder.proxy(proxy);
+        }
+    }
+    // extra roots for TLS-inspecting proxies that re-sign traffic with their own CA
+    if let Ok(path) = env::var("AI_CA_CERT") {
+        let pem = std::fs::read(&path).with_context(|| format!("reading {}", path))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("{} is not a PEM certificate bundle", path))?;
+        if certs.is_empty() {
+            return Err(anyhow!("{} contains no certificates", path));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    Ok(builder.build()?)
+}
@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+// Set while an answer is streaming; Ctrl-C then cancels it instead of exiting.
+static STREAMING: AtomicBool = AtomicBool::new(false);
+// Set while the pager runs; Ctrl-C is then the pager's alone.
+static PAGING: AtomicBool = AtomicBool::new(false);
+
+fn cancel() -> &'static Notify {
+    static CANCEL: OnceLock<Notify> = OnceLock::new();
+    CANCEL.get_or_init(Notify::new)
+}
+
+fn quit() -> &'static Notify {
+    static QUIT: OnceLock<Notify> = OnceLock::new();
+    QUIT.get_or_init(Notify::new)
+}
+
+/// Take over Ctrl-C: abort the in-flight stream if there is one, ignore it while the pager runs,
+/// otherwise ask `main` to return, so memory is saved on the way out. Should it be stuck in a
+/// blocking read, a second Ctrl-C or a second's wait exits like the default handler.
+pub fn install() {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if PAGING.load(Ordering::SeqCst) {
+                continue;
+            }
+            if STREAMING.load(Ordering::SeqCst) {
+                cancel().notify_waiters();
+            } else {
+                quit().notify_one();
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                }
+                std::process::exit(130);
+            }
+        }
+    });
+}
+
+/// Resolves when Ctrl-C is pressed with no stream or pager running.
+pub async fn quitting() {
+    quit().notified().await
+}
+
+/// Resolves when Ctrl-C is pressed during the current stream.
+pub async fn cancelled() {
+    cancel().notified().await
+}
+
+/// Cancel the current stream as Ctrl-C does, for the TUI, where Ctrl-C is a key press.
+pub fn cancel_stream() {
+    cancel().notify_waiters();
+}
+
+/// Marks a stream as in flight for as long as it is held.
+pub struct Streaming;
+
+impl Streaming {
+    pub fn begin() -> Self {
+        STREAMING.store(true, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for Streaming {
+    fn drop(&mut self) {
+        STREAMING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Marks the pager as running for as long as it is held.
+pub struct Paging;
+
+impl Paging {
+    pub fn begin() -> Self {
+        PAGING.store(true, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for Paging {
+    fn drop(&mut self) {
+        PAGING.store(false, Ordering::SeqCst);
+    }
+}
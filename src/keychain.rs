@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+use std::env;
+use std::sync::Mutex;
+
+/// What the entries are filed under; each one is named after its provider.
+const SERVICE: &str = "mindlink";
+
+/// The variable holding the API key of each provider that takes one.
+pub const KEYS: &[(&str, &str)] = &[
+    ("openai", "OPENAI_API_KEY"),
+    ("azure", "AZURE_OPENAI_API_KEY"),
+    ("openrouter", "OPENROUTER_API_KEY"),
+    ("mistral", "MISTRAL_API_KEY"),
+    ("groq", "GROQ_API_KEY"),
+    ("anthropic", "ANTHROPIC_API_KEY"),
+];
+
+/// The providers a key can be kept for, for `auth`'s arguments.
+pub fn providers() -> impl Iterator<Item = &'static str> {
+    KEYS.iter().map(|(p, _)| *p)
+}
+
+/// The key variable of `provider`.
+pub fn var(provider: &str) -> Result<&'static str> {
+    KEYS.iter()
+        .find(|(p, _)| *p == provider)
+        .map(|(_, var)| *var)
+        .ok_or_else(|| anyhow!("{} takes no API key", provider))
+}
+
+/// Whether `AI_KEYCHAIN` leaves the keychain to be used; it is unless set to false.
+pub fn enabled() -> bool {
+    !matches!(env::var("AI_KEYCHAIN").as_deref(), Ok("0") | Ok("false"))
+}
+
+fn entry(provider: &str) -> Result<keyring::Entry> {
+    var(provider)?;
+    Ok(keyring::Entry::new(SERVICE, provider)?)
+}
+
+fn unavailable(e: keyring::Error) -> anyhow::Error {
+    match e {
+        keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_) => {
+            anyhow!("no keychain to use here ({})", e)
+        }
+        e => e.into(),
+    }
+}
+
+/// The key kept for `provider`, if there is one.
+pub fn get(provider: &str) -> Result<Option<String>> {
+    match entry(provider)?.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(unavailable(e)),
+    }
+}
+
+/// Keep `key` for `provider`, replacing the one kept before.
+pub fn set(provider: &str, key: &str) -> Result<()> {
+    entry(provider)?.set_password(key).map_err(unavailable)
+}
+
+/// Delete the key kept for `provider`; false if there was none.
+pub fn remove(provider: &str) -> Result<bool> {
+    match entry(provider)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(unavailable(e)),
+    }
+}
+
+/// Set `provider`'s key variable from the keychain if neither the environment nor the config
+/// files, which go first, set one. The keychain is asked once per provider, when it is first
+/// used, rather than for every provider at startup. A system without a keychain just has none
+/// in it; `Err` is for that, and `Ok(false)` for a provider with no key anywhere.
+pub fn load(provider: &str) -> Result<bool> {
+    static LOOKED_UP: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+    let Ok(var) = var(provider) else {
+        return Ok(false);
+    };
+    if env::var(var).is_ok_and(|v| !v.trim().is_empty()) {
+        return Ok(true);
+    }
+    let first = LOOKED_UP
+        .lock()
+        .is_ok_and(|mut seen| seen.insert(provider.to_string()));
+    if !enabled() || !first {
+        return Ok(false);
+    }
+    let key = get(provider)?;
+    if let Some(key) = &key {
+        env::set_var(var, key);
+    }
+    Ok(key.is_some())
+}
+
+/// The first of `providers` with a key in the keychain, which is then set as by `load`.
+pub fn first_kept<'a>(providers: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    for provider in providers {
+        match load(provider) {
+            Ok(true) => return Some(provider),
+            Ok(false) => {}
+            // no keychain here, or a locked one: the rest can't be read either
+            Err(_) => return None,
+        }
+    }
+    None
+}
@@ -0,0 +1,56 @@
+use crate::spinner;
+use crate::theme::{self, Stream};
+use std::env;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing_subscriber::EnvFilter;
+
+/// Whether the answer on stdout stopped mid-line, where a log line shouldn't start.
+static MID_LINE: AtomicBool = AtomicBool::new(false);
+
+/// Note where printing `text` of an answer left the cursor.
+pub fn printed(text: &str) {
+    if !text.is_empty() {
+        MID_LINE.store(!text.ends_with('\n'), Ordering::Relaxed);
+    }
+}
+
+/// What mindlink logs with `verbose` `-v`s: warnings (retries, memory that couldn't be read or
+/// saved...), then the requests sent, then body sizes and timings. Nothing under `AI_QUIET`
+/// unless asked for with `-v`.
+fn directives(verbose: u8) -> &'static str {
+    let quiet = matches!(env::var("AI_QUIET").as_deref(), Ok("1") | Ok("true"));
+    match verbose {
+        0 if quiet => "off",
+        0 => "mindlink=warn",
+        1 => "mindlink=info",
+        2 => "mindlink=debug",
+        _ => "mindlink=trace",
+    }
+}
+
+/// Send `tracing` events to stderr, clear of the spinner and coloured only as `NO_COLOR` allows.
+/// `RUST_LOG`, when set, picks the events instead of `-v`, e.g. `RUST_LOG=mindlink=debug,reqwest=trace`.
+pub fn init(verbose: u8) {
+    let filter = match env::var("RUST_LOG") {
+        Ok(spec) if !spec.trim().is_empty() => EnvFilter::try_new(&spec).unwrap_or_else(|e| {
+            note!("(RUST_LOG={:?} isn't a filter: {})", spec, e);
+            EnvFilter::new(directives(verbose))
+        }),
+        _ => EnvFilter::new(directives(verbose)),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(|| {
+            spinner::pause();
+            if MID_LINE.swap(false, Ordering::Relaxed) && std::io::stdout().is_terminal() {
+                eprintln!();
+            }
+            std::io::stderr()
+        })
+        .with_ansi(theme::enabled(Stream::Stderr))
+        .without_time()
+        // which module it came from only matters once you look into the details
+        .with_target(verbose >= 2)
+        .init();
+}
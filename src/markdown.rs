@@ -0,0 +1,393 @@
+use ratatui::crossterm::terminal;
+use regex::Regex;
+use std::env;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const UNDERLINE: &str = "\x1b[4m";
+const CODE: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+struct Patterns {
+    heading: Regex,
+    bullet: Regex,
+    table_rule: Regex,
+    link: Regex,
+    bold: Regex,
+    italic: Regex,
+    strike: Regex,
+    ansi: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        let re = |p: &str| Regex::new(p).expect("built-in markdown pattern");
+        Patterns {
+            heading: re(r"^(#{1,6})\s+(.*?)(?:\s+#+)?\s*$"),
+            bullet: re(r"^(\s*)[-*+]\s+(.*)$"),
+            table_rule: re(r"^\s*:?-+:?\s*$"),
+            link: re(r"\[([^\]]+)\]\(([^)\s]+)\)"),
+            bold: re(r"\*\*([^*]+)\*\*|\b__([^_]+)__\b"),
+            // `*` followed by a space is multiplication or a bullet, not emphasis
+            italic: re(r"\*([^*\s](?:[^*]*[^*\s])?)\*"),
+            strike: re(r"~~([^~]+)~~"),
+            ansi: re(r"\x1b\[[0-9;]*m"),
+        }
+    })
+}
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// `AI_CODE_THEME` if syntect ships a theme by that name, else base16-ocean.dark.
+fn theme() -> &'static Theme {
+    static THEME: OnceLock<Theme> = OnceLock::new();
+    THEME.get_or_init(|| {
+        let mut themes = ThemeSet::load_defaults().themes;
+        let name = env::var("AI_CODE_THEME").unwrap_or_default();
+        themes
+            .remove(&name)
+            .or_else(|| themes.remove(DEFAULT_THEME))
+            .unwrap_or_default()
+    })
+}
+
+/// Terminal columns `text` takes once its escape codes are left out.
+pub fn width(text: &str) -> usize {
+    patterns().ansi.replace_all(text, "").chars().count()
+}
+
+/// Columns to wrap answers at: `AI_WIDTH` (`--width`), or else the terminal's. `None` for `0`,
+/// or when stdout isn't a terminal, leaves the lines as the model sent them.
+pub fn wrap_width() -> Option<usize> {
+    match env::var("AI_WIDTH")
+        .ok()
+        .and_then(|w| w.trim().parse().ok())
+    {
+        Some(0) => None,
+        Some(columns) => Some(columns),
+        None if std::io::stdout().is_terminal() => terminal::size()
+            .ok()
+            .map(|(columns, _)| usize::from(columns))
+            .filter(|&c| c > 0),
+        None => None,
+    }
+}
+
+/// `line` (styled, without its newline) broken at spaces into lines of at most `columns`
+/// columns, the ones after the first starting with `indent`. A word longer than that is left
+/// whole.
+fn wrap(line: &str, columns: usize, indent: &str) -> String {
+    if width(line) <= columns {
+        return line.to_string();
+    }
+    let body = line.trim_start_matches(' ');
+    let mut out = line[..line.len() - body.len()].to_string();
+    let mut column = width(&out);
+    for (i, word) in body.split(' ').enumerate() {
+        let w = width(word);
+        if i > 0 && w > 0 && column + 1 + w > columns {
+            out.push('\n');
+            out.push_str(indent);
+            column = width(indent);
+        } else if i > 0 {
+            out.push(' ');
+            column += 1;
+        }
+        out.push_str(word);
+        column += w;
+    }
+    out
+}
+
+/// The fence a line opening a code block starts it with: three or more backticks or tildes.
+fn opens_fence(trimmed: &str) -> Option<String> {
+    if !trimmed.starts_with("```") && !trimmed.starts_with("~~~") {
+        return None;
+    }
+    let marker = trimmed.chars().next().unwrap_or('`');
+    let len = trimmed.chars().take_while(|&c| c == marker).count();
+    Some(marker.to_string().repeat(len))
+}
+
+fn closes_fence(fence: &str, trimmed: &str) -> bool {
+    trimmed.starts_with(fence) && trimmed.trim_start_matches(fence).trim().is_empty()
+}
+
+/// Bold, italics, strikethrough, links and code spans within one line.
+fn inline(text: &str) -> String {
+    let p = patterns();
+    let mut out = String::new();
+    let spans: Vec<&str> = text.split('`').collect();
+    for (i, span) in spans.iter().enumerate() {
+        if i % 2 == 1 && i + 1 < spans.len() {
+            out.push_str(&format!("{}{}{}", CODE, span, RESET));
+            continue;
+        }
+        if i % 2 == 1 {
+            // an unmatched backtick
+            out.push('`');
+        }
+        let styled = p.link.replace_all(span, |c: &regex::Captures| {
+            if c[1] == c[2] {
+                format!("{}{}\x1b[24m", UNDERLINE, &c[1])
+            } else {
+                format!("{}{}\x1b[24m {}({})\x1b[22m", UNDERLINE, &c[1], DIM, &c[2])
+            }
+        });
+        let styled = p.bold.replace_all(&styled, |c: &regex::Captures| {
+            let inner = c.get(1).or_else(|| c.get(2)).map_or("", |m| m.as_str());
+            format!("{}{}\x1b[22m", BOLD, inner)
+        });
+        let styled = p.italic.replace_all(&styled, "\x1b[3m$1\x1b[23m");
+        let styled = p.strike.replace_all(&styled, "\x1b[9m$1\x1b[29m");
+        out.push_str(&styled);
+    }
+    out
+}
+
+/// The contents of the fenced code blocks in `text`, each ending in a newline.
+pub fn code_blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(String, String)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match &mut open {
+            Some((fence, code)) => {
+                if closes_fence(fence, trimmed) {
+                    blocks.extend(open.take().map(|(_, code)| code));
+                } else {
+                    code.push_str(line);
+                    code.push('\n');
+                }
+            }
+            None => open = opens_fence(trimmed).map(|fence| (fence, String::new())),
+        }
+    }
+    // an answer cut off inside a block still has what came of it
+    blocks.extend(open.map(|(_, code)| code).filter(|c| !c.is_empty()));
+    blocks
+}
+
+/// Turns markdown into styled terminal text as it streams in. Each line is shown once it is
+/// complete; a table waits for its last row so that its columns line up.
+#[derive(Default)]
+pub struct Renderer {
+    line: String,
+    fence: Option<String>,
+    // None inside a block whose language syntect doesn't know
+    code: Option<HighlightLines<'static>>,
+    table: Vec<String>,
+    /// Only wrap the lines, leaving the markdown as it is.
+    plain: bool,
+    width: Option<usize>,
+}
+
+impl Renderer {
+    /// Styling the markdown, or with `plain` leaving it be; either way, lines outside code
+    /// blocks and tables are wrapped at `width` columns, if given.
+    pub fn new(plain: bool, width: Option<usize>) -> Self {
+        Self {
+            plain,
+            width,
+            ..Self::default()
+        }
+    }
+
+    /// The whole of `text`, rendered.
+    pub fn render(mut self, text: &str) -> String {
+        let mut out = self.push(text);
+        out.push_str(&self.finish());
+        out
+    }
+
+    /// What can be shown of the text so far, given the next piece of it.
+    pub fn push(&mut self, piece: &str) -> String {
+        self.line.push_str(piece);
+        let mut out = String::new();
+        while let Some(i) = self.line.find('\n') {
+            let line: String = self.line.drain(..=i).collect();
+            out.push_str(&self.block(line.trim_end_matches(['\n', '\r'])));
+        }
+        out
+    }
+
+    /// The rest of the text, once it is complete (or cancelled), without a final newline.
+    pub fn finish(&mut self) -> String {
+        let line = std::mem::take(&mut self.line);
+        let mut out = if line.is_empty() {
+            String::new()
+        } else {
+            self.block(&line)
+        };
+        out.push_str(&self.table());
+        out.truncate(out.trim_end_matches('\n').len());
+        out
+    }
+
+    /// `line`, rendered from `original`, wrapped at the width if there is one. Continued lines
+    /// start with `hang`, or else line up with the text after `original`'s indentation and bullet.
+    fn wrapped(&self, line: &str, original: &str, hang: Option<&str>) -> String {
+        let Some(columns) = self.width else {
+            return line.to_string();
+        };
+        let trimmed = original.trim_start();
+        let bullet = ["- ", "* ", "+ "].iter().any(|b| trimmed.starts_with(b));
+        let indent = " ".repeat(original.len() - trimmed.len() + if bullet { 2 } else { 0 });
+        wrap(line, columns, hang.unwrap_or(&indent))
+    }
+
+    fn block(&mut self, line: &str) -> String {
+        let trimmed = line.trim_start();
+        if self.plain {
+            match &self.fence {
+                Some(fence) if closes_fence(fence, trimmed) => self.fence = None,
+                Some(_) => {}
+                None => match opens_fence(trimmed) {
+                    Some(fence) => self.fence = Some(fence),
+                    None if trimmed.starts_with('|') => {}
+                    None => {
+                        let hang = trimmed.starts_with('>').then_some("> ");
+                        return format!("{}\n", self.wrapped(line, line, hang));
+                    }
+                },
+            }
+            return format!("{}\n", line);
+        }
+        if let Some(fence) = &self.fence {
+            if closes_fence(fence, trimmed) {
+                self.fence = None;
+                self.code = None;
+                return format!("{}{}{}\n", DIM, line, RESET);
+            }
+            let highlighted = self.code.as_mut().and_then(|h| {
+                let line = format!("{}\n", line);
+                let ranges = h.highlight_line(&line, syntaxes()).ok()?;
+                Some(as_24_bit_terminal_escaped(&ranges, false))
+            });
+            return match highlighted {
+                Some(text) => format!("{}{}\n", text.trim_end_matches('\n'), RESET),
+                None => format!("{}{}{}\n", CODE, line, RESET),
+            };
+        }
+        if trimmed.starts_with('|') {
+            self.table.push(trimmed.to_string());
+            return String::new();
+        }
+        let mut out = self.table();
+        let p = patterns();
+        if let Some(fence) = opens_fence(trimmed) {
+            let lang = trimmed[fence.len()..]
+                .split_whitespace()
+                .next()
+                .unwrap_or("");
+            self.fence = Some(fence);
+            self.code = syntaxes()
+                .find_syntax_by_token(lang)
+                .filter(|_| !lang.is_empty())
+                .map(|syntax| HighlightLines::new(syntax, theme()));
+            out.push_str(&format!("{}{}{}\n", DIM, line, RESET));
+        } else if let Some(c) = p.heading.captures(line) {
+            let style = if c[1].len() == 1 {
+                format!("{}{}", BOLD, UNDERLINE)
+            } else {
+                BOLD.into()
+            };
+            let heading = format!("{}{}{}", style, inline(&c[2]), RESET);
+            out.push_str(&format!("{}\n", self.wrapped(&heading, "", None)));
+        } else if trimmed.len() >= 3
+            && trimmed
+                .chars()
+                .all(|c| c == ' ' || c == trimmed.as_bytes()[0] as char)
+            && matches!(trimmed.as_bytes()[0], b'-' | b'*' | b'_')
+        {
+            out.push_str(&format!("{}{}{}\n", DIM, "─".repeat(40), RESET));
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            let quote = quote.strip_prefix(' ').unwrap_or(quote);
+            let bar = format!("{}│{} ", DIM, RESET);
+            let quote = format!("{}{}", bar, inline(quote));
+            out.push_str(&format!("{}\n", self.wrapped(&quote, "", Some(&bar))));
+        } else if let Some(c) = p.bullet.captures(line) {
+            let bullet = format!("{}• {}", &c[1], inline(&c[2]));
+            out.push_str(&format!("{}\n", self.wrapped(&bullet, line, None)));
+        } else {
+            out.push_str(&self.wrapped(&inline(line), line, None));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The buffered table with its columns padded to the same width (and aligned the way its
+    /// `|:--|--:|` row asks), or nothing.
+    fn table(&mut self) -> String {
+        if self.table.is_empty() {
+            return String::new();
+        }
+        let p = patterns();
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        let mut header = false;
+        let mut align: Vec<(bool, bool)> = Vec::new();
+        for line in std::mem::take(&mut self.table) {
+            let line = line.trim();
+            let line = line.strip_prefix('|').unwrap_or(line);
+            let line = line.strip_suffix('|').unwrap_or(line);
+            let cells: Vec<&str> = line.split('|').map(str::trim).collect();
+            if rows.len() == 1 && cells.iter().all(|c| p.table_rule.is_match(c)) {
+                header = true;
+                align = cells
+                    .iter()
+                    .map(|c| (c.starts_with(':'), c.ends_with(':')))
+                    .collect();
+                continue;
+            }
+            rows.push(cells.into_iter().map(inline).collect());
+        }
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|i| {
+                rows.iter()
+                    .filter_map(|r| r.get(i))
+                    .map(|c| width(c))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let mut out = String::new();
+        for (n, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = (0..columns)
+                .map(|i| {
+                    let cell = row.get(i).map_or("", String::as_str);
+                    let pad = widths[i] - width(cell);
+                    let left = match align.get(i) {
+                        Some((true, true)) => pad / 2,
+                        Some((false, true)) => pad,
+                        _ => 0,
+                    };
+                    let (left, right) = (" ".repeat(left), " ".repeat(pad - left));
+                    if header && n == 0 {
+                        format!("{}{}{}{}{}", left, BOLD, cell, RESET, right)
+                    } else {
+                        format!("{}{}{}", left, cell, right)
+                    }
+                })
+                .collect();
+            out.push_str(cells.join(&format!(" {}│{} ", DIM, RESET)).trim_end());
+            out.push('\n');
+            if header && n == 0 {
+                let rule: Vec<String> = widths.iter().map(|&w| "─".repeat(w)).collect();
+                out.push_str(&format!("{}{}{}\n", DIM, rule.join("─┼─"), RESET));
+            }
+        }
+        out
+    }
+}
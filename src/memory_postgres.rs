@@ -0,0 +1,886 @@
+use crate::ai_memory::{
+    content_hash, recall_words, ChatTurn, Fact, MemoryBackend, Retention, SearchHit, Session,
+    Summary, TurnFilter, TurnMeta, DEFAULT_SESSION,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::Row;
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::time::Duration;
+
+/// Everything lives in `mindlink_*` tables, so the memory can share a database with other things.
+/// Sessions, turns and facts are shared by the whole team; which session is current is per user.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS mindlink_sessions(
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created TIMESTAMPTZ NOT NULL,
+    system_prompt TEXT
+);
+CREATE TABLE IF NOT EXISTS mindlink_turns(
+    id BIGSERIAL PRIMARY KEY,
+    session_id BIGINT NOT NULL REFERENCES mindlink_sessions(id) ON DELETE CASCADE,
+    author TEXT NOT NULL,
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+    ts TIMESTAMPTZ NOT NULL,
+    prompt_tokens BIGINT,
+    completion_tokens BIGINT,
+    fingerprint TEXT,
+    pinned BOOLEAN NOT NULL DEFAULT FALSE,
+    provider TEXT,
+    model TEXT,
+    latency_ms BIGINT,
+    uid TEXT NOT NULL UNIQUE DEFAULT md5(random()::text || clock_timestamp()::text),
+    updated TIMESTAMPTZ,
+    content_hash TEXT NOT NULL,
+    search TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED
+);
+CREATE INDEX IF NOT EXISTS mindlink_turns_session ON mindlink_turns(session_id, id);
+CREATE INDEX IF NOT EXISTS mindlink_turns_search ON mindlink_turns USING GIN(search);
+-- how often a turn was recalled into a later prompt, for scored retrieval
+ALTER TABLE mindlink_turns ADD COLUMN IF NOT EXISTS refs BIGINT NOT NULL DEFAULT 0;
+CREATE TABLE IF NOT EXISTS mindlink_tags(
+    turn_id BIGINT NOT NULL REFERENCES mindlink_turns(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY(turn_id, tag)
+);
+CREATE TABLE IF NOT EXISTS mindlink_facts(
+    id BIGSERIAL PRIMARY KEY,
+    author TEXT NOT NULL,
+    content TEXT NOT NULL,
+    ts TIMESTAMPTZ NOT NULL
+);
+CREATE TABLE IF NOT EXISTS mindlink_summaries(
+    id BIGSERIAL PRIMARY KEY,
+    session_id BIGINT NOT NULL REFERENCES mindlink_sessions(id) ON DELETE CASCADE,
+    through_id BIGINT NOT NULL,
+    content TEXT NOT NULL,
+    ts TIMESTAMPTZ NOT NULL
+);
+CREATE TABLE IF NOT EXISTS mindlink_embeddings(
+    turn_id BIGINT NOT NULL REFERENCES mindlink_turns(id) ON DELETE CASCADE,
+    model TEXT NOT NULL,
+    vector BYTEA NOT NULL,
+    PRIMARY KEY(turn_id, model)
+);
+CREATE TABLE IF NOT EXISTS mindlink_cache(
+    key TEXT PRIMARY KEY,
+    response TEXT NOT NULL,
+    ts TIMESTAMPTZ NOT NULL
+);
+CREATE TABLE IF NOT EXISTS mindlink_current(
+    author TEXT PRIMARY KEY,
+    session TEXT NOT NULL
+);
+";
+
+const TURN_COLUMNS: &str = "id, role, content, ts, prompt_tokens, completion_tokens, fingerprint, \
+                            pinned, provider, model, latency_ms, author";
+
+/// Memory in a PostgreSQL database shared by a team (`AI_MEMORY_BACKEND=postgres`). Every turn
+/// and fact records who added it, from `AI_MEMORY_USER` (default: the login name).
+pub struct PgMemory {
+    pool: PgPool,
+    author: String,
+}
+
+/// The memory trait is synchronous; queries run on the surrounding Tokio runtime, whose other
+/// tasks carry on meanwhile.
+fn block<F: Future>(fut: F) -> F::Output {
+    tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(fut))
+}
+
+fn turn_row(r: &PgRow) -> Result<ChatTurn> {
+    Ok(ChatTurn {
+        id: r.try_get("id")?,
+        role: r.try_get("role")?,
+        content: r.try_get("content")?,
+        ts: r.try_get("ts")?,
+        prompt_tokens: r.try_get("prompt_tokens")?,
+        completion_tokens: r.try_get("completion_tokens")?,
+        fingerprint: r.try_get("fingerprint")?,
+        pinned: r.try_get("pinned")?,
+        provider: r.try_get("provider")?,
+        model: r.try_get("model")?,
+        latency_ms: r.try_get("latency_ms")?,
+        author: r.try_get("author")?,
+    })
+}
+
+fn no_turn(id: i64) -> anyhow::Error {
+    anyhow!("no turn #{} (see `mindlink memory-show`)", id)
+}
+
+impl PgMemory {
+    async fn connect(url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(4)
+            .acquire_timeout(Duration::from_secs(10))
+            .connect(url)
+            .await?;
+        // the lock keeps teammates starting up at the same moment from creating the tables twice
+        let mut tx = pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('mindlink-schema'))")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::raw_sql(SCHEMA).execute(&mut *tx).await?;
+        sqlx::query(
+            "INSERT INTO mindlink_sessions (name, created) VALUES ($1, now()) ON CONFLICT (name) DO NOTHING",
+        )
+        .bind(DEFAULT_SESSION)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        let author = ["AI_MEMORY_USER", "USER", "USERNAME"]
+            .iter()
+            .find_map(|k| env::var(k).ok().filter(|v| !v.trim().is_empty()))
+            .unwrap_or_else(|| "unknown".to_string());
+        Ok(Self { pool, author })
+    }
+
+    async fn session_id(&self) -> Result<i64> {
+        let name = self.current_session_async().await?;
+        Ok(
+            sqlx::query_scalar("SELECT id FROM mindlink_sessions WHERE name = $1")
+                .bind(name)
+                .fetch_one(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn current_session_async(&self) -> Result<String> {
+        let name: Option<String> = sqlx::query_scalar(
+            "SELECT s.name FROM mindlink_current c JOIN mindlink_sessions s ON s.name = c.session WHERE c.author = $1",
+        )
+        .bind(&self.author)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(name.unwrap_or_else(|| DEFAULT_SESSION.to_string()))
+    }
+
+    async fn session_exists(&self, name: &str) -> Result<bool> {
+        let n: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM mindlink_sessions WHERE name = $1")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(n > 0)
+    }
+
+    async fn turns(&self, sql: &str, session: i64, extra: Option<i64>) -> Result<Vec<ChatTurn>> {
+        let mut q = sqlx::query(sql).bind(session);
+        if let Some(x) = extra {
+            q = q.bind(x);
+        }
+        q.fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(turn_row)
+            .collect()
+    }
+
+    // a summary covering a deleted turn would keep repeating it
+    async fn drop_summaries_from(&self, session: i64, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM mindlink_summaries WHERE session_id = $1 AND through_id >= $2")
+            .bind(session)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn turn_session(&self, id: i64) -> Result<i64> {
+        sqlx::query_scalar("SELECT session_id FROM mindlink_turns WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| no_turn(id))
+    }
+}
+
+impl MemoryBackend for PgMemory {
+    /// `path` is a `postgres://` connection URL.
+    fn open(path: &str) -> Result<Self> {
+        block(Self::connect(path))
+    }
+
+    fn append_meta(&self, role: &str, content: &str, meta: &TurnMeta) -> Result<i64> {
+        block(async {
+            let session = self.session_id().await?;
+            Ok(sqlx::query_scalar(
+                "INSERT INTO mindlink_turns (session_id, author, role, content, ts, prompt_tokens, completion_tokens, \
+                 fingerprint, provider, model, latency_ms, content_hash)
+                 VALUES ($1, $2, $3, $4, now(), $5, $6, $7, $8, $9, $10, $11) RETURNING id",
+            )
+            .bind(session)
+            .bind(&self.author)
+            .bind(role)
+            .bind(content)
+            .bind(meta.prompt_tokens)
+            .bind(meta.completion_tokens)
+            .bind(&meta.fingerprint)
+            .bind(&meta.provider)
+            .bind(&meta.model)
+            .bind(meta.latency_ms)
+            .bind(content_hash(content))
+            .fetch_one(&self.pool)
+            .await?)
+        })
+    }
+
+    fn last_turns(&self, limit: usize) -> Result<Vec<ChatTurn>> {
+        block(async {
+            let sql = format!(
+                "SELECT {} FROM mindlink_turns WHERE session_id = $1 ORDER BY id DESC LIMIT $2",
+                TURN_COLUMNS
+            );
+            let mut v = self
+                .turns(&sql, self.session_id().await?, Some(limit as i64))
+                .await?;
+            v.reverse();
+            Ok(v)
+        })
+    }
+
+    fn last_turns_filtered(&self, limit: usize, filter: &TurnFilter) -> Result<Vec<ChatTurn>> {
+        block(async {
+            let session = match &filter.session {
+                Some(name) => {
+                    sqlx::query_scalar("SELECT id FROM mindlink_sessions WHERE name = $1")
+                        .bind(name)
+                        .fetch_optional(&self.pool)
+                        .await?
+                        .ok_or_else(|| {
+                            anyhow!("no session named '{}' (see `mindlink session list`)", name)
+                        })?
+                }
+                None => self.session_id().await?,
+            };
+            let sql = format!(
+                "SELECT {} FROM mindlink_turns WHERE session_id = $1 \
+                 AND ($2::TEXT IS NULL OR id IN (SELECT turn_id FROM mindlink_tags WHERE tag = $2)) \
+                 AND ($3::TEXT IS NULL OR role = $3) AND ($4::TIMESTAMPTZ IS NULL OR ts >= $4) \
+                 AND ($5::TIMESTAMPTZ IS NULL OR ts < $5) ORDER BY id DESC LIMIT $6",
+                TURN_COLUMNS
+            );
+            let rows = sqlx::query(&sql)
+                .bind(session)
+                .bind(&filter.tag)
+                .bind(&filter.role)
+                .bind(filter.since)
+                .bind(filter.until)
+                .bind(limit as i64)
+                .fetch_all(&self.pool)
+                .await?;
+            let mut v = rows.iter().map(turn_row).collect::<Result<Vec<_>>>()?;
+            v.reverse();
+            Ok(v)
+        })
+    }
+
+    fn turns_after(&self, id: i64) -> Result<Vec<ChatTurn>> {
+        block(async {
+            let sql = format!(
+                "SELECT {} FROM mindlink_turns WHERE session_id = $1 AND id > $2 ORDER BY id",
+                TURN_COLUMNS
+            );
+            self.turns(&sql, self.session_id().await?, Some(id)).await
+        })
+    }
+
+    fn turns_by_id(&self, ids: &[i64]) -> Result<Vec<ChatTurn>> {
+        block(async {
+            let sql = format!(
+                "SELECT {} FROM mindlink_turns WHERE id = ANY($1) ORDER BY id",
+                TURN_COLUMNS
+            );
+            let rows = sqlx::query(&sql).bind(ids).fetch_all(&self.pool).await?;
+            rows.iter().map(turn_row).collect()
+        })
+    }
+
+    fn note_references(&self, ids: &[i64]) -> Result<()> {
+        block(async {
+            sqlx::query("UPDATE mindlink_turns SET refs = refs + 1 WHERE id = ANY($1)")
+                .bind(ids)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn references(&self, ids: &[i64]) -> Result<HashMap<i64, i64>> {
+        block(async {
+            let rows: Vec<(i64, i64)> = sqlx::query_as(
+                "SELECT id, refs FROM mindlink_turns WHERE id = ANY($1) AND refs > 0",
+            )
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await?;
+            Ok(rows.into_iter().collect())
+        })
+    }
+
+    fn pinned_turns(&self) -> Result<Vec<ChatTurn>> {
+        block(async {
+            let sql = format!(
+                "SELECT {} FROM mindlink_turns WHERE session_id = $1 AND pinned ORDER BY id",
+                TURN_COLUMNS
+            );
+            self.turns(&sql, self.session_id().await?, None).await
+        })
+    }
+
+    fn turn(&self, id: i64) -> Result<ChatTurn> {
+        block(async {
+            let sql = format!("SELECT {} FROM mindlink_turns WHERE id = $1", TURN_COLUMNS);
+            let row = sqlx::query(&sql)
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+            turn_row(&row.ok_or_else(|| no_turn(id))?)
+        })
+    }
+
+    fn search(
+        &self,
+        query: &str,
+        all_sessions: bool,
+        limit: usize,
+        mark: (&str, &str),
+    ) -> Result<Vec<SearchHit>> {
+        if query.split_whitespace().next().is_none() {
+            return Ok(Vec::new());
+        }
+        block(async {
+            let session = if all_sessions {
+                None
+            } else {
+                Some(self.session_id().await?)
+            };
+            // plainto_tsquery wants every word, like the SQLite search
+            let options = format!(
+                "StartSel=\"{}\", StopSel=\"{}\", MaxWords=24, MinWords=12",
+                mark.0.replace('"', "\"\""),
+                mark.1.replace('"', "\"\"")
+            );
+            let rows = sqlx::query(
+                "SELECT t.role, t.ts, s.name, ts_headline('simple', t.content, q, $2) AS snippet
+                 FROM mindlink_turns t JOIN mindlink_sessions s ON s.id = t.session_id,
+                      plainto_tsquery('simple', $1) q
+                 WHERE t.search @@ q AND ($3::BIGINT IS NULL OR t.session_id = $3)
+                 ORDER BY ts_rank(t.search, q) DESC LIMIT $4",
+            )
+            .bind(query)
+            .bind(options)
+            .bind(session)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+            rows.iter()
+                .map(|r| {
+                    Ok(SearchHit {
+                        role: r.try_get("role")?,
+                        ts: r.try_get("ts")?,
+                        session: r.try_get("name")?,
+                        snippet: r.try_get("snippet")?,
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn other_session_turns(&self, text: &str, limit: usize) -> Result<Vec<(String, ChatTurn)>> {
+        let words = recall_words(text);
+        if words.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+        block(async {
+            let sql = format!(
+                "SELECT {}, (SELECT name FROM mindlink_sessions s WHERE s.id = session_id) AS session_name
+                 FROM mindlink_turns, websearch_to_tsquery('simple', $1) q
+                 WHERE search @@ q AND session_id <> $2 ORDER BY ts_rank(search, q) DESC LIMIT $3",
+                TURN_COLUMNS
+            );
+            let rows = sqlx::query(&sql)
+                .bind(words.join(" or "))
+                .bind(self.session_id().await?)
+                .bind(limit as i64)
+                .fetch_all(&self.pool)
+                .await?;
+            rows.iter()
+                .map(|r| Ok((r.try_get("session_name")?, turn_row(r)?)))
+                .collect()
+        })
+    }
+
+    fn clear(&self) -> Result<()> {
+        block(async {
+            let session = self.session_id().await?;
+            sqlx::query("DELETE FROM mindlink_turns WHERE session_id = $1")
+                .bind(session)
+                .execute(&self.pool)
+                .await?;
+            sqlx::query("DELETE FROM mindlink_summaries WHERE session_id = $1")
+                .bind(session)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn has_exchange(&self, question: &str, answer: &str) -> Result<bool> {
+        block(async {
+            let n: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM mindlink_turns u JOIN mindlink_turns a
+                   ON a.id = (SELECT MIN(id) FROM mindlink_turns WHERE session_id = u.session_id AND id > u.id)
+                 WHERE u.session_id = $1 AND u.role = 'user' AND u.content_hash = $2
+                   AND a.role = 'assistant' AND a.content_hash = $3",
+            )
+            .bind(self.session_id().await?)
+            .bind(content_hash(question))
+            .bind(content_hash(answer))
+            .fetch_one(&self.pool)
+            .await?;
+            Ok(n > 0)
+        })
+    }
+
+    /// Like the SQLite version, except that the size limit counts stored text rather than disk
+    /// pages, which Postgres only hands back after its own vacuum.
+    fn prune(&self, policy: &Retention) -> Result<usize> {
+        block(async {
+            let mut n = 0;
+            if let Some(days) = policy.max_age_days {
+                n += sqlx::query(
+                    "DELETE FROM mindlink_turns WHERE NOT pinned AND ts < now() - make_interval(days => $1)",
+                )
+                .bind(days as i32)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+            }
+            if let Some(max) = policy.max_turns {
+                n += sqlx::query(
+                    "DELETE FROM mindlink_turns WHERE NOT pinned AND id NOT IN \
+                     (SELECT id FROM mindlink_turns WHERE NOT pinned ORDER BY id DESC LIMIT $1)",
+                )
+                .bind(max as i64)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+            }
+            if let Some(mb) = policy.max_size_mb {
+                let limit = (mb * 1024 * 1024) as i64;
+                let used = || {
+                    sqlx::query_scalar::<_, i64>(
+                        "SELECT COALESCE(SUM(octet_length(content)), 0)::BIGINT FROM mindlink_turns",
+                    )
+                    .fetch_one(&self.pool)
+                };
+                if used().await? > limit {
+                    sqlx::query("DELETE FROM mindlink_cache")
+                        .execute(&self.pool)
+                        .await?;
+                    while used().await? > limit {
+                        let k = sqlx::query(
+                            "DELETE FROM mindlink_turns WHERE id IN \
+                             (SELECT id FROM mindlink_turns WHERE NOT pinned ORDER BY id LIMIT 100)",
+                        )
+                        .execute(&self.pool)
+                        .await?
+                        .rows_affected();
+                        if k == 0 {
+                            break;
+                        }
+                        n += k;
+                    }
+                }
+            }
+            Ok(n as usize)
+        })
+    }
+
+    fn set_pinned(&self, id: i64, pinned: bool) -> Result<()> {
+        block(async {
+            let done =
+                sqlx::query("UPDATE mindlink_turns SET pinned = $1, updated = now() WHERE id = $2")
+                    .bind(pinned)
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+            if done.rows_affected() == 0 {
+                return Err(no_turn(id));
+            }
+            Ok(())
+        })
+    }
+
+    fn tag(&self, id: i64, tag: &str) -> Result<()> {
+        if tag.is_empty() || tag.contains(char::is_whitespace) {
+            return Err(anyhow!("tags are single words, e.g. bug-hunt"));
+        }
+        block(async {
+            self.turn_session(id).await?;
+            sqlx::query(
+                "INSERT INTO mindlink_tags (turn_id, tag) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            )
+            .bind(id)
+            .bind(tag)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn untag(&self, id: i64, tag: &str) -> Result<()> {
+        block(async {
+            let done = sqlx::query("DELETE FROM mindlink_tags WHERE turn_id = $1 AND tag = $2")
+                .bind(id)
+                .bind(tag)
+                .execute(&self.pool)
+                .await?;
+            if done.rows_affected() == 0 {
+                return Err(anyhow!("turn #{} is not tagged {}", id, tag));
+            }
+            Ok(())
+        })
+    }
+
+    fn set_content(&self, id: i64, content: &str) -> Result<()> {
+        block(async {
+            let session = self.turn_session(id).await?;
+            sqlx::query(
+                "UPDATE mindlink_turns SET content = $1, updated = now(), content_hash = $2 WHERE id = $3",
+            )
+            .bind(content)
+            .bind(content_hash(content))
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+            sqlx::query("DELETE FROM mindlink_embeddings WHERE turn_id = $1")
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+            self.drop_summaries_from(session, id).await
+        })
+    }
+
+    fn delete(&self, id: i64) -> Result<()> {
+        block(async {
+            let session = self.turn_session(id).await?;
+            sqlx::query("DELETE FROM mindlink_turns WHERE id = $1")
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+            self.drop_summaries_from(session, id).await
+        })
+    }
+
+    fn delete_range(&self, from: Option<i64>, to: Option<i64>) -> Result<usize> {
+        let (from, to) = (from.unwrap_or(i64::MIN), to.unwrap_or(i64::MAX));
+        if from > to {
+            return Err(anyhow!("--from {} is after --to {}", from, to));
+        }
+        block(async {
+            let session = self.session_id().await?;
+            let n = sqlx::query(
+                "DELETE FROM mindlink_turns WHERE session_id = $1 AND id BETWEEN $2 AND $3",
+            )
+            .bind(session)
+            .bind(from)
+            .bind(to)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+            if n > 0 {
+                self.drop_summaries_from(session, from).await?;
+            }
+            Ok(n as usize)
+        })
+    }
+
+    fn latest_summary(&self) -> Result<Option<Summary>> {
+        block(async {
+            let row = sqlx::query(
+                "SELECT through_id, content FROM mindlink_summaries WHERE session_id = $1 ORDER BY id DESC LIMIT 1",
+            )
+            .bind(self.session_id().await?)
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(|r| {
+                Ok(Summary {
+                    through_id: r.try_get("through_id")?,
+                    content: r.try_get("content")?,
+                })
+            })
+            .transpose()
+        })
+    }
+
+    fn summary_as_of(&self, session: &str, at: DateTime<Utc>) -> Result<Option<Summary>> {
+        block(async {
+            let row = sqlx::query(
+                "SELECT through_id, content FROM mindlink_summaries \
+                 WHERE session_id = (SELECT id FROM mindlink_sessions WHERE name = $1) AND ts < $2 \
+                 ORDER BY id DESC LIMIT 1",
+            )
+            .bind(session)
+            .bind(at)
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(|r| {
+                Ok(Summary {
+                    through_id: r.try_get("through_id")?,
+                    content: r.try_get("content")?,
+                })
+            })
+            .transpose()
+        })
+    }
+
+    fn put_summary(&self, through_id: i64, content: &str) -> Result<()> {
+        block(async {
+            sqlx::query(
+                "INSERT INTO mindlink_summaries (session_id, through_id, content, ts) VALUES ($1, $2, $3, now())",
+            )
+            .bind(self.session_id().await?)
+            .bind(through_id)
+            .bind(content)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn put_embedding(&self, turn_id: i64, model: &str, vector: &[f32]) -> Result<()> {
+        let bytes: Vec<u8> = vector.iter().flat_map(|x| x.to_le_bytes()).collect();
+        block(async {
+            sqlx::query(
+                "INSERT INTO mindlink_embeddings (turn_id, model, vector) VALUES ($1, $2, $3)
+                 ON CONFLICT (turn_id, model) DO UPDATE SET vector = EXCLUDED.vector",
+            )
+            .bind(turn_id)
+            .bind(model)
+            .bind(bytes)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn embeddings(&self, model: &str) -> Result<Vec<(i64, Vec<f32>)>> {
+        block(async {
+            let rows = sqlx::query(
+                "SELECT e.turn_id, e.vector FROM mindlink_embeddings e JOIN mindlink_turns t ON t.id = e.turn_id
+                 WHERE e.model = $1 AND t.session_id = $2",
+            )
+            .bind(model)
+            .bind(self.session_id().await?)
+            .fetch_all(&self.pool)
+            .await?;
+            rows.iter()
+                .map(|r| {
+                    let bytes: Vec<u8> = r.try_get("vector")?;
+                    let vector = bytes
+                        .chunks_exact(4)
+                        .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                        .collect();
+                    Ok((r.try_get("turn_id")?, vector))
+                })
+                .collect()
+        })
+    }
+
+    fn cache_get(&self, key: &str, ttl_secs: i64) -> Result<Option<String>> {
+        block(async {
+            Ok(sqlx::query_scalar(
+                "SELECT response FROM mindlink_cache WHERE key = $1 AND ts >= now() - make_interval(secs => $2)",
+            )
+            .bind(key)
+            .bind(ttl_secs as f64)
+            .fetch_optional(&self.pool)
+            .await?)
+        })
+    }
+
+    fn cache_put(&self, key: &str, response: &str) -> Result<()> {
+        block(async {
+            sqlx::query(
+                "INSERT INTO mindlink_cache (key, response, ts) VALUES ($1, $2, now())
+                 ON CONFLICT (key) DO UPDATE SET response = EXCLUDED.response, ts = EXCLUDED.ts",
+            )
+            .bind(key)
+            .bind(response)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn facts(&self) -> Result<Vec<Fact>> {
+        block(async {
+            let rows = sqlx::query("SELECT id, content, ts FROM mindlink_facts ORDER BY id")
+                .fetch_all(&self.pool)
+                .await?;
+            rows.iter()
+                .map(|r| {
+                    Ok(Fact {
+                        id: r.try_get("id")?,
+                        content: r.try_get("content")?,
+                        ts: r.try_get::<DateTime<Utc>, _>("ts")?,
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn add_fact(&self, content: &str) -> Result<i64> {
+        if content.trim().is_empty() {
+            return Err(anyhow!("nothing to remember"));
+        }
+        block(async {
+            Ok(sqlx::query_scalar(
+                "INSERT INTO mindlink_facts (author, content, ts) VALUES ($1, $2, now()) RETURNING id",
+            )
+            .bind(&self.author)
+            .bind(content.trim())
+            .fetch_one(&self.pool)
+            .await?)
+        })
+    }
+
+    fn forget_fact(&self, id: i64) -> Result<()> {
+        block(async {
+            let done = sqlx::query("DELETE FROM mindlink_facts WHERE id = $1")
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+            if done.rows_affected() == 0 {
+                return Err(anyhow!("no fact #{} (see `mindlink facts list`)", id));
+            }
+            Ok(())
+        })
+    }
+
+    fn current_session(&self) -> Result<String> {
+        block(self.current_session_async())
+    }
+
+    fn system_prompt(&self) -> Result<Option<String>> {
+        block(async {
+            Ok(
+                sqlx::query_scalar("SELECT system_prompt FROM mindlink_sessions WHERE id = $1")
+                    .bind(self.session_id().await?)
+                    .fetch_one(&self.pool)
+                    .await?,
+            )
+        })
+    }
+
+    fn set_system_prompt(&self, prompt: Option<&str>) -> Result<()> {
+        block(async {
+            sqlx::query("UPDATE mindlink_sessions SET system_prompt = $1 WHERE id = $2")
+                .bind(prompt)
+                .bind(self.session_id().await?)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn sessions(&self) -> Result<Vec<Session>> {
+        block(async {
+            let rows = sqlx::query(
+                "SELECT s.name, s.created, COUNT(t.id) AS turns, s.system_prompt
+                 FROM mindlink_sessions s LEFT JOIN mindlink_turns t ON t.session_id = s.id
+                 GROUP BY s.id ORDER BY s.name",
+            )
+            .fetch_all(&self.pool)
+            .await?;
+            rows.iter()
+                .map(|r| {
+                    Ok(Session {
+                        name: r.try_get("name")?,
+                        created: r.try_get("created")?,
+                        turns: r.try_get("turns")?,
+                        system_prompt: r.try_get("system_prompt")?,
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn latest_session(&self) -> Result<Option<String>> {
+        block(async {
+            Ok(sqlx::query_scalar(
+                "SELECT s.name FROM mindlink_turns t JOIN mindlink_sessions s ON s.id = t.session_id
+                 WHERE t.author = $1 ORDER BY t.id DESC LIMIT 1",
+            )
+            .bind(&self.author)
+            .fetch_optional(&self.pool)
+            .await?)
+        })
+    }
+
+    fn session_new(&self, name: &str) -> Result<()> {
+        if name.trim().is_empty() {
+            return Err(anyhow!("session name must not be empty"));
+        }
+        block(async {
+            let done = sqlx::query(
+                "INSERT INTO mindlink_sessions (name, created) VALUES ($1, now()) ON CONFLICT (name) DO NOTHING",
+            )
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+            if done.rows_affected() == 0 {
+                return Err(anyhow!("session '{}' already exists", name));
+            }
+            Ok(())
+        })?;
+        self.session_switch(name)
+    }
+
+    fn session_switch(&self, name: &str) -> Result<()> {
+        block(async {
+            if !self.session_exists(name).await? {
+                return Err(anyhow!(
+                    "no session named '{}' (see `mindlink session list`)",
+                    name
+                ));
+            }
+            sqlx::query(
+                "INSERT INTO mindlink_current (author, session) VALUES ($1, $2)
+                 ON CONFLICT (author) DO UPDATE SET session = EXCLUDED.session",
+            )
+            .bind(&self.author)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    /// Deletes the session for everyone; teammates who had it open fall back to the default one.
+    fn session_delete(&self, name: &str) -> Result<()> {
+        if name == DEFAULT_SESSION {
+            return Err(anyhow!(
+                "the default session cannot be deleted (use memory-clear to empty it)"
+            ));
+        }
+        block(async {
+            let done = sqlx::query("DELETE FROM mindlink_sessions WHERE name = $1")
+                .bind(name)
+                .execute(&self.pool)
+                .await?;
+            if done.rows_affected() == 0 {
+                return Err(anyhow!("no session named '{}'", name));
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{interrupt, markdown};
+use anyhow::{anyhow, Result};
+use ratatui::crossterm::terminal;
+use std::env;
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Whether an answer too long for the screen is shown again in the pager: only on a terminal,
+/// and not with `AI_PAGER=false` (`--no-pager`).
+pub fn enabled() -> bool {
+    std::io::stdout().is_terminal()
+        && !matches!(env::var("AI_PAGER").as_deref(), Ok("0") | Ok("false"))
+}
+
+/// Whether `text`, as shown with its escape codes, takes more rows than the terminal has once
+/// long lines wrap.
+fn too_long(text: &str) -> bool {
+    let Ok((columns, rows)) = terminal::size() else {
+        return false;
+    };
+    let columns = usize::from(columns.max(1));
+    let lines: usize = text
+        .lines()
+        .map(|line| markdown::width(line).div_ceil(columns).max(1))
+        .sum();
+    lines > usize::from(rows)
+}
+
+/// Show `text` in `$PAGER` (`less -R` by default) when it didn't fit on the screen, so an answer
+/// that scrolled away can be read from the top.
+pub fn page(text: &str) -> Result<()> {
+    if !enabled() || !too_long(text) {
+        return Ok(());
+    }
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less -R".to_string());
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or("less");
+    let mut command = Command::new(program);
+    command.args(words).stdin(Stdio::piped());
+    // as git does: a plain `less` still shows the colours
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "R");
+    }
+    // Ctrl-C goes to the pager as well, and is its alone to handle
+    let _paging = interrupt::Paging::begin();
+    let mut child = command
+        .spawn()
+        .map_err(|e| anyhow!("cannot start pager '{}': {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // quitting early closes the pipe, which is no error
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
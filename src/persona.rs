@@ -0,0 +1,83 @@
+use crate::config;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// A system prompt to answer as, with the model and temperature that suit it if it has them;
+/// `--persona <name>` sends it.
+#[derive(Serialize, Deserialize)]
+pub struct Persona {
+    pub system: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+}
+
+fn path(name: &str) -> Result<PathBuf> {
+    config::named_file("personas", name, "toml")
+}
+
+/// Save `persona` as `<name>.toml` in `personas` in the global config directory; an existing
+/// one is only replaced with `force`. Returns the file written.
+pub fn add(name: &str, persona: &Persona, force: bool) -> Result<PathBuf> {
+    let path = path(name)?;
+    if !force && path.exists() {
+        return Err(anyhow!(
+            "there is a persona named '{}' already; --force replaces it",
+            name
+        ));
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, toml::to_string(persona)?)
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(path)
+}
+
+pub fn load(name: &str) -> Result<Persona> {
+    let path = path(name)?;
+    let text = std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => {
+            anyhow!("no persona named '{}' (see `mindlink persona list`)", name)
+        }
+        _ => anyhow!("reading {}: {}", path.display(), e),
+    })?;
+    toml::from_str(&text).with_context(|| format!("in {}", path.display()))
+}
+
+/// Every persona's name, by name; a file that doesn't parse is left out with a note.
+pub fn list() -> Result<Vec<(String, Persona)>> {
+    let dir = config::named_dir("personas")?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", dir.display())),
+    };
+    let mut personas = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(".toml"))
+        else {
+            continue;
+        };
+        match load(name) {
+            Ok(persona) => personas.push((name.to_string(), persona)),
+            Err(e) => note!("({:#})", e),
+        }
+    }
+    personas.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(personas)
+}
+
+pub fn remove(name: &str) -> Result<()> {
+    let path = path(name)?;
+    std::fs::remove_file(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => anyhow!("no persona named '{}'", name),
+        _ => anyhow!("removing {}: {}", path.display(), e),
+    })
+}
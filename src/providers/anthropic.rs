@@ -0,0 +1,322 @@
+use super::{
+    required_env, send, split_system, stream_sse, ChatRequest, Completion, ImageData, ModelInfo,
+    Provider, StreamPiece, TokenSink, Usage,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{header, Client, Method, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+// Anthropic requires max_tokens on every request
+const ANTHROPIC_MAX_TOKENS: u32 = 4096;
+
+#[derive(Serialize)]
+struct AnthropicRequest {
+    model: String,
+    max_tokens: u32,
+    // Anthropic takes the system prompt as a top-level field, not a message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<AnthropicContent>,
+    messages: Vec<AnthropicMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+}
+
+#[derive(Serialize)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+#[derive(Serialize)]
+struct AnthropicImageSource {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    media_type: String,
+    data: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicBlockKind {
+    Text { text: String },
+    Image { source: AnthropicImageSource },
+}
+
+#[derive(Serialize)]
+struct AnthropicBlock {
+    #[serde(flatten)]
+    kind: AnthropicBlockKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+/// Plain text, or a block list when there are images or a cache breakpoint to attach.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum AnthropicContent {
+    Text(String),
+    Blocks(Vec<AnthropicBlock>),
+}
+
+impl AnthropicContent {
+    fn new(text: String, images: &[ImageData], cache: bool) -> Self {
+        if !cache && images.is_empty() {
+            return AnthropicContent::Text(text);
+        }
+        let block = |kind| AnthropicBlock {
+            kind,
+            cache_control: None,
+        };
+        let mut blocks: Vec<AnthropicBlock> = images
+            .iter()
+            .map(|img| {
+                block(AnthropicBlockKind::Image {
+                    source: AnthropicImageSource {
+                        kind: "base64",
+                        media_type: img.media_type.clone(),
+                        data: img.data.clone(),
+                    },
+                })
+            })
+            .collect();
+        blocks.push(block(AnthropicBlockKind::Text { text }));
+        if cache {
+            // the breakpoint covers everything up to and including the last block
+            if let Some(last) = blocks.last_mut() {
+                last.cache_control = Some(CacheControl { kind: "ephemeral" });
+            }
+        }
+        AnthropicContent::Blocks(blocks)
+    }
+}
+
+#[derive(Serialize)]
+struct AnthropicMessage {
+    role: String,
+    content: AnthropicContent,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct AnthropicUsage {
+    // excludes the cache reads and writes below
+    input_tokens: u64,
+    output_tokens: u64,
+    cache_read_input_tokens: u64,
+    cache_creation_input_tokens: u64,
+}
+
+impl From<AnthropicUsage> for Usage {
+    fn from(u: AnthropicUsage) -> Self {
+        Usage {
+            prompt_tokens: u.input_tokens
+                + u.cache_read_input_tokens
+                + u.cache_creation_input_tokens,
+            completion_tokens: u.output_tokens,
+            cached_tokens: u.cache_read_input_tokens,
+            reasoning_tokens: 0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AnthropicMessageStart {
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicStreamEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    delta: Option<AnthropicDelta>,
+    // input tokens arrive with message_start, output tokens with message_delta
+    message: Option<AnthropicMessageStart>,
+    usage: Option<AnthropicUsage>,
+    // on error, e.g. overloaded_error
+    error: Option<AnthropicError>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicError {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct AnthropicDelta {
+    text: Option<String>,
+    // on message_delta
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicContentBlock {
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicResponse {
+    content: Vec<AnthropicContentBlock>,
+    usage: Option<AnthropicUsage>,
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicModel {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct AnthropicModelList {
+    data: Vec<AnthropicModel>,
+}
+
+pub struct Anthropic {
+    client: Client,
+}
+
+impl Anthropic {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn endpoint(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        Ok(self
+            .client
+            .request(method, format!("{}/{}", ANTHROPIC_BASE_URL, path))
+            .header("x-api-key", required_env("ANTHROPIC_API_KEY")?)
+            .header("anthropic-version", ANTHROPIC_VERSION))
+    }
+
+    fn request(&self, req: &ChatRequest, stream: bool) -> Result<RequestBuilder> {
+        let (system, messages) = split_system(&req.messages);
+        // the breakpoint goes on the last message of the stable prefix; system messages
+        // were pulled out above, so count which side of the split the prefix ends on
+        let prefix = req.cache_prefix.min(req.messages.len());
+        let cached_system = req.messages[..prefix]
+            .iter()
+            .filter(|m| m.role == "system")
+            .count();
+        let cached_messages = prefix - cached_system;
+        let system = system
+            .map(|s| AnthropicContent::new(s, &[], cached_messages == 0 && cached_system > 0));
+        let messages = messages
+            .into_iter()
+            .enumerate()
+            .map(|(i, m)| AnthropicMessage {
+                role: m.role,
+                content: AnthropicContent::new(m.content, &m.images, i + 1 == cached_messages),
+            })
+            .collect();
+        Ok(self
+            .endpoint(Method::POST, "messages")?
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(&AnthropicRequest {
+                model: req.model.clone(),
+                max_tokens: req.params.max_tokens.unwrap_or(ANTHROPIC_MAX_TOKENS),
+                system,
+                messages,
+                stream,
+                temperature: req.params.temperature,
+                top_p: req.params.top_p,
+            }))
+    }
+}
+
+fn parse_stream_event(data: &str) -> Vec<StreamPiece> {
+    let ev = match serde_json::from_str::<AnthropicStreamEvent>(data) {
+        Ok(ev) => ev,
+        Err(_) => return Vec::new(),
+    };
+    match ev.kind.as_str() {
+        "message_stop" => return vec![StreamPiece::Done],
+        "error" => {
+            return vec![StreamPiece::Error(match ev.error {
+                Some(e) => format!("{}: {}", e.kind, e.message),
+                None => "error event".to_string(),
+            })]
+        }
+        _ => {}
+    }
+    let mut pieces = Vec::new();
+    let (text, stop_reason) = match ev.delta {
+        Some(d) => (d.text, d.stop_reason),
+        None => (None, None),
+    };
+    if let Some(text) = text {
+        pieces.push(StreamPiece::Text(text));
+    }
+    let usage = ev.usage.or(ev.message.and_then(|m| m.usage));
+    match (stop_reason, usage) {
+        (Some(reason), usage) => pieces.push(StreamPiece::Finished {
+            reason,
+            usage: usage.map(Usage::from),
+        }),
+        (None, Some(usage)) => pieces.push(StreamPiece::Usage(usage.into())),
+        (None, None) => {}
+    }
+    pieces
+}
+
+#[async_trait]
+impl Provider for Anthropic {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn default_model(&self) -> &str {
+        "claude-3-5-sonnet-latest"
+    }
+
+    fn chat_request(&self, req: &ChatRequest) -> Result<Option<RequestBuilder>> {
+        self.request(req, true).map(Some)
+    }
+
+    async fn stream_chat(&self, req: &ChatRequest, on_token: TokenSink<'_>) -> Result<Completion> {
+        match stream_sse(|| self.request(req, true), parse_stream_event, on_token).await? {
+            Some(acc) => Ok(acc),
+            None => {
+                let out = self.complete(req).await?;
+                on_token(&out.text);
+                Ok(out)
+            }
+        }
+    }
+
+    async fn complete(&self, req: &ChatRequest) -> Result<Completion> {
+        let res: AnthropicResponse = send(|| self.request(req, false)).await?.json().await?;
+        Ok(Completion {
+            text: res
+                .content
+                .into_iter()
+                .filter_map(|b| b.text)
+                .collect::<Vec<_>>()
+                .join(""),
+            usage: res.usage.map(Usage::from),
+            finish_reason: res.stop_reason,
+            ..Default::default()
+        })
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let res: AnthropicModelList = send(|| self.endpoint(Method::GET, "models"))
+            .await?
+            .json()
+            .await?;
+        Ok(res
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.id,
+                context_length: None,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,393 @@
+use super::{
+    send, split_system, ChatRequest, Completion, ImageData, ModelInfo, Provider, TokenSink, Usage,
+};
+use crate::aws_sigv4::{self, AwsCredentials, Signer};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+const BEDROCK_MAX_TOKENS: u32 = 4096;
+
+#[derive(Serialize, Deserialize)]
+struct BedrockContentBlock {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image: Option<BedrockImage>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BedrockImageSource {
+    bytes: String, // base64
+}
+
+#[derive(Serialize, Deserialize)]
+struct BedrockImage {
+    format: String, // "png" | "jpeg" | "gif" | "webp"
+    source: BedrockImageSource,
+}
+
+impl BedrockContentBlock {
+    fn text(text: String) -> Self {
+        Self {
+            text: Some(text),
+            image: None,
+        }
+    }
+
+    fn image(img: &ImageData) -> Self {
+        Self {
+            text: None,
+            image: Some(BedrockImage {
+                format: img.media_type.trim_start_matches("image/").to_string(),
+                source: BedrockImageSource {
+                    bytes: img.data.clone(),
+                },
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct BedrockMessage {
+    role: String,
+    content: Vec<BedrockContentBlock>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockInferenceConfig {
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockRequest {
+    messages: Vec<BedrockMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    system: Vec<BedrockContentBlock>,
+    inference_config: BedrockInferenceConfig,
+}
+
+#[derive(Deserialize)]
+struct BedrockOutput {
+    message: BedrockMessage,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+    // counted separately from input_tokens
+    #[serde(default)]
+    cache_read_input_tokens: u64,
+}
+
+impl From<BedrockUsage> for Usage {
+    fn from(u: BedrockUsage) -> Self {
+        Usage {
+            prompt_tokens: u.input_tokens + u.cache_read_input_tokens,
+            completion_tokens: u.output_tokens,
+            cached_tokens: u.cache_read_input_tokens,
+            reasoning_tokens: 0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockResponse {
+    output: BedrockOutput,
+    usage: Option<BedrockUsage>,
+    stop_reason: Option<String>,
+}
+
+/// `messageStop` event of ConverseStream.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockStopEvent {
+    stop_reason: Option<String>,
+}
+
+/// Trailing `metadata` event of ConverseStream.
+#[derive(Deserialize)]
+struct BedrockMetadataEvent {
+    usage: Option<BedrockUsage>,
+}
+
+#[derive(Deserialize)]
+struct BedrockDelta {
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BedrockDeltaEvent {
+    delta: Option<BedrockDelta>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FoundationModel {
+    model_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FoundationModels {
+    model_summaries: Vec<FoundationModel>,
+}
+
+/// One `application/vnd.amazon.eventstream` frame; only string headers are kept.
+struct EventFrame {
+    headers: Vec<(String, String)>,
+    payload: Vec<u8>,
+}
+
+impl EventFrame {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// The next `n` bytes of a frame's headers, which a truncated frame doesn't have.
+fn take<'a>(raw: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if raw.len() < n {
+        return Err(anyhow!("malformed event stream frame"));
+    }
+    let (head, rest) = raw.split_at(n);
+    *raw = rest;
+    Ok(head)
+}
+
+/// Pop one event stream frame off the front of `buf`, or `None` until a whole frame has arrived.
+fn decode_event_frame(buf: &mut Vec<u8>) -> Result<Option<EventFrame>> {
+    let be32 = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize;
+    if buf.len() < 12 {
+        return Ok(None);
+    }
+    let total = be32(&buf[0..4]);
+    let headers_len = be32(&buf[4..8]);
+    if total < 16 + headers_len {
+        return Err(anyhow!("malformed event stream frame"));
+    }
+    if buf.len() < total {
+        return Ok(None);
+    }
+    // prelude and message CRCs are not verified; TLS already guards integrity
+    let frame: Vec<u8> = buf.drain(..total).collect();
+    let mut raw = frame
+        .get(12..12 + headers_len)
+        .ok_or_else(|| anyhow!("malformed event stream frame"))?;
+    let payload = frame[12 + headers_len..total - 4].to_vec();
+
+    let mut headers = Vec::new();
+    while !raw.is_empty() {
+        let name_len = take(&mut raw, 1)?[0] as usize;
+        let name = String::from_utf8_lossy(take(&mut raw, name_len)?).into_owned();
+        let kind = take(&mut raw, 1)?[0];
+        let value_len = match kind {
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 | 8 => 8,
+            9 => 16,
+            6 | 7 => {
+                let len = take(&mut raw, 2)?;
+                u16::from_be_bytes([len[0], len[1]]) as usize
+            }
+            _ => return Err(anyhow!("unknown event stream header type {}", kind)),
+        };
+        let value = take(&mut raw, value_len)?;
+        if kind == 7 {
+            headers.push((name, String::from_utf8_lossy(value).into_owned()));
+        }
+    }
+    Ok(Some(EventFrame { headers, payload }))
+}
+
+fn signer(region: String) -> Result<Signer> {
+    Ok(Signer {
+        creds: AwsCredentials::from_env()?,
+        service: "bedrock".into(),
+        region,
+    })
+}
+
+pub struct Bedrock {
+    client: Client,
+}
+
+impl Bedrock {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Bedrock authenticates with SigV4 over the exact payload bytes.
+    fn request(&self, req: &ChatRequest, stream: bool) -> Result<RequestBuilder> {
+        let (system, messages) = split_system(&req.messages);
+        let body = serde_json::to_vec(&BedrockRequest {
+            messages: messages
+                .into_iter()
+                .map(|m| {
+                    let mut content: Vec<BedrockContentBlock> =
+                        m.images.iter().map(BedrockContentBlock::image).collect();
+                    content.push(BedrockContentBlock::text(m.content));
+                    BedrockMessage {
+                        role: m.role,
+                        content,
+                    }
+                })
+                .collect(),
+            system: system
+                .map(|text| vec![BedrockContentBlock::text(text)])
+                .unwrap_or_default(),
+            inference_config: BedrockInferenceConfig {
+                max_tokens: req.params.max_tokens.unwrap_or(BEDROCK_MAX_TOKENS),
+                temperature: req.params.temperature,
+                top_p: req.params.top_p,
+            },
+        })?;
+
+        let region = aws_sigv4::region();
+        let host = format!("bedrock-runtime.{}.amazonaws.com", region);
+        let path = format!(
+            "/model/{}/{}",
+            aws_sigv4::uri_encode(&req.model),
+            if stream {
+                "converse-stream"
+            } else {
+                "converse"
+            }
+        );
+        let signer = signer(region)?;
+
+        let mut rb = self
+            .client
+            .post(format!("https://{}{}", host, path))
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        for (k, v) in signer.sign("POST", &host, &path, &[], &body) {
+            rb = rb.header(k, v);
+        }
+        Ok(rb.body(body))
+    }
+}
+
+#[async_trait]
+impl Provider for Bedrock {
+    fn name(&self) -> &str {
+        "bedrock"
+    }
+
+    fn default_model(&self) -> &str {
+        "anthropic.claude-3-5-sonnet-20240620-v1:0"
+    }
+
+    fn chat_request(&self, req: &ChatRequest) -> Result<Option<RequestBuilder>> {
+        self.request(req, true).map(Some)
+    }
+
+    /// ConverseStream uses AWS binary event framing rather than SSE.
+    async fn stream_chat(&self, req: &ChatRequest, on_token: TokenSink<'_>) -> Result<Completion> {
+        let res = send(|| self.request(req, true)).await?;
+        let mut body = res.bytes_stream();
+        let mut buf: Vec<u8> = Vec::new();
+        let mut acc = Completion::default();
+
+        // usage arrives in a metadata event after messageStop, so read to the end
+        while let Some(chunk) = body.next().await {
+            buf.extend_from_slice(&chunk?);
+            while let Some(frame) = decode_event_frame(&mut buf)? {
+                if frame.header(":message-type") == Some("exception") {
+                    return Err(anyhow!(
+                        "bedrock {}: {}",
+                        frame.header(":exception-type").unwrap_or("exception"),
+                        String::from_utf8_lossy(&frame.payload)
+                    ));
+                }
+                match frame.header(":event-type") {
+                    Some("contentBlockDelta") => {
+                        if let Some(piece) =
+                            serde_json::from_slice::<BedrockDeltaEvent>(&frame.payload)
+                                .ok()
+                                .and_then(|ev| ev.delta)
+                                .and_then(|d| d.text)
+                        {
+                            on_token(&piece);
+                            acc.text.push_str(&piece);
+                        }
+                    }
+                    Some("messageStop") => {
+                        acc.finish_reason =
+                            serde_json::from_slice::<BedrockStopEvent>(&frame.payload)
+                                .ok()
+                                .and_then(|ev| ev.stop_reason);
+                    }
+                    Some("metadata") => {
+                        if let Some(usage) =
+                            serde_json::from_slice::<BedrockMetadataEvent>(&frame.payload)
+                                .ok()
+                                .and_then(|ev| ev.usage)
+                        {
+                            acc.add_usage(usage.into());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(acc)
+    }
+
+    async fn complete(&self, req: &ChatRequest) -> Result<Completion> {
+        let res: BedrockResponse = send(|| self.request(req, false)).await?.json().await?;
+        Ok(Completion {
+            text: res
+                .output
+                .message
+                .content
+                .into_iter()
+                .filter_map(|b| b.text)
+                .collect::<Vec<_>>()
+                .join(""),
+            usage: res.usage.map(Usage::from),
+            finish_reason: res.stop_reason,
+            ..Default::default()
+        })
+    }
+
+    /// Model listing lives on the control-plane host, not bedrock-runtime.
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let region = aws_sigv4::region();
+        let host = format!("bedrock.{}.amazonaws.com", region);
+        let path = "/foundation-models";
+        let signer = signer(region)?;
+        let build = || {
+            // re-signed per attempt so retries carry a fresh timestamp
+            let mut rb = self.client.get(format!("https://{}{}", host, path));
+            for (k, v) in signer.sign("GET", &host, path, &[], b"") {
+                rb = rb.header(k, v);
+            }
+            Ok(rb)
+        };
+        let res: FoundationModels = send(build).await?.json().await?;
+        Ok(res
+            .model_summaries
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.model_id,
+                context_length: None,
+            })
+            .collect())
+    }
+}
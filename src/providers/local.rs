@@ -0,0 +1,253 @@
+use super::{ChatMessage, ChatRequest, Completion, Provider, SamplingParams, TokenSink, Usage};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use candle_core::quantized::gguf_file;
+use candle_core::{Device, Tensor};
+use candle_transformers::generation::LogitsProcessor;
+use candle_transformers::models::quantized_llama::ModelWeights;
+use candle_transformers::utils::apply_repeat_penalty;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokenizers::Tokenizer;
+use tokio::sync::mpsc;
+
+const LOCAL_MAX_TOKENS: u32 = 1024;
+// llama.cpp's defaults, so answers feel the same as under its CLI
+const LOCAL_TEMPERATURE: f64 = 0.8;
+const REPEAT_PENALTY: f32 = 1.1;
+const REPEAT_LAST_N: usize = 64;
+// used when the GGUF carries no chat template of its own
+const CHATML_TEMPLATE: &str = "{% for m in messages %}<|im_start|>{{ m.role }}\n{{ m.content }}<|im_end|>\n{% endfor %}{% if add_generation_prompt %}<|im_start|>assistant\n{% endif %}";
+// end-of-turn markers of the common chat formats, beside the model's own EOS
+const STOP_TOKENS: &[&str] = &[
+    "</s>",
+    "<|eot_id|>",
+    "<|end_of_text|>",
+    "<|im_end|>",
+    "<|end|>",
+];
+
+/// A GGUF model loaded into memory, ready to generate.
+struct Loaded {
+    weights: ModelWeights,
+    tokenizer: Tokenizer,
+    template: String,
+    bos: String,
+    eos: String,
+    stop: Vec<u32>,
+}
+
+/// In-process inference on a quantized llama-family GGUF model; `AI_MODEL` is the file path.
+pub struct Local {
+    // loading takes seconds, so keep the last model around between turns
+    loaded: Mutex<Option<(String, Arc<Loaded>)>>,
+}
+
+impl Local {
+    pub fn new() -> Self {
+        Self {
+            loaded: Mutex::new(None),
+        }
+    }
+
+    async fn load(&self, path: &str) -> Result<Arc<Loaded>> {
+        if let Some((p, loaded)) = &*self.loaded.lock().unwrap() {
+            if p == path {
+                return Ok(loaded.clone());
+            }
+        }
+        let owned = path.to_string();
+        let loaded = Arc::new(tokio::task::spawn_blocking(move || load_model(&owned)).await??);
+        *self.loaded.lock().unwrap() = Some((path.to_string(), loaded.clone()));
+        Ok(loaded)
+    }
+
+    /// Run generation on a blocking thread, forwarding pieces as they are decoded.
+    async fn generate(&self, req: &ChatRequest, on_token: TokenSink<'_>) -> Result<Completion> {
+        let loaded = self.load(&req.model).await?;
+        let prompt = render_prompt(&loaded, &req.messages)?;
+        let params = req.params.clone();
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        // a closed channel means the caller stopped listening (e.g. Ctrl-C), which ends generation
+        let worker = tokio::task::spawn_blocking(move || {
+            run(&loaded, &prompt, &params, |piece| tx.send(piece).is_ok())
+        });
+
+        let mut acc = Completion::default();
+        while let Some(piece) = rx.recv().await {
+            on_token(&piece);
+            acc.text.push_str(&piece);
+        }
+        acc.usage = Some(worker.await??);
+        Ok(acc)
+    }
+}
+
+fn tokenizer_path(model: &Path) -> PathBuf {
+    match env::var("LOCAL_TOKENIZER") {
+        Ok(p) if !p.trim().is_empty() => PathBuf::from(p),
+        _ => model.with_file_name("tokenizer.json"),
+    }
+}
+
+fn load_model(path: &str) -> Result<Loaded> {
+    let path = Path::new(path);
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let content = gguf_file::Content::read(&mut file)
+        .map_err(|e| anyhow!("{}: not a GGUF file ({})", path.display(), e))?;
+    let meta_str = |key: &str| {
+        content
+            .metadata
+            .get(key)
+            .and_then(|v| v.to_string().ok())
+            .cloned()
+    };
+    let arch = meta_str("general.architecture").unwrap_or_default();
+    if arch != "llama" {
+        return Err(anyhow!(
+            "{}: architecture '{}' not supported (llama-family GGUF only)",
+            path.display(),
+            arch
+        ));
+    }
+    let template = meta_str("tokenizer.chat_template").unwrap_or_else(|| CHATML_TEMPLATE.into());
+    let eos_id = content
+        .metadata
+        .get("tokenizer.ggml.eos_token_id")
+        .and_then(|v| v.to_u32().ok());
+    let bos_id = content
+        .metadata
+        .get("tokenizer.ggml.bos_token_id")
+        .and_then(|v| v.to_u32().ok());
+    let weights = ModelWeights::from_gguf(content, &mut file, &Device::Cpu)?;
+
+    let tok_path = tokenizer_path(path);
+    let tokenizer = Tokenizer::from_file(&tok_path)
+        .map_err(|e| anyhow!("loading tokenizer {}: {}", tok_path.display(), e))?;
+    let name = |id: Option<u32>| {
+        id.and_then(|id| tokenizer.id_to_token(id))
+            .unwrap_or_default()
+    };
+    let (bos, eos) = (name(bos_id), name(eos_id));
+    let mut stop: Vec<u32> = STOP_TOKENS
+        .iter()
+        .filter_map(|t| tokenizer.token_to_id(t))
+        .collect();
+    stop.extend(eos_id);
+
+    Ok(Loaded {
+        weights,
+        tokenizer,
+        template,
+        bos,
+        eos,
+        stop,
+    })
+}
+
+/// Format the conversation with the model's own Jinja chat template.
+fn render_prompt(loaded: &Loaded, messages: &[ChatMessage]) -> Result<String> {
+    let mut env = minijinja::Environment::new();
+    // templates are written for Python's jinja2 and call str methods like .strip()
+    env.set_unknown_method_callback(minijinja_contrib::pycompat::unknown_method_callback);
+    env.add_function(
+        "raise_exception",
+        |msg: String| -> Result<String, minijinja::Error> {
+            Err(minijinja::Error::new(
+                minijinja::ErrorKind::InvalidOperation,
+                msg,
+            ))
+        },
+    );
+    env.add_template("chat", &loaded.template)?;
+    Ok(env.get_template("chat")?.render(minijinja::context! {
+        messages => messages,
+        add_generation_prompt => true,
+        bos_token => &loaded.bos,
+        eos_token => &loaded.eos,
+    })?)
+}
+
+/// Generate until a stop token, the token budget, or `emit` returning false.
+fn run(
+    loaded: &Loaded,
+    prompt: &str,
+    params: &SamplingParams,
+    mut emit: impl FnMut(String) -> bool,
+) -> Result<Usage> {
+    let tokenizer = &loaded.tokenizer;
+    // the rendered template already spells out BOS and friends
+    let prompt_tokens = tokenizer
+        .encode(prompt, false)
+        .map_err(|e| anyhow!("tokenizing prompt: {}", e))?
+        .get_ids()
+        .to_vec();
+    let mut model = loaded.weights.clone();
+    let mut sampler = LogitsProcessor::new(
+        params.seed.unwrap_or_else(rand::random),
+        Some(params.temperature.map_or(LOCAL_TEMPERATURE, f64::from)),
+        params.top_p.map(f64::from),
+    );
+    let max_tokens = params.max_tokens.unwrap_or(LOCAL_MAX_TOKENS) as usize;
+
+    let mut all = prompt_tokens.clone();
+    let mut generated: Vec<u32> = Vec::new();
+    let mut printed = 0usize;
+    let mut input = prompt_tokens.clone();
+    while generated.len() < max_tokens {
+        let pos = all.len() - input.len();
+        let x = Tensor::new(input.as_slice(), &Device::Cpu)?.unsqueeze(0)?;
+        let logits = model.forward(&x, pos)?.squeeze(0)?;
+        let start = all.len().saturating_sub(REPEAT_LAST_N);
+        let logits = apply_repeat_penalty(&logits, REPEAT_PENALTY, &all[start..])?;
+        let next = sampler.sample(&logits)?;
+        if loaded.stop.contains(&next) {
+            break;
+        }
+        all.push(next);
+        generated.push(next);
+        input = vec![next];
+
+        // decode the whole answer so multi-token characters come out whole
+        let text = tokenizer
+            .decode(&generated, true)
+            .map_err(|e| anyhow!("decoding: {}", e))?;
+        if text.ends_with('\u{fffd}') {
+            continue;
+        }
+        if let Some(piece) = text.get(printed..).filter(|p| !p.is_empty()) {
+            if !emit(piece.to_string()) {
+                break;
+            }
+            printed = text.len();
+        }
+    }
+
+    Ok(Usage {
+        prompt_tokens: prompt_tokens.len() as u64,
+        completion_tokens: generated.len() as u64,
+        cached_tokens: 0,
+        reasoning_tokens: 0,
+    })
+}
+
+#[async_trait]
+impl Provider for Local {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn default_model(&self) -> &str {
+        "model.gguf"
+    }
+
+    async fn stream_chat(&self, req: &ChatRequest, on_token: TokenSink<'_>) -> Result<Completion> {
+        self.generate(req, on_token).await
+    }
+
+    async fn complete(&self, req: &ChatRequest) -> Result<Completion> {
+        self.generate(req, &mut |_| {}).await
+    }
+}
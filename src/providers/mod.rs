@@ -0,0 +1,706 @@
+use crate::retry::{self, BackoffPolicy};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use futures_util::StreamExt;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use reqwest_eventsource::EventSource;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+
+mod anthropic;
+mod bedrock;
+#[cfg(feature = "local")]
+mod local;
+mod ollama;
+mod openai;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChatMessage {
+    pub role: String, // "user" | "assistant" | "system" | "tool"
+    // null when an OpenAI assistant message only carries tool calls
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub content: String,
+    /// Calls the assistant asked for instead of (or before) answering.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// On a "tool" message, the call this is the result of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Pictures sent along with a user message, for vision-capable models.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageData>,
+}
+
+/// An image file, base64-encoded the way every provider's API takes it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ImageData {
+    pub media_type: String, // "image/png"
+    pub data: String,
+}
+
+impl ImageData {
+    pub fn load(path: &str) -> Result<Self> {
+        let ext = std::path::Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let media_type = match ext.as_str() {
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            _ => return Err(anyhow!("{}: not a png, jpeg, gif or webp image", path)),
+        };
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path))?;
+        Ok(Self {
+            media_type: media_type.to_string(),
+            data: base64::engine::general_purpose::STANDARD.encode(bytes),
+        })
+    }
+
+    /// The `data:` URL form OpenAI-style APIs expect.
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+fn null_as_empty<'de, D: serde::Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    Ok(Option::<String>::deserialize(d)?.unwrap_or_default())
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            images: Vec::new(),
+        }
+    }
+}
+
+/// A function the model may call, described by a JSON schema of its arguments.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FunctionCall {
+    pub name: String,
+    pub arguments: String, // JSON text, as generated
+}
+
+/// One call requested by the model (OpenAI's wire shape).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+fn function_type() -> String {
+    "function".into()
+}
+
+/// A streamed fragment of the `index`th tool call; later fragments extend the arguments.
+pub struct ToolCallDelta {
+    pub index: usize,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
+/// Generation controls; `None` leaves the provider's default in place.
+#[derive(Clone, Default, Serialize)]
+pub struct SamplingParams {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// How hard reasoning models think before answering: minimal, low, medium, high.
+    pub reasoning_effort: Option<String>,
+    /// JSON schema the answer must follow, for providers that can enforce one.
+    pub schema: Option<serde_json::Value>,
+    /// Sampling seed, so repeated requests give (mostly) the same answer.
+    pub seed: Option<u64>,
+}
+
+/// Everything a provider needs to answer one turn.
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub params: SamplingParams,
+    /// How many leading messages are unchanged since the previous turn (0 = none);
+    /// providers with prompt caching mark the end of this prefix as cacheable.
+    pub cache_prefix: usize,
+    /// Functions the model may call; empty disables tool calling.
+    pub tools: Vec<ToolSpec>,
+}
+
+/// Token accounting reported by the provider.
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Part of `prompt_tokens` served from the provider's prompt cache.
+    pub cached_tokens: u64,
+    /// Part of `completion_tokens` spent on hidden reasoning.
+    pub reasoning_tokens: u64,
+}
+
+impl Usage {
+    /// Streams report usage piecemeal (e.g. input at start, output at end); keep the largest of each.
+    pub fn merge(self, other: Usage) -> Usage {
+        Usage {
+            prompt_tokens: self.prompt_tokens.max(other.prompt_tokens),
+            completion_tokens: self.completion_tokens.max(other.completion_tokens),
+            cached_tokens: self.cached_tokens.max(other.cached_tokens),
+            reasoning_tokens: self.reasoning_tokens.max(other.reasoning_tokens),
+        }
+    }
+}
+
+/// Totals across the several requests of one tool-using turn.
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cached_tokens += other.cached_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+    }
+}
+
+/// A finished answer and what the provider reported about it.
+#[derive(Default)]
+pub struct Completion {
+    pub text: String,
+    pub usage: Option<Usage>,
+    /// Non-empty when the model wants tool results before it answers.
+    pub tool_calls: Vec<ToolCall>,
+    /// Backend configuration the answer came from (OpenAI's `system_fingerprint`);
+    /// seeded answers are only comparable while it stays the same.
+    pub fingerprint: Option<String>,
+    /// Which provider and model answered; set by the agent, which knows the fallback it took.
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    /// Why the answer ended (`stop`, `length`, `end_turn`...), in the provider's own words.
+    pub finish_reason: Option<String>,
+}
+
+impl Completion {
+    pub fn add_tool_delta(&mut self, delta: ToolCallDelta) {
+        while self.tool_calls.len() <= delta.index {
+            self.tool_calls.push(ToolCall {
+                id: String::new(),
+                kind: function_type(),
+                function: FunctionCall::default(),
+            });
+        }
+        let call = &mut self.tool_calls[delta.index];
+        if let Some(id) = delta.id {
+            call.id = id;
+        }
+        if let Some(name) = delta.name {
+            call.function.name.push_str(&name);
+        }
+        if let Some(args) = delta.arguments {
+            call.function.arguments.push_str(&args);
+        }
+    }
+
+    pub fn add_usage(&mut self, usage: Usage) {
+        self.usage = Some(match self.usage {
+            Some(u) => u.merge(usage),
+            None => usage,
+        });
+    }
+
+    pub fn finish(&mut self, reason: String, usage: Option<Usage>) {
+        self.finish_reason = Some(reason);
+        if let Some(usage) = usage {
+            self.add_usage(usage);
+        }
+    }
+}
+
+/// An entry from a provider's model listing.
+pub struct ModelInfo {
+    pub id: String,
+    pub context_length: Option<u64>,
+}
+
+/// Callback receiving each streamed piece of the answer as it arrives.
+pub type TokenSink<'a> = &'a mut (dyn FnMut(&str) + Send);
+
+#[async_trait]
+pub trait Provider: Send + Sync {
+    fn name(&self) -> &str;
+    fn default_model(&self) -> &str;
+    /// Stream an answer through `on_token` and return the full completion.
+    async fn stream_chat(&self, req: &ChatRequest, on_token: TokenSink<'_>) -> Result<Completion>;
+    /// Single non-streaming completion.
+    async fn complete(&self, req: &ChatRequest) -> Result<Completion>;
+    /// The HTTP request `stream_chat` sends for `req`, for `--dry-run` to show; `None` if the
+    /// answer is made in-process.
+    fn chat_request(&self, _req: &ChatRequest) -> Result<Option<RequestBuilder>> {
+        Ok(None)
+    }
+    /// Models the provider offers, for discovering what to put in `AI_MODEL`.
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        Err(anyhow!("{} does not support listing models", self.name()))
+    }
+    /// Embedding model used when `AI_EMBED_MODEL` is unset; `None` if the provider has no embeddings.
+    fn embedding_model(&self) -> Option<&str> {
+        None
+    }
+    /// One embedding vector per text, in order.
+    async fn embed(&self, _model: &str, _texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Err(anyhow!("{} does not support embeddings", self.name()))
+    }
+    /// Speech-to-text for an audio file, for prompts given as a recording.
+    async fn transcribe(&self, _path: &Path) -> Result<String> {
+        Err(anyhow!("{} does not support transcription", self.name()))
+    }
+    /// Rough token estimate (see `tokens::estimate`); providers with a real tokenizer can override.
+    fn count_tokens(&self, messages: &[ChatMessage]) -> usize {
+        messages
+            .iter()
+            .map(|m| crate::tokens::estimate(&m.content) + 4)
+            .sum()
+    }
+}
+
+/// Providers by name, as selected with `AI_PROVIDER`.
+pub struct Registry {
+    providers: HashMap<String, Arc<dyn Provider>>,
+}
+
+impl Registry {
+    /// Every built-in provider, each with the HTTP client `client` builds for its name.
+    pub fn with_defaults(client: impl Fn(&str) -> Result<Client>) -> Result<Self> {
+        let mut reg = Self {
+            providers: HashMap::new(),
+        };
+        reg.register(Arc::new(openai::OpenAiCompatible::openai(client(
+            "openai",
+        )?)));
+        reg.register(Arc::new(openai::OpenAiCompatible::azure(client("azure")?)));
+        reg.register(Arc::new(openai::OpenAiCompatible::openrouter(client(
+            "openrouter",
+        )?)));
+        reg.register(Arc::new(openai::OpenAiCompatible::mistral(client(
+            "mistral",
+        )?)));
+        reg.register(Arc::new(openai::OpenAiCompatible::groq(client("groq")?)));
+        reg.register(Arc::new(anthropic::Anthropic::new(client("anthropic")?)));
+        reg.register(Arc::new(ollama::Ollama::new(client("ollama")?)));
+        reg.register(Arc::new(bedrock::Bedrock::new(client("bedrock")?)));
+        #[cfg(feature = "local")]
+        reg.register(Arc::new(local::Local::new()));
+        Ok(reg)
+    }
+
+    pub fn register(&mut self, provider: Arc<dyn Provider>) {
+        self.providers.insert(provider.name().to_string(), provider);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.providers.contains_key(name)
+    }
+
+    /// The provider called `name`, with its key taken from the keychain if it is kept there.
+    pub fn get(&self, name: &str) -> Result<Arc<dyn Provider>> {
+        let provider = self.providers.get(name).cloned();
+        if provider.is_some() {
+            if let Err(e) = crate::keychain::load(name) {
+                debug!("no {} key from the keychain: {:#}", name, e);
+            }
+        }
+        provider.ok_or_else(|| {
+            let mut known: Vec<&str> = self.providers.keys().map(|k| k.as_str()).collect();
+            known.sort();
+            anyhow!(
+                "Unknown provider '{}' (available: {}).",
+                name,
+                known.join(", ")
+            )
+        })
+    }
+}
+
+/// Part of what a stream message contributes to the answer; one message can carry several.
+pub enum StreamPiece {
+    Text(String),
+    /// Thinking that precedes the answer; kept out of it and only shown with `AI_SHOW_REASONING=1`.
+    Reasoning(String),
+    ToolCall(ToolCallDelta),
+    Usage(Usage),
+    Fingerprint(String),
+    /// Why the answer ended, with the usage when the same message reports it.
+    Finished {
+        reason: String,
+        usage: Option<Usage>,
+    },
+    /// The server reported an error partway through, e.g. that it is overloaded.
+    Error(String),
+    Done,
+}
+
+/// Provider to use when `AI_PROVIDER` is unset, judged by which credentials are present
+/// (in the environment, then the keychain, or a running Ollama), along with what gave it away.
+pub fn detect_provider() -> Option<(&'static str, &'static str)> {
+    const KEYS: &[(&str, &str)] = &[
+        ("OPENAI_API_KEY", "openai"),
+        ("OPENAI_BASE_URL", "openai"),
+        ("ANTHROPIC_API_KEY", "anthropic"),
+        ("OPENROUTER_API_KEY", "openrouter"),
+        ("MISTRAL_API_KEY", "mistral"),
+        ("GROQ_API_KEY", "groq"),
+        ("AZURE_OPENAI_API_KEY", "azure"),
+    ];
+    let found = KEYS
+        .iter()
+        .find(|(var, _)| env::var(var).is_ok_and(|v| !v.trim().is_empty()));
+    match found {
+        Some((var, provider)) => Some((provider, var)),
+        None => match crate::keychain::first_kept(KEYS.iter().map(|(_, p)| *p)) {
+            Some(provider) => Some((provider, "a key in the system keychain")),
+            None if ollama::is_reachable() => Some(("ollama", "a running Ollama server")),
+            None => None,
+        },
+    }
+}
+
+/// A variable a provider can't do without, such as its API key, isn't set.
+#[derive(Debug)]
+pub struct MissingVar(pub String);
+
+impl std::fmt::Display for MissingVar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} not set", self.0)
+    }
+}
+
+impl std::error::Error for MissingVar {}
+
+pub fn required_env(var: &str) -> Result<String> {
+    env::var(var).map_err(|_| MissingVar(var.to_string()).into())
+}
+
+/// Split system messages out of the conversation for APIs that want them separately.
+pub fn split_system(messages: &[ChatMessage]) -> (Option<String>, Vec<ChatMessage>) {
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.as_str())
+        .collect();
+    let rest = messages
+        .iter()
+        .filter(|m| m.role != "system")
+        .cloned()
+        .collect();
+    let system = if system.is_empty() {
+        None
+    } else {
+        Some(system.join("\n\n"))
+    };
+    (system, rest)
+}
+
+/// Reasoning goes to stderr so it never mixes into the answer or memory.
+fn show_reasoning(piece: &str, thinking: &mut bool) {
+    if matches!(
+        env::var("AI_SHOW_REASONING").as_deref(),
+        Ok("1") | Ok("true")
+    ) {
+        crate::spinner::stop();
+        eprint!("{}", piece);
+        *thinking = true;
+    }
+}
+
+/// Break the line after shown reasoning once the answer starts.
+fn end_reasoning(thinking: &mut bool) {
+    if std::mem::take(thinking) {
+        eprintln!();
+    }
+}
+
+/// An error status, with the response body: that is where providers explain what went wrong
+/// (bad key, unknown model...).
+#[derive(Debug)]
+pub struct StatusError {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.body.is_empty() {
+            write!(f, "HTTP {}", self.status)
+        } else {
+            write!(f, "HTTP {}: {}", self.status, self.body)
+        }
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+async fn status_error(status: StatusCode, res: Response) -> anyhow::Error {
+    let body = res.text().await.unwrap_or_default();
+    StatusError {
+        status,
+        body: body.trim().to_string(),
+    }
+    .into()
+}
+
+/// Headers that carry keys or signatures, whose values `--dry-run` leaves out.
+fn is_secret_header(name: &str) -> bool {
+    ["authorization", "key", "token", "secret", "signature"]
+        .iter()
+        .any(|part| name.contains(part))
+}
+
+/// Long strings without a space are images and the like, base64-encoded: left out of what
+/// `--dry-run` prints, they would fill the screen.
+fn elide_blobs(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(s) if s.len() > 256 && !s.contains(char::is_whitespace) => {
+            *s = format!("({} characters left out)", s.len());
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(elide_blobs),
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(elide_blobs),
+        _ => {}
+    }
+}
+
+/// Print the request `builder` would send, with the values of key headers left out, for
+/// `--dry-run`.
+pub fn print_request(builder: &RequestBuilder) -> Result<()> {
+    let req = builder
+        .try_clone()
+        .ok_or_else(|| anyhow!("the request can't be shown: its body is streamed"))?
+        .build()?;
+    println!("{} {}", req.method(), req.url());
+    for (name, value) in req.headers() {
+        let value = value.to_str().unwrap_or("(not text)");
+        let shown = match value.split_once(' ') {
+            _ if !is_secret_header(name.as_str()) => value.to_string(),
+            Some((scheme, _)) => format!("{} (redacted)", scheme),
+            None => "(redacted)".to_string(),
+        };
+        println!("{}: {}", name, shown);
+    }
+    if let Some(bytes) = req.body().and_then(|b| b.as_bytes()) {
+        println!();
+        match serde_json::from_slice::<serde_json::Value>(bytes) {
+            Ok(mut body) => {
+                elide_blobs(&mut body);
+                println!("{:#}", body);
+            }
+            Err(_) => println!("({} bytes, not JSON)", bytes.len()),
+        }
+    }
+    Ok(())
+}
+
+/// Log a request about to go out: its method and URL (never its headers, which carry the
+/// keys), and with `-vv` the size of its body.
+fn log_request(builder: &RequestBuilder, attempt: usize) {
+    if !tracing::enabled!(tracing::Level::INFO) {
+        return;
+    }
+    // a streamed body can't be copied, and then there is nothing to say about it
+    let Some(req) = builder.try_clone().and_then(|b| b.build().ok()) else {
+        return;
+    };
+    info!("{} {} (attempt {})", req.method(), req.url(), attempt);
+    if let Some(bytes) = req.body().and_then(|b| b.as_bytes()) {
+        debug!("request body: {} bytes", bytes.len());
+    }
+}
+
+/// Send a request, retrying retryable statuses per the backoff policy.
+pub async fn send(build: impl Fn() -> Result<RequestBuilder>) -> Result<Response> {
+    let policy = BackoffPolicy::from_env();
+    let mut attempts = 0usize;
+    loop {
+        attempts += 1;
+        let builder = build()?;
+        log_request(&builder, attempts);
+        let started = Instant::now();
+        let res = builder.send().await?;
+        let status = res.status();
+        debug!(
+            "{} after {:.2?}, {} bytes",
+            status,
+            started.elapsed(),
+            res.content_length()
+                .map_or("unknown".to_string(), |n| n.to_string())
+        );
+        if status.is_success() {
+            return Ok(res);
+        }
+        if retry::is_retryable(status) && attempts <= policy.max_retries {
+            let backoff = policy.delay(attempts, retry::retry_after(res.headers()));
+            warn!("{} from server, retrying in {:?}...", status, backoff);
+            sleep(backoff).await;
+            continue;
+        }
+        return Err(status_error(status, res).await);
+    }
+}
+
+/// Stream an SSE response, retrying retryable statuses per the backoff policy.
+/// Returns `None` once retries are exhausted so the caller can fall back to non-stream.
+pub async fn stream_sse(
+    build: impl Fn() -> Result<RequestBuilder>,
+    parse: impl Fn(&str) -> Vec<StreamPiece>,
+    on_token: TokenSink<'_>,
+) -> Result<Option<Completion>> {
+    let policy = BackoffPolicy::from_env();
+    let mut attempts = 0usize;
+
+    'attempt: loop {
+        attempts += 1;
+        let mut acc = Completion::default();
+        let mut thinking = false;
+        let builder = build()?;
+        log_request(&builder, attempts);
+        let started = Instant::now();
+        let (mut events, mut bytes, mut first) = (0usize, 0usize, true);
+        let mut es = EventSource::new(builder)?;
+
+        'events: while let Some(event) = es.next().await {
+            match event {
+                Ok(reqwest_eventsource::Event::Open) => {
+                    debug!("stream open after {:.2?}", started.elapsed());
+                }
+                Ok(reqwest_eventsource::Event::Message(msg)) => {
+                    events += 1;
+                    bytes += msg.data.len();
+                    for piece in parse(msg.data.trim()) {
+                        match piece {
+                            StreamPiece::Text(piece) => {
+                                if std::mem::take(&mut first) {
+                                    debug!("first token after {:.2?}", started.elapsed());
+                                }
+                                end_reasoning(&mut thinking);
+                                on_token(&piece);
+                                acc.text.push_str(&piece);
+                            }
+                            StreamPiece::Reasoning(piece) => show_reasoning(&piece, &mut thinking),
+                            StreamPiece::ToolCall(delta) => acc.add_tool_delta(delta),
+                            StreamPiece::Usage(usage) => acc.add_usage(usage),
+                            StreamPiece::Fingerprint(fp) => acc.fingerprint = Some(fp),
+                            StreamPiece::Finished { reason, usage } => acc.finish(reason, usage),
+                            StreamPiece::Error(message) => {
+                                es.close();
+                                return Err(anyhow!("stream error: {}", message));
+                            }
+                            StreamPiece::Done => {
+                                es.close();
+                                break 'events;
+                            }
+                        }
+                    }
+                }
+                Err(reqwest_eventsource::Error::StreamEnded) => break,
+                Err(reqwest_eventsource::Error::InvalidStatusCode(status, res)) => {
+                    es.close();
+                    if !retry::is_retryable(status) {
+                        return Err(status_error(status, res).await);
+                    }
+                    if attempts > policy.max_retries {
+                        warn!(
+                            "stream failed after {} attempts; falling back to non-stream.",
+                            attempts - 1
+                        );
+                        return Ok(None);
+                    }
+                    let backoff = policy.delay(attempts, retry::retry_after(res.headers()));
+                    warn!("stream {}, retrying in {:?}...", status, backoff);
+                    sleep(backoff).await;
+                    continue 'attempt;
+                }
+                Err(e) => {
+                    es.close();
+                    return Err(anyhow!("stream error: {}", e));
+                }
+            }
+        }
+
+        debug!(
+            "stream done after {:.2?}: {} events, {} bytes",
+            started.elapsed(),
+            events,
+            bytes
+        );
+        return Ok(Some(acc));
+    }
+}
+
+/// Stream a newline-delimited JSON response body.
+pub async fn stream_lines(
+    res: Response,
+    parse: impl Fn(&str) -> Vec<StreamPiece>,
+    on_token: TokenSink<'_>,
+) -> Result<Completion> {
+    let mut body = res.bytes_stream();
+    let mut buf: Vec<u8> = Vec::new();
+    let mut acc = Completion::default();
+    let mut thinking = false;
+    let started = Instant::now();
+    let (mut bytes, mut first) = (0usize, true);
+
+    'read: while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        bytes += chunk.len();
+        buf.extend_from_slice(&chunk);
+        while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            for piece in parse(line.trim()) {
+                match piece {
+                    StreamPiece::Text(piece) => {
+                        if std::mem::take(&mut first) {
+                            debug!("first token after {:.2?}", started.elapsed());
+                        }
+                        end_reasoning(&mut thinking);
+                        on_token(&piece);
+                        acc.text.push_str(&piece);
+                    }
+                    StreamPiece::Reasoning(piece) => show_reasoning(&piece, &mut thinking),
+                    StreamPiece::ToolCall(delta) => acc.add_tool_delta(delta),
+                    StreamPiece::Usage(usage) => acc.add_usage(usage),
+                    StreamPiece::Fingerprint(fp) => acc.fingerprint = Some(fp),
+                    StreamPiece::Finished { reason, usage } => acc.finish(reason, usage),
+                    StreamPiece::Error(message) => {
+                        return Err(anyhow!("stream error: {}", message))
+                    }
+                    StreamPiece::Done => break 'read,
+                }
+            }
+        }
+    }
+    debug!(
+        "stream done after {:.2?}: {} bytes",
+        started.elapsed(),
+        bytes
+    );
+    Ok(acc)
+}
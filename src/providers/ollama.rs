@@ -0,0 +1,243 @@
+use super::{
+    send, stream_lines, ChatMessage, ChatRequest, Completion, ModelInfo, Provider, StreamPiece,
+    TokenSink, Usage,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{header, Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const OLLAMA_DEFAULT_HOST: &str = "http://localhost:11434";
+// a local server answers at once; don't hold up startup when there is none
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+
+#[derive(Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    // Ollama's name for the response length cap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    options: OllamaOptions,
+    // a JSON schema here constrains the output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+    // bare base64, no data: URL prefix
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaChunk {
+    message: Option<ChatMessage>,
+    #[serde(default)]
+    done: bool,
+    // token counts, present on the final chunk
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+    done_reason: Option<String>,
+}
+
+impl OllamaChunk {
+    fn usage(&self) -> Option<Usage> {
+        if self.prompt_eval_count.is_none() && self.eval_count.is_none() {
+            return None;
+        }
+        Some(Usage {
+            prompt_tokens: self.prompt_eval_count.unwrap_or(0),
+            completion_tokens: self.eval_count.unwrap_or(0),
+            cached_tokens: 0,
+            reasoning_tokens: 0,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct OllamaTag {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct OllamaTags {
+    models: Vec<OllamaTag>,
+}
+
+/// Whether something is listening at `OLLAMA_HOST`, without speaking HTTP to it.
+pub fn is_reachable() -> bool {
+    let Ok(url) = reqwest::Url::parse(&ollama_host()) else {
+        return false;
+    };
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return false;
+    };
+    let Ok(addrs) = (host, port).to_socket_addrs() else {
+        return false;
+    };
+    addrs
+        .into_iter()
+        .any(|addr| TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).is_ok())
+}
+
+/// Base URL of the Ollama server; `OLLAMA_HOST` may omit the scheme like the ollama CLI allows.
+fn ollama_host() -> String {
+    let host = env::var("OLLAMA_HOST").unwrap_or_else(|_| OLLAMA_DEFAULT_HOST.into());
+    let host = host.trim_end_matches('/');
+    if host.starts_with("http://") || host.starts_with("https://") {
+        host.to_string()
+    } else {
+        format!("http://{}", host)
+    }
+}
+
+#[derive(Serialize)]
+struct OllamaEmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbeddings {
+    embeddings: Vec<Vec<f32>>,
+}
+
+pub struct Ollama {
+    client: Client,
+}
+
+impl Ollama {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn request(&self, req: &ChatRequest, stream: bool) -> RequestBuilder {
+        self.client
+            .post(format!("{}/api/chat", ollama_host()))
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(&OllamaRequest {
+                model: req.model.clone(),
+                messages: req
+                    .messages
+                    .iter()
+                    .map(|m| OllamaMessage {
+                        role: m.role.clone(),
+                        content: m.content.clone(),
+                        images: m.images.iter().map(|i| i.data.clone()).collect(),
+                    })
+                    .collect(),
+                stream,
+                options: OllamaOptions {
+                    temperature: req.params.temperature,
+                    top_p: req.params.top_p,
+                    num_predict: req.params.max_tokens,
+                    seed: req.params.seed,
+                },
+                format: req.params.schema.clone(),
+            })
+    }
+}
+
+// Ollama streams newline-delimited JSON objects instead of SSE
+fn parse_stream_line(data: &str) -> Vec<StreamPiece> {
+    let chunk = match serde_json::from_str::<OllamaChunk>(data) {
+        Ok(chunk) => chunk,
+        Err(_) => return Vec::new(),
+    };
+    let usage = chunk.usage();
+    let mut pieces = Vec::new();
+    if let Some(m) = chunk.message.filter(|m| !m.content.is_empty()) {
+        pieces.push(StreamPiece::Text(m.content));
+    }
+    match (chunk.done_reason, usage) {
+        (Some(reason), usage) => pieces.push(StreamPiece::Finished { reason, usage }),
+        (None, Some(usage)) => pieces.push(StreamPiece::Usage(usage)),
+        (None, None) if chunk.done => pieces.push(StreamPiece::Done),
+        (None, None) => {}
+    }
+    pieces
+}
+
+#[async_trait]
+impl Provider for Ollama {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn default_model(&self) -> &str {
+        "llama3"
+    }
+
+    fn chat_request(&self, req: &ChatRequest) -> Result<Option<RequestBuilder>> {
+        Ok(Some(self.request(req, true)))
+    }
+
+    async fn stream_chat(&self, req: &ChatRequest, on_token: TokenSink<'_>) -> Result<Completion> {
+        let res = send(|| Ok(self.request(req, true))).await?;
+        stream_lines(res, parse_stream_line, on_token).await
+    }
+
+    async fn complete(&self, req: &ChatRequest) -> Result<Completion> {
+        let res: OllamaChunk = send(|| Ok(self.request(req, false))).await?.json().await?;
+        let usage = res.usage();
+        Ok(Completion {
+            text: res.message.map(|m| m.content).unwrap_or_default(),
+            usage,
+            finish_reason: res.done_reason,
+            ..Default::default()
+        })
+    }
+
+    fn embedding_model(&self) -> Option<&str> {
+        Some("nomic-embed-text")
+    }
+
+    async fn embed(&self, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let res: OllamaEmbeddings = send(|| {
+            Ok(self
+                .client
+                .post(format!("{}/api/embed", ollama_host()))
+                .json(&OllamaEmbedRequest {
+                    model,
+                    input: texts,
+                }))
+        })
+        .await?
+        .json()
+        .await?;
+        Ok(res.embeddings)
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let res: OllamaTags = send(|| Ok(self.client.get(format!("{}/api/tags", ollama_host()))))
+            .await?
+            .json()
+            .await?;
+        Ok(res
+            .models
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.name,
+                context_length: None,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,673 @@
+use super::{
+    required_env, send, stream_sse, ChatMessage, ChatRequest, Completion, ModelInfo, Provider,
+    StreamPiece, TokenSink, ToolCall, ToolCallDelta, ToolSpec, Usage,
+};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use reqwest::multipart::{Form, Part};
+use reqwest::{header, Client, Method, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::Path;
+use tracing::warn;
+
+const OPENAI_DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const AZURE_DEFAULT_API_VERSION: &str = "2024-06-01";
+const MISTRAL_BASE_URL: &str = "https://api.mistral.ai/v1";
+const GROQ_BASE_URL: &str = "https://api.groq.com/openai/v1";
+const OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api/v1";
+// OpenRouter uses these for attribution on its app rankings
+const OPENROUTER_REFERER: &str = "https://github.com/NubleX/Mindlink";
+const OPENROUTER_TITLE: &str = "mindlink";
+
+#[derive(Serialize)]
+struct OpenAIRequest {
+    model: String,
+    messages: Vec<OpenAIMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    // api.openai.com deprecated max_tokens; newer models reject it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<OpenAITool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+/// Plain text, or parts when the message carries images.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum OpenAIContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Serialize)]
+struct OpenAIMessage {
+    role: String,
+    content: OpenAIContent,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ToolCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+impl From<&ChatMessage> for OpenAIMessage {
+    fn from(m: &ChatMessage) -> Self {
+        let content = if m.images.is_empty() {
+            OpenAIContent::Text(m.content.clone())
+        } else {
+            let mut parts = vec![ContentPart::Text {
+                text: m.content.clone(),
+            }];
+            parts.extend(m.images.iter().map(|img| ContentPart::ImageUrl {
+                image_url: ImageUrl {
+                    url: img.data_url(),
+                },
+            }));
+            OpenAIContent::Parts(parts)
+        };
+        Self {
+            role: m.role.clone(),
+            content,
+            tool_calls: m.tool_calls.clone(),
+            tool_call_id: m.tool_call_id.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct OpenAITool {
+    #[serde(rename = "type")]
+    kind: String,
+    function: ToolSpec,
+}
+
+#[derive(Deserialize)]
+struct FunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ToolCallChunk {
+    index: usize,
+    id: Option<String>,
+    function: Option<FunctionDelta>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonSchemaFormat {
+    name: String,
+    schema: serde_json::Value,
+    // strict mode rejects schemas without additionalProperties: false everywhere; answers are validated anyway
+    strict: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: String,
+    json_schema: JsonSchemaFormat,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StreamOptions {
+    // adds a final chunk carrying token usage
+    include_usage: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct PromptTokensDetails {
+    // prompts over 1024 tokens are cached automatically; this is how much of it hit
+    cached_tokens: u64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct CompletionTokensDetails {
+    reasoning_tokens: u64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct OpenAIUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+impl From<OpenAIUsage> for Usage {
+    fn from(u: OpenAIUsage) -> Self {
+        Usage {
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+            cached_tokens: u.prompt_tokens_details.map_or(0, |d| d.cached_tokens),
+            reasoning_tokens: u
+                .completion_tokens_details
+                .map_or(0, |d| d.reasoning_tokens),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct StreamChunkChoiceDelta {
+    content: Option<String>,
+    // OpenAI keeps reasoning hidden; DeepSeek, vLLM and OpenRouter stream it under these names
+    #[serde(alias = "reasoning")]
+    reasoning_content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallChunk>,
+    // role is present in the schema but not needed; keep to avoid schema drift warnings
+    #[allow(dead_code)]
+    role: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StreamChunkChoice {
+    delta: StreamChunkChoiceDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChunkChoice>,
+    usage: Option<OpenAIUsage>,
+    system_fingerprint: Option<String>,
+    // sent in place of a chunk when the server fails partway through
+    error: Option<StreamError>,
+}
+
+#[derive(Deserialize)]
+struct StreamError {
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct RespChoice {
+    message: ChatMessage,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Resp {
+    choices: Vec<RespChoice>,
+    usage: Option<OpenAIUsage>,
+    system_fingerprint: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+    // each service names the context size differently, when it reports it at all
+    #[serde(alias = "max_context_length", alias = "context_window")]
+    context_length: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingEntry {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingList {
+    data: Vec<EmbeddingEntry>,
+}
+
+#[derive(Deserialize)]
+struct Transcription {
+    text: String,
+}
+
+/// Services speaking the OpenAI chat completions protocol, differing in URL and auth.
+enum Kind {
+    OpenAi,
+    Azure,
+    OpenRouter,
+    Mistral,
+    Groq,
+}
+
+pub struct OpenAiCompatible {
+    client: Client,
+    kind: Kind,
+    name: &'static str,
+    default_model: &'static str,
+    key_env: &'static str,
+}
+
+/// `OPENAI_BASE_URL` lets the OpenAI path target any compatible server (LM Studio, vLLM, LiteLLM...).
+fn openai_base_url() -> Option<String> {
+    env::var("OPENAI_BASE_URL")
+        .ok()
+        .filter(|u| !u.trim().is_empty())
+        .map(|u| u.trim().trim_end_matches('/').to_string())
+}
+
+/// Azure routes chat by deployment name and pins the API version in the query string.
+fn azure_url(path: &str) -> Result<String> {
+    let endpoint = required_env("AZURE_OPENAI_ENDPOINT")?;
+    let api_version =
+        env::var("AZURE_OPENAI_API_VERSION").unwrap_or_else(|_| AZURE_DEFAULT_API_VERSION.into());
+    let endpoint = endpoint.trim_end_matches('/');
+    let url = match path {
+        "chat/completions" => format!(
+            "{}/openai/deployments/{}/chat/completions",
+            endpoint,
+            required_env("AZURE_OPENAI_DEPLOYMENT")?
+        ),
+        _ => format!("{}/openai/{}", endpoint, path),
+    };
+    Ok(format!("{}?api-version={}", url, api_version))
+}
+
+/// o-series and gpt-5 models think before answering and reject sampling controls.
+fn is_reasoning_model(model: &str) -> bool {
+    let model = model.strip_prefix("openai/").unwrap_or(model);
+    let o_series = model
+        .strip_prefix('o')
+        .is_some_and(|r| r.starts_with(|c: char| c.is_ascii_digit()));
+    o_series || (model.starts_with("gpt-5") && !model.starts_with("gpt-5-chat"))
+}
+
+/// OpenRouter model slugs are `vendor/model`; bare names are assumed to be OpenAI models.
+fn openrouter_slug(model: &str) -> String {
+    if model.contains('/') {
+        model.to_string()
+    } else {
+        format!("openai/{}", model)
+    }
+}
+
+impl OpenAiCompatible {
+    fn with(
+        client: Client,
+        kind: Kind,
+        name: &'static str,
+        default_model: &'static str,
+        key_env: &'static str,
+    ) -> Self {
+        Self {
+            client,
+            kind,
+            name,
+            default_model,
+            key_env,
+        }
+    }
+
+    pub fn openai(client: Client) -> Self {
+        Self::with(client, Kind::OpenAi, "openai", "gpt-5", "OPENAI_API_KEY")
+    }
+
+    pub fn azure(client: Client) -> Self {
+        Self::with(
+            client,
+            Kind::Azure,
+            "azure",
+            "gpt-5",
+            "AZURE_OPENAI_API_KEY",
+        )
+    }
+
+    pub fn openrouter(client: Client) -> Self {
+        Self::with(
+            client,
+            Kind::OpenRouter,
+            "openrouter",
+            "openai/gpt-4o",
+            "OPENROUTER_API_KEY",
+        )
+    }
+
+    pub fn mistral(client: Client) -> Self {
+        Self::with(
+            client,
+            Kind::Mistral,
+            "mistral",
+            "mistral-large-latest",
+            "MISTRAL_API_KEY",
+        )
+    }
+
+    pub fn groq(client: Client) -> Self {
+        Self::with(
+            client,
+            Kind::Groq,
+            "groq",
+            "llama-3.3-70b-versatile",
+            "GROQ_API_KEY",
+        )
+    }
+
+    /// Build an authenticated request to `path` under the service's API root.
+    fn endpoint(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        let base_url = match self.kind {
+            Kind::OpenAi => openai_base_url(),
+            _ => None,
+        };
+        // local OpenAI-compatible servers usually don't check keys
+        let api_key = match (&base_url, env::var(self.key_env)) {
+            (_, Ok(key)) => Some(key),
+            (Some(_), Err(_)) => None,
+            (None, Err(_)) => Some(required_env(self.key_env)?),
+        };
+        let url = match self.kind {
+            Kind::OpenAi => format!(
+                "{}/{}",
+                base_url.as_deref().unwrap_or(OPENAI_DEFAULT_BASE_URL),
+                path
+            ),
+            Kind::Azure => azure_url(path)?,
+            Kind::OpenRouter => format!("{}/{}", OPENROUTER_BASE_URL, path),
+            Kind::Mistral => format!("{}/{}", MISTRAL_BASE_URL, path),
+            Kind::Groq => format!("{}/{}", GROQ_BASE_URL, path),
+        };
+        let mut rb = self.client.request(method, url);
+        if let Some(api_key) = api_key {
+            rb = match self.kind {
+                Kind::Azure => rb.header("api-key", api_key),
+                _ => rb.header(header::AUTHORIZATION, format!("Bearer {}", api_key)),
+            };
+        }
+        if let Kind::OpenAi = self.kind {
+            // multi-org accounts bill to the default org unless told otherwise
+            for (var, name) in [
+                ("OPENAI_ORG_ID", "OpenAI-Organization"),
+                ("OPENAI_PROJECT_ID", "OpenAI-Project"),
+            ] {
+                if let Ok(id) = env::var(var) {
+                    if !id.trim().is_empty() {
+                        rb = rb.header(name, id.trim());
+                    }
+                }
+            }
+        }
+        if let Kind::OpenRouter = self.kind {
+            rb = rb
+                .header(
+                    "HTTP-Referer",
+                    env::var("OPENROUTER_REFERER").unwrap_or_else(|_| OPENROUTER_REFERER.into()),
+                )
+                .header(
+                    "X-Title",
+                    env::var("OPENROUTER_TITLE").unwrap_or_else(|_| OPENROUTER_TITLE.into()),
+                );
+        }
+        Ok(rb)
+    }
+
+    fn request(&self, req: &ChatRequest, stream: bool) -> Result<RequestBuilder> {
+        let model = match self.kind {
+            Kind::OpenRouter => openrouter_slug(&req.model),
+            _ => req.model.clone(),
+        };
+        let official_openai = matches!(self.kind, Kind::OpenAi) && openai_base_url().is_none();
+        let reasoning = is_reasoning_model(&model);
+        let (temperature, top_p) = if reasoning {
+            if req.params.temperature.is_some() || req.params.top_p.is_some() {
+                warn!("{} does not support temperature/top_p; not sent", model);
+            }
+            (None, None)
+        } else {
+            (req.params.temperature, req.params.top_p)
+        };
+        // not every compatible server accepts stream_options; the rest report usage unasked or not at all
+        let stream_options = match self.kind {
+            Kind::OpenAi | Kind::OpenRouter if stream => Some(StreamOptions {
+                include_usage: true,
+            }),
+            _ => None,
+        };
+        // elsewhere the model name says little (deployments, proxies), so trust the user
+        let reasoning_effort = match &req.params.reasoning_effort {
+            Some(_) if official_openai && !reasoning => {
+                warn!(
+                    "{} is not a reasoning model; reasoning effort not sent",
+                    model
+                );
+                None
+            }
+            effort => effort.clone(),
+        };
+        let (max_tokens, max_completion_tokens) =
+            if official_openai || (reasoning && matches!(self.kind, Kind::Azure)) {
+                (None, req.params.max_tokens)
+            } else {
+                (req.params.max_tokens, None)
+            };
+        Ok(self
+            .endpoint(Method::POST, "chat/completions")?
+            .header(header::CONTENT_TYPE, "application/json")
+            .json(&OpenAIRequest {
+                model,
+                messages: req.messages.iter().map(OpenAIMessage::from).collect(),
+                stream,
+                temperature,
+                top_p,
+                max_tokens,
+                max_completion_tokens,
+                reasoning_effort,
+                seed: req.params.seed,
+                tools: req
+                    .tools
+                    .iter()
+                    .map(|t| OpenAITool {
+                        kind: "function".into(),
+                        function: t.clone(),
+                    })
+                    .collect(),
+                response_format: req.params.schema.clone().map(|schema| ResponseFormat {
+                    kind: "json_schema".into(),
+                    json_schema: JsonSchemaFormat {
+                        name: "response".into(),
+                        schema,
+                        strict: false,
+                    },
+                }),
+                stream_options,
+            }))
+    }
+}
+
+fn parse_stream_event(data: &str) -> Vec<StreamPiece> {
+    if data == "[DONE]" {
+        return vec![StreamPiece::Done];
+    }
+    let payload = match serde_json::from_str::<StreamChunk>(data) {
+        Ok(p) => p,
+        Err(_) => return Vec::new(),
+    };
+    if let Some(e) = payload.error {
+        return vec![StreamPiece::Error(e.message)];
+    }
+    let mut pieces = Vec::new();
+    let (delta, finish) = match payload.choices.into_iter().next() {
+        Some(choice) => (Some(choice.delta), choice.finish_reason),
+        None => (None, None),
+    };
+    if let Some(d) = delta {
+        if let Some(text) = d.reasoning_content.filter(|t| !t.is_empty()) {
+            pieces.push(StreamPiece::Reasoning(text));
+        }
+        if let Some(text) = d.content.filter(|t| !t.is_empty()) {
+            pieces.push(StreamPiece::Text(text));
+        }
+        pieces.extend(d.tool_calls.into_iter().map(|call| {
+            let function = call.function;
+            StreamPiece::ToolCall(ToolCallDelta {
+                index: call.index,
+                id: call.id,
+                name: function.as_ref().and_then(|f| f.name.clone()),
+                arguments: function.and_then(|f| f.arguments),
+            })
+        }));
+    }
+    // every chunk repeats the fingerprint; the last one seen is kept
+    if let Some(fp) = payload.system_fingerprint {
+        pieces.push(StreamPiece::Fingerprint(fp));
+    }
+    match (finish, payload.usage) {
+        (Some(reason), usage) => pieces.push(StreamPiece::Finished {
+            reason,
+            usage: usage.map(Usage::from),
+        }),
+        (None, Some(usage)) => pieces.push(StreamPiece::Usage(usage.into())),
+        (None, None) => {}
+    }
+    pieces
+}
+
+#[async_trait]
+impl Provider for OpenAiCompatible {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn default_model(&self) -> &str {
+        self.default_model
+    }
+
+    fn chat_request(&self, req: &ChatRequest) -> Result<Option<RequestBuilder>> {
+        self.request(req, true).map(Some)
+    }
+
+    async fn stream_chat(&self, req: &ChatRequest, on_token: TokenSink<'_>) -> Result<Completion> {
+        match stream_sse(|| self.request(req, true), parse_stream_event, on_token).await? {
+            Some(acc) => Ok(acc),
+            None => {
+                let out = self.complete(req).await?;
+                on_token(&out.text);
+                Ok(out)
+            }
+        }
+    }
+
+    async fn complete(&self, req: &ChatRequest) -> Result<Completion> {
+        let res: Resp = send(|| self.request(req, false)).await?.json().await?;
+        let (message, finish_reason) = match res.choices.into_iter().next() {
+            Some(choice) => (Some(choice.message), choice.finish_reason),
+            None => (None, None),
+        };
+        Ok(Completion {
+            usage: res.usage.map(Usage::from),
+            text: message
+                .as_ref()
+                .map(|m| m.content.clone())
+                .unwrap_or_default(),
+            tool_calls: message.map(|m| m.tool_calls).unwrap_or_default(),
+            fingerprint: res.system_fingerprint,
+            finish_reason,
+            ..Default::default()
+        })
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let res: ModelList = send(|| self.endpoint(Method::GET, "models"))
+            .await?
+            .json()
+            .await?;
+        Ok(res
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.id,
+                context_length: m.context_length,
+            })
+            .collect())
+    }
+
+    fn embedding_model(&self) -> Option<&str> {
+        match self.kind {
+            Kind::OpenAi => Some("text-embedding-3-small"),
+            Kind::Mistral => Some("mistral-embed"),
+            _ => None,
+        }
+    }
+
+    async fn embed(&self, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let res: EmbeddingList = send(|| {
+            Ok(self
+                .endpoint(Method::POST, "embeddings")?
+                .json(&EmbeddingRequest {
+                    model,
+                    input: texts,
+                }))
+        })
+        .await?
+        .json()
+        .await?;
+        let mut data = res.data;
+        data.sort_by_key(|e| e.index);
+        Ok(data.into_iter().map(|e| e.embedding).collect())
+    }
+
+    async fn transcribe(&self, path: &Path) -> Result<String> {
+        let default_model = match self.kind {
+            Kind::OpenAi => "whisper-1",
+            Kind::Groq => "whisper-large-v3",
+            _ => return Err(anyhow!("{} does not support transcription", self.name)),
+        };
+        let model = env::var("AI_TRANSCRIBE_MODEL").unwrap_or_else(|_| default_model.into());
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        // the service sniffs the format from the file name's extension
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "audio".into());
+        let res: Transcription = send(|| {
+            let form = Form::new().text("model", model.clone()).part(
+                "file",
+                Part::bytes(bytes.clone()).file_name(file_name.clone()),
+            );
+            Ok(self
+                .endpoint(Method::POST, "audio/transcriptions")?
+                .multipart(form))
+        })
+        .await?
+        .json()
+        .await?;
+        Ok(res.text.trim().to_string())
+    }
+}
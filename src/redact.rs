@@ -0,0 +1,186 @@
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use std::collections::HashMap;
+use std::env;
+
+/// Credentials that are easy to paste by mistake, most specific first so a key is labelled by
+/// what it is rather than by a looser pattern that also matches it.
+const DEFAULT_PATTERNS: &[(&str, &str)] = &[
+    (
+        "private-key",
+        r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+    ),
+    ("anthropic-key", r"sk-ant-[A-Za-z0-9_-]{20,}"),
+    ("openai-key", r"sk-[A-Za-z0-9_-]{20,}"),
+    ("aws-access-key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    (
+        "github-token",
+        r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{40,})",
+    ),
+    ("slack-token", r"\bxox[abprs]-[A-Za-z0-9-]{10,}"),
+    ("google-api-key", r"\bAIza[0-9A-Za-z_-]{35}"),
+    ("bearer-token", r"(?i)\bbearer\s+[A-Za-z0-9._~+/-]{20,}=*"),
+    (
+        "email",
+        r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b",
+    ),
+];
+
+/// What ties a shared conversation to a person or an organization, on top of the credentials
+/// above. Home directories keep the rest of the path, which usually matters for a reproduction.
+const ANONYMIZE_PATTERNS: &[(&str, &str)] = &[
+    ("home", r"(?:/home/|/Users/|[A-Za-z]:\\Users\\)[^\s/\\]+"),
+    ("ip", r"\b(?:\d{1,3}\.){3}\d{1,3}\b"),
+    (
+        "host",
+        r"(?i)\b(?:[a-z0-9-]+\.)+(?:com|net|org|io|dev|app|ai|cloud|co|uk|de|fr|eu|internal|local|lan|corp|intranet|localdomain)\b",
+    ),
+];
+
+/// `name=regex` entries from `var` (separated by `;`), then from `extra`.
+fn custom_rules(var: &str, extra: &[String]) -> Result<Vec<(String, Regex)>> {
+    let configured = env::var(var).unwrap_or_default();
+    let custom = configured
+        .split(';')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .chain(extra.iter().map(String::as_str));
+    let mut rules = Vec::new();
+    for entry in custom {
+        let (name, pattern) = entry
+            .split_once('=')
+            .filter(|(name, pattern)| !name.trim().is_empty() && !pattern.is_empty())
+            .ok_or_else(|| anyhow!("redaction pattern {:?} is not name=regex", entry))?;
+        let regex = Regex::new(pattern)
+            .with_context(|| format!("redaction pattern {} is not a valid regex", name))?;
+        rules.push((name.trim().to_string(), regex));
+    }
+    Ok(rules)
+}
+
+fn compiled(patterns: &[(&str, &str)]) -> Result<Vec<(String, Regex)>> {
+    patterns
+        .iter()
+        .map(|(name, pattern)| Ok((name.to_string(), Regex::new(pattern)?)))
+        .collect()
+}
+
+/// Finds secrets with a list of named regexes and swaps them for `[REDACTED:<name>]`.
+pub struct Redactor {
+    rules: Vec<(String, Regex)>,
+}
+
+impl Redactor {
+    /// The built-in patterns, then those in `AI_REDACT_PATTERNS` (`name=regex` entries separated
+    /// by `;`), then `extra` in the same `name=regex` form.
+    pub fn from_env(extra: &[String]) -> Result<Self> {
+        let mut rules = compiled(DEFAULT_PATTERNS)?;
+        rules.extend(custom_rules("AI_REDACT_PATTERNS", extra)?);
+        Ok(Self { rules })
+    }
+
+    /// The text with every match replaced, and the names of the patterns that matched (once per
+    /// match); `None` when nothing matched.
+    pub fn redact(&self, text: &str) -> Option<(String, Vec<String>)> {
+        let mut out = text.to_string();
+        let mut hits = Vec::new();
+        for (name, regex) in &self.rules {
+            let mut n = 0;
+            let placeholder = format!("[REDACTED:{}]", name);
+            let replaced = regex.replace_all(&out, |_: &regex::Captures| {
+                n += 1;
+                placeholder.clone()
+            });
+            if n > 0 {
+                out = replaced.into_owned();
+                hits.extend(std::iter::repeat_n(name.clone(), n));
+            }
+        }
+        (!hits.is_empty()).then_some((out, hits))
+    }
+}
+
+/// Replaces what identifies someone with numbered placeholders, the same one for every copy of
+/// a value (`[email-1]` stays `[email-1]`), so a shared conversation still reads coherently.
+pub struct Anonymizer {
+    rules: Vec<(String, Regex)>,
+    seen: HashMap<(String, String), String>,
+    counts: HashMap<String, usize>,
+}
+
+impl Anonymizer {
+    /// The secrets [`Redactor`] knows, emails, home directories, IP addresses and host names,
+    /// the login and machine names, and the names in `AI_ANONYMIZE_NAMES` (separated by `;`);
+    /// then `AI_REDACT_PATTERNS`, `AI_ANONYMIZE_PATTERNS` and `extra` as `name=regex` entries.
+    pub fn from_env(extra: &[String]) -> Result<Self> {
+        let mut rules = compiled(DEFAULT_PATTERNS)?;
+        rules.extend(compiled(ANONYMIZE_PATTERNS)?);
+        let hostname = env::var("HOSTNAME")
+            .or_else(|_| env::var("COMPUTERNAME"))
+            .ok()
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok());
+        let names = env::var("AI_ANONYMIZE_NAMES").unwrap_or_default();
+        let known = [
+            (
+                "user",
+                env::var("USER").or_else(|_| env::var("USERNAME")).ok(),
+            ),
+            ("machine", hostname),
+        ];
+        // full names first, so "Alice Smith" isn't half taken by the login name alice
+        let known = names.split(';').map(|n| ("name", n.to_string())).chain(
+            known
+                .into_iter()
+                .filter_map(|(kind, value)| value.map(|v| (kind, v))),
+        );
+        for (kind, value) in known {
+            // anything shorter would turn up inside ordinary words
+            let value = value.trim();
+            if value.chars().count() >= 3 {
+                let pattern = format!(r"(?i)\b{}\b", regex::escape(value));
+                rules.push((kind.to_string(), Regex::new(&pattern)?));
+            }
+        }
+        rules.extend(custom_rules("AI_REDACT_PATTERNS", &[])?);
+        rules.extend(custom_rules("AI_ANONYMIZE_PATTERNS", extra)?);
+        Ok(Self {
+            rules,
+            seen: HashMap::new(),
+            counts: HashMap::new(),
+        })
+    }
+
+    pub fn anonymize(&mut self, text: &str) -> String {
+        let Self {
+            rules,
+            seen,
+            counts,
+        } = self;
+        let mut out = text.to_string();
+        for (name, regex) in rules.iter() {
+            if !regex.is_match(&out) {
+                continue;
+            }
+            out = regex
+                .replace_all(&out, |c: &regex::Captures| {
+                    seen.entry((name.clone(), c[0].to_lowercase()))
+                        .or_insert_with(|| {
+                            let n = counts.entry(name.clone()).or_insert(0);
+                            *n += 1;
+                            format!("[{}-{}]", name, n)
+                        })
+                        .clone()
+                })
+                .into_owned();
+        }
+        out
+    }
+
+    /// How many distinct values were replaced, per kind, most first.
+    pub fn replaced(&self) -> Vec<(String, usize)> {
+        let mut kinds: Vec<(String, usize)> =
+            self.counts.iter().map(|(k, n)| (k.clone(), *n)).collect();
+        kinds.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        kinds
+    }
+}
@@ -0,0 +1,209 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::env;
+use std::time::Duration;
+
+/// How much randomness to mix into each delay.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Jitter {
+    None,
+    /// Uniform in `[0, delay]` ("full jitter"), which spreads out clients retrying in lockstep.
+    Full,
+}
+
+/// Exponential backoff with a cap: `min(cap, base * 2^(attempt-1))`, then jittered.
+#[derive(Clone, Debug)]
+pub struct BackoffPolicy {
+    pub max_retries: usize,
+    pub base: Duration,
+    pub cap: Duration,
+    pub jitter: Jitter,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base: Duration::from_millis(300),
+            cap: Duration::from_secs(30),
+            jitter: Jitter::Full,
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// `AI_MAX_RETRIES`, `AI_BACKOFF_MS`, `AI_BACKOFF_CAP_MS`, `AI_BACKOFF_JITTER=full|none`.
+    pub fn from_env() -> Self {
+        let d = Self::default();
+        let ms = |var: &str| {
+            env::var(var)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_millis)
+        };
+        Self {
+            max_retries: env::var("AI_MAX_RETRIES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(d.max_retries),
+            base: ms("AI_BACKOFF_MS").unwrap_or(d.base),
+            cap: ms("AI_BACKOFF_CAP_MS").unwrap_or(d.cap),
+            jitter: match env::var("AI_BACKOFF_JITTER").as_deref() {
+                Ok("none") => Jitter::None,
+                _ => d.jitter,
+            },
+        }
+    }
+
+    /// Delay before retry number `attempt` (1-based). A server-provided
+    /// `Retry-After` wins, since retrying sooner would just be rejected again,
+    /// but is held to the cap too.
+    pub fn delay(&self, attempt: usize, retry_after: Option<Duration>) -> Duration {
+        self.delay_with(attempt, retry_after, &mut rand::thread_rng())
+    }
+
+    fn delay_with(
+        &self,
+        attempt: usize,
+        retry_after: Option<Duration>,
+        rng: &mut impl Rng,
+    ) -> Duration {
+        if let Some(d) = retry_after {
+            return d.min(self.cap);
+        }
+        let exp = attempt.saturating_sub(1).min(32) as u32;
+        let delay = self
+            .base
+            .checked_mul(2u32.saturating_pow(exp))
+            .unwrap_or(self.cap)
+            .min(self.cap);
+        match self.jitter {
+            Jitter::None => delay,
+            Jitter::Full => Duration::from_millis(rng.gen_range(0..=delay.as_millis() as u64)),
+        }
+    }
+}
+
+/// Statuses worth retrying: rate limits, timeouts, and transient server/overload errors.
+pub fn is_retryable(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504 | 529)
+}
+
+/// Read the server's requested delay from `retry-after-ms` or `Retry-After`.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(ms) = headers
+        .get("retry-after-ms")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<f64>().ok())
+    {
+        return Some(Duration::from_millis(ms.max(0.0) as u64));
+    }
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_retry_after(v, Utc::now()))
+}
+
+/// `Retry-After` is either delay-seconds or an HTTP-date.
+pub fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let when = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    Some((when - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn policy(jitter: Jitter) -> BackoffPolicy {
+        BackoffPolicy {
+            max_retries: 5,
+            base: Duration::from_millis(100),
+            cap: Duration::from_millis(1000),
+            jitter,
+        }
+    }
+
+    #[test]
+    fn exponential_growth_is_capped() {
+        let p = policy(Jitter::None);
+        let mut rng = StdRng::seed_from_u64(1);
+        let delays: Vec<u128> = (1..=6)
+            .map(|a| p.delay_with(a, None, &mut rng).as_millis())
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(p.delay_with(500, None, &mut rng), p.cap);
+    }
+
+    #[test]
+    fn full_jitter_stays_within_bound() {
+        let p = policy(Jitter::Full);
+        let mut rng = StdRng::seed_from_u64(7);
+        for attempt in 1..=10 {
+            let bound = policy(Jitter::None).delay_with(attempt, None, &mut rng);
+            assert!(p.delay_with(attempt, None, &mut rng) <= bound);
+        }
+    }
+
+    #[test]
+    fn retry_after_overrides_backoff() {
+        let p = policy(Jitter::Full);
+        let mut rng = StdRng::seed_from_u64(3);
+        let d = Duration::from_millis(800);
+        assert_eq!(p.delay_with(1, Some(d), &mut rng), d);
+    }
+
+    #[test]
+    fn retry_after_is_capped() {
+        let p = policy(Jitter::None);
+        let mut rng = StdRng::seed_from_u64(5);
+        let day = Duration::from_secs(86400);
+        assert_eq!(p.delay_with(1, Some(day), &mut rng), p.cap);
+    }
+
+    #[test]
+    fn parses_retry_after_forms() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:27:30Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn header_lookup_prefers_milliseconds() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "3".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+        headers.insert("retry-after-ms", "250".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn retryable_statuses() {
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_retryable(StatusCode::UNAUTHORIZED));
+        assert!(!is_retryable(StatusCode::BAD_REQUEST));
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+
+/// `spec` is inline JSON when it starts with `{`, otherwise a path to a schema file.
+pub fn load(spec: &str) -> Result<Value> {
+    let spec = spec.trim();
+    let text = if spec.starts_with('{') {
+        spec.to_string()
+    } else {
+        std::fs::read_to_string(spec).with_context(|| format!("reading schema {}", spec))?
+    };
+    let schema: Value = serde_json::from_str(&text).context("schema is not valid JSON")?;
+    jsonschema::validator_for(&schema).map_err(|e| anyhow!("invalid JSON schema: {}", e))?;
+    Ok(schema)
+}
+
+/// Instruction for providers without native schema enforcement (and a hint for the rest).
+pub fn instruction(schema: &Value) -> String {
+    format!(
+        "Respond with a single JSON value that validates against this JSON schema, \
+         and nothing else (no prose, no code fences):\n{}",
+        schema
+    )
+}
+
+/// The JSON in a model's answer, without the code fences some models add anyway.
+pub fn extract(text: &str) -> &str {
+    let text = text.trim();
+    let Some(rest) = text.strip_prefix("```") else {
+        return text;
+    };
+    let rest = rest.strip_prefix("json").unwrap_or(rest);
+    rest.strip_suffix("```").unwrap_or(rest).trim()
+}
+
+/// Check an answer against the schema, describing every violation on failure.
+pub fn validate(schema: &Value, text: &str) -> Result<(), String> {
+    let value: Value =
+        serde_json::from_str(extract(text)).map_err(|e| format!("not valid JSON: {}", e))?;
+    let validator = jsonschema::validator_for(schema).map_err(|e| e.to_string())?;
+    let errors: Vec<String> = validator
+        .iter_errors(&value)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            if path.is_empty() {
+                e.to_string()
+            } else {
+                format!("{} at {}", e, path)
+            }
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
@@ -0,0 +1,248 @@
+use crate::config;
+use crate::http;
+use crate::keychain::{self, KEYS};
+use crate::providers::{self, Registry};
+use anyhow::{anyhow, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+
+/// Print `question`, with `default` in brackets, and read the answer; Enter takes the default.
+fn ask(question: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    if std::io::stdin().read_line(&mut line)? == 0 {
+        return Err(anyhow!(
+            "setup cancelled; nothing after this question was saved"
+        ));
+    }
+    Ok(match line.trim() {
+        "" => default.to_string(),
+        answer => answer.to_string(),
+    })
+}
+
+/// Ask until the answer is one of `choices`.
+fn choose(question: &str, choices: &[&str], default: &str) -> Result<String> {
+    loop {
+        let answer = ask(&format!("{} ({})", question, choices.join(", ")), default)?;
+        if choices.contains(&answer.as_str()) {
+            return Ok(answer);
+        }
+        println!("  {:?} isn't one of those.", answer);
+    }
+}
+
+/// Read an answer without echoing it, for a key. Piped answers are read like the others.
+pub fn ask_secret(question: &str) -> Result<String> {
+    if !std::io::stdin().is_terminal() {
+        return ask(question, "");
+    }
+    print!("{} (not shown as you type): ", question);
+    std::io::stdout().flush()?;
+    terminal::enable_raw_mode()?;
+    let typed = read_hidden();
+    terminal::disable_raw_mode()?;
+    println!();
+    typed
+}
+
+fn read_hidden() -> Result<String> {
+    let mut typed = String::new();
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(typed.trim().to_string()),
+            // raw mode turns Ctrl-C into a key like any other
+            KeyCode::Char('c' | 'd') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Err(anyhow!(
+                    "setup cancelled; nothing after this question was saved"
+                ))
+            }
+            KeyCode::Char(c) => typed.push(c),
+            KeyCode::Backspace => {
+                typed.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The value `key` has now, in the files or the environment.
+fn current(key: &str) -> Option<String> {
+    config::get(key, None)
+        .ok()
+        .map(|s| s.value)
+        .filter(|v| !v.trim().is_empty())
+}
+
+/// Write `key` to the global config file, and say so if the environment will keep overriding it.
+fn set(key: &str, value: &str) -> Result<PathBuf> {
+    let file = config::set(key, value, false, None)?;
+    if let Ok(s) = config::get(key, None) {
+        if s.source == "environment" && s.value != value {
+            println!(
+                "  ({} is set to {:?} in the environment, which goes before the file)",
+                s.var, s.value
+            );
+        }
+    }
+    Ok(file)
+}
+
+/// A key typed in, kept in the system keychain or, where there is none, the file; the
+/// environment's or the file's own is kept unless replaced, where it is.
+fn ask_key(provider: &str, var: &str, optional: bool) -> Result<()> {
+    let key = config::key_name(var);
+    let existing = config::get(&key, None).ok();
+    if let Some(s) = existing.as_ref().filter(|s| s.source == "environment") {
+        println!(
+            "  {} is set in the environment, which goes before the file; it stays there.",
+            s.var
+        );
+        return Ok(());
+    }
+    let kept = existing.as_ref().map(|s| s.source.clone()).or_else(|| {
+        keychain::enabled()
+            .then(|| keychain::get(provider).ok().flatten())
+            .flatten()
+            .map(|_| "the keychain".to_string())
+    });
+    let question = match (&kept, optional) {
+        (Some(place), _) => format!("API key ({}; Enter keeps the one in {})", var, place),
+        (None, true) => format!("API key ({}; Enter if the server takes none)", var),
+        (None, false) => format!("API key ({})", var),
+    };
+    let typed = ask_secret(&question)?;
+    if !typed.is_empty() {
+        let in_keychain = existing.is_none()
+            && keychain::enabled()
+            && match keychain::set(provider, &typed) {
+                Ok(()) => {
+                    println!("  Kept in the system keychain.");
+                    true
+                }
+                Err(e) => {
+                    println!("  ({:#}; it goes in the file instead)", e);
+                    false
+                }
+            };
+        if !in_keychain {
+            set(&key, &typed)?;
+        }
+    } else if kept.is_none() && !optional {
+        println!(
+            "  No key saved; add one later with `mindlink auth set {}` or by exporting {}.",
+            provider, var
+        );
+    }
+    Ok(())
+}
+
+/// `mindlink init`: ask for a provider and its key, a default model and where memory is kept,
+/// and write the answers to the global config file, which is returned. Keys go in the system
+/// keychain, or else in the file, made readable by its owner only.
+pub fn run() -> Result<PathBuf> {
+    let registry = Registry::with_defaults(http::client)?;
+    let available: Vec<&str> = config::PROVIDERS
+        .iter()
+        .copied()
+        .filter(|p| registry.contains(p))
+        .collect();
+    let file = config::global_dir()
+        .ok_or_else(|| anyhow!("no home directory for the config file"))?
+        .join("config.toml");
+    println!(
+        "Setting up mindlink in {}; Enter takes the answer in brackets.\n",
+        file.display()
+    );
+
+    let detected = providers::detect_provider().map(|(p, _)| p.to_string());
+    let was = current("provider");
+    let default = was.clone().or(detected).unwrap_or_else(|| "openai".into());
+    let provider = choose("Provider", &available, &default)?;
+    set("provider", &provider)?;
+    let mut key_optional = false;
+    match provider.as_str() {
+        "openai" => {
+            let url = ask(
+                "Base URL of an OpenAI-compatible server (LM Studio, vLLM...), or Enter for OpenAI itself",
+                &current("openai.base_url").unwrap_or_default(),
+            )?;
+            if !url.is_empty() {
+                set("openai.base_url", &url)?;
+                key_optional = true;
+            }
+        }
+        "azure" => {
+            for (key, question) in [
+                (
+                    "azure.openai_endpoint",
+                    "Endpoint, e.g. https://my-resource.openai.azure.com",
+                ),
+                ("azure.openai_deployment", "Deployment"),
+            ] {
+                let value = ask(question, &current(key).unwrap_or_default())?;
+                set(key, &value)?;
+            }
+        }
+        "ollama" => {
+            let default = current("ollama.host").unwrap_or_else(|| "http://localhost:11434".into());
+            set("ollama.host", &ask("Ollama server", &default)?)?;
+        }
+        "bedrock" => {
+            let default = current("aws.region").unwrap_or_else(|| "us-east-1".into());
+            set("aws.region", &ask("AWS region", &default)?)?;
+            println!(
+                "  The credentials come from ~/.aws/credentials (AWS_PROFILE picks a profile) or AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY."
+            );
+        }
+        _ => {}
+    }
+    if let Some((_, var)) = KEYS.iter().find(|(p, _)| *p == provider) {
+        ask_key(&provider, var, key_optional)?;
+    }
+
+    // a model chosen for another provider is no default for this one
+    let default = current("model")
+        .filter(|_| was.as_deref() == Some(provider.as_str()))
+        .or_else(|| {
+            registry
+                .get(&provider)
+                .ok()
+                .map(|p| p.default_model().to_string())
+        })
+        .unwrap_or_default();
+    let question = match provider.as_str() {
+        "local" => "Path to the GGUF model file",
+        _ => "Default model",
+    };
+    let model = ask(question, &default)?;
+    if !model.is_empty() {
+        set("model", &model)?;
+    }
+
+    let dir = current("memory_dir");
+    let mode = choose(
+        "Memory: project keeps one per directory you run in (./.mindlink), global one for everywhere",
+        &["project", "global"],
+        if dir.is_some() { "global" } else { "project" },
+    )?;
+    let file = match (mode.as_str(), dir) {
+        ("global", None) => set("memory_dir", "~/.mindlink")?,
+        ("project", Some(_)) => set("memory_dir", "")?,
+        _ => file,
+    };
+    Ok(file)
+}
@@ -0,0 +1,72 @@
+use crate::theme::{self, Stream};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Which spinner may draw (0 for none), and whether its line is on the screen.
+static SHOWN: Mutex<(u64, bool)> = Mutex::new((0, false));
+static NEXT: AtomicU64 = AtomicU64::new(1);
+
+fn shown() -> MutexGuard<'static, (u64, bool)> {
+    SHOWN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Stops the spinner when it goes, in case the answer never came.
+pub struct Spinner;
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        stop();
+    }
+}
+
+/// Spin on stderr with the seconds waited so far, until `stop`. Only when stdout and stderr are
+/// both terminals: piped output gets nothing in between.
+pub fn start() -> Spinner {
+    if !std::io::stdout().is_terminal() || !std::io::stderr().is_terminal() {
+        return Spinner;
+    }
+    let id = NEXT.fetch_add(1, Ordering::SeqCst);
+    *shown() = (id, false);
+    let started = Instant::now();
+    let dim = theme::enabled(Stream::Stderr);
+    tokio::spawn(async move {
+        // a quick answer shows no spinner at all
+        let first = tokio::time::Instant::now() + Duration::from_millis(200);
+        let mut tick = tokio::time::interval_at(first, Duration::from_millis(100));
+        for frame in FRAMES.iter().cycle() {
+            tick.tick().await;
+            let mut shown = shown();
+            if shown.0 != id {
+                break;
+            }
+            let text = format!("{} {:.1}s", frame, started.elapsed().as_secs_f64());
+            match dim {
+                true => eprint!("\r\x1b[2m{}\x1b[0m\x1b[K", text),
+                false => eprint!("\r{}\x1b[K", text),
+            }
+            shown.1 = true;
+        }
+    });
+    Spinner
+}
+
+/// Take the spinner off the screen for good.
+pub fn stop() {
+    let mut shown = shown();
+    if shown.1 {
+        eprint!("\r\x1b[K");
+    }
+    *shown = (0, false);
+}
+
+/// Take the spinner's line off the screen so a note can have it; it comes back below.
+pub fn pause() {
+    let mut shown = shown();
+    if std::mem::take(&mut shown.1) {
+        eprint!("\r\x1b[K");
+    }
+}
@@ -0,0 +1,278 @@
+use crate::ai_memory::{ImportStats, Memory};
+use crate::archive;
+use crate::aws_sigv4::{self, AwsCredentials, Signer};
+use crate::http;
+use anyhow::{anyhow, Context, Result};
+use reqwest::{header, Client, Method, RequestBuilder, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the shared copy in a git repository, or under an S3 prefix / WebDAV folder ending in `/`.
+const FILE_NAME: &str = "mindlink-memory.json";
+
+/// Where `mindlink sync` keeps the shared copy of the memory (`AI_SYNC_REMOTE`).
+pub enum Remote {
+    /// `git+<url or path>`, or anything ending in `.git`.
+    Git(String),
+    /// `s3://bucket/key`; `AWS_ENDPOINT_URL` points it at an S3-compatible server.
+    S3 { bucket: String, key: String },
+    /// An `http(s)://` URL on a WebDAV server, with basic auth from `AI_SYNC_USER` / `AI_SYNC_PASSWORD`.
+    WebDav(String),
+}
+
+impl Remote {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let in_folder = |s: &str| {
+            if s.is_empty() || s.ends_with('/') {
+                format!("{}{}", s, FILE_NAME)
+            } else {
+                s.to_string()
+            }
+        };
+        if let Some(url) = spec.strip_prefix("git+") {
+            return Ok(Remote::Git(url.to_string()));
+        }
+        if spec.ends_with(".git") {
+            return Ok(Remote::Git(spec.to_string()));
+        }
+        if let Some(rest) = spec.strip_prefix("s3://") {
+            let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+            let key = in_folder(key);
+            if bucket.is_empty() {
+                return Err(anyhow!("{} names no bucket", spec));
+            }
+            // such keys read the same percent-encoded or not, which is what the signer assumes for S3
+            if !key
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_.~/".contains(&b))
+            {
+                return Err(anyhow!(
+                    "S3 key {} may only use letters, digits and - _ . ~ /",
+                    key
+                ));
+            }
+            return Ok(Remote::S3 {
+                bucket: bucket.to_string(),
+                key,
+            });
+        }
+        if spec.starts_with("https://") || spec.starts_with("http://") {
+            return Ok(Remote::WebDav(in_folder(spec)));
+        }
+        Err(anyhow!(
+            "unknown sync remote {} (expected git+<url>, s3://bucket/key or a WebDAV https:// URL)",
+            spec
+        ))
+    }
+
+    /// The shared copy, or `None` when nobody has pushed one yet.
+    async fn pull(&self, client: &Client) -> Result<Option<String>> {
+        match self {
+            Remote::Git(url) => {
+                let dir = git_checkout(url)?;
+                match std::fs::read_to_string(dir.join(FILE_NAME)) {
+                    Ok(text) => Ok(Some(text)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Remote::S3 { bucket, key } => {
+                let res = s3_request(client, Method::GET, bucket, key, None)?
+                    .send()
+                    .await?;
+                body_unless_missing(res).await
+            }
+            Remote::WebDav(url) => {
+                let res = webdav_auth(client.get(url)).send().await?;
+                body_unless_missing(res).await
+            }
+        }
+    }
+
+    async fn push(&self, client: &Client, text: String) -> Result<()> {
+        match self {
+            Remote::Git(url) => {
+                let dir = git_checkout(url)?;
+                std::fs::write(dir.join(FILE_NAME), text)?;
+                git(&dir, &["add", FILE_NAME])?;
+                if !git(&dir, &["status", "--porcelain"])?.trim().is_empty() {
+                    // a machine that only syncs may have no git identity set up
+                    let mut commit = vec!["commit", "-q", "-m", "mindlink sync"];
+                    if git(&dir, &["config", "user.email"]).is_err() {
+                        commit.splice(
+                            0..0,
+                            [
+                                "-c",
+                                "user.name=mindlink",
+                                "-c",
+                                "user.email=mindlink@localhost",
+                            ],
+                        );
+                    }
+                    git(&dir, &commit)?;
+                }
+                let branch = git(&dir, &["symbolic-ref", "--short", "HEAD"])?;
+                git(&dir, &["push", "-q", "origin", branch.trim()])?;
+                Ok(())
+            }
+            Remote::S3 { bucket, key } => {
+                let res = s3_request(client, Method::PUT, bucket, key, Some(text))?
+                    .send()
+                    .await?;
+                ok_or_error(res).await
+            }
+            Remote::WebDav(url) => {
+                let res = webdav_auth(client.put(url))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(text)
+                    .send()
+                    .await?;
+                ok_or_error(res).await
+            }
+        }
+    }
+}
+
+/// Pull the shared copy and merge it in (newer edits win turn by turn), then push the merged
+/// memory back. Returns what the merge did and how many turns were pushed.
+pub async fn sync(mem: &Memory, remote: &Remote) -> Result<(ImportStats, usize)> {
+    let client = http::client("sync")?;
+    let stats = match remote.pull(&client).await? {
+        Some(text) => mem.import(&archive::load(&text).context("reading the remote copy")?)?,
+        None => ImportStats::default(),
+    };
+    let merged = mem.export(None)?;
+    let pushed = merged.sessions.iter().map(|s| s.turns.len()).sum();
+    remote
+        .push(&client, serde_json::to_string_pretty(&merged)? + "\n")
+        .await?;
+    Ok((stats, pushed))
+}
+
+async fn body_unless_missing(res: Response) -> Result<Option<String>> {
+    if res.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let status = res.status();
+    let text = res.text().await?;
+    if !status.is_success() {
+        return Err(anyhow!("remote answered {}: {}", status, text.trim()));
+    }
+    Ok(Some(text))
+}
+
+async fn ok_or_error(res: Response) -> Result<()> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "remote answered {}: {}",
+        status,
+        res.text().await.unwrap_or_default().trim()
+    ))
+}
+
+fn webdav_auth(rb: RequestBuilder) -> RequestBuilder {
+    match env::var("AI_SYNC_USER") {
+        Ok(user) => rb.basic_auth(user, env::var("AI_SYNC_PASSWORD").ok()),
+        Err(_) => rb,
+    }
+}
+
+fn s3_request(
+    client: &Client,
+    method: Method,
+    bucket: &str,
+    key: &str,
+    body: Option<String>,
+) -> Result<RequestBuilder> {
+    let region = aws_sigv4::region();
+    // virtual-hosted on AWS, path-style on S3-compatible servers such as MinIO
+    let (base, host, path) = match env::var("AWS_ENDPOINT_URL") {
+        Ok(endpoint) => {
+            let url = reqwest::Url::parse(&endpoint)
+                .with_context(|| format!("invalid AWS_ENDPOINT_URL {}", endpoint))?;
+            let host = match (url.host_str(), url.port()) {
+                (Some(h), Some(p)) => format!("{}:{}", h, p),
+                (Some(h), None) => h.to_string(),
+                (None, _) => return Err(anyhow!("AWS_ENDPOINT_URL {} has no host", endpoint)),
+            };
+            let base = endpoint.trim_end_matches('/').to_string();
+            (base, host, format!("/{}/{}", bucket, key))
+        }
+        Err(_) => {
+            let host = format!("{}.s3.{}.amazonaws.com", bucket, region);
+            (format!("https://{}", host), host, format!("/{}", key))
+        }
+    };
+    let payload = body.as_deref().unwrap_or("").as_bytes();
+    let hash = hex::encode(Sha256::digest(payload));
+    let signer = Signer {
+        creds: AwsCredentials::from_env()?,
+        service: "s3".into(),
+        region,
+    };
+    let mut rb = client
+        .request(method.clone(), format!("{}{}", base, path))
+        .header("x-amz-content-sha256", &hash);
+    for (k, v) in signer.sign(
+        method.as_str(),
+        &host,
+        &path,
+        &[("x-amz-content-sha256", &hash)],
+        payload,
+    ) {
+        rb = rb.header(k, v);
+    }
+    if let Some(body) = body {
+        rb = rb
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body);
+    }
+    Ok(rb)
+}
+
+/// A clone of `url` under `~/.mindlink/sync`, up to date with the remote. The clone holds
+/// nothing of its own: the file is rewritten from the database on every push.
+fn git_checkout(url: &str) -> Result<PathBuf> {
+    let name = hex::encode(&Sha256::digest(url.as_bytes())[..8]);
+    let dir = dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".mindlink")
+        .join("sync")
+        .join(name);
+    if !dir.join(".git").exists() {
+        std::fs::create_dir_all(&dir)?;
+        git(&dir, &["clone", "-q", url, "."])?;
+        return Ok(dir);
+    }
+    git(&dir, &["fetch", "-q", "origin"])?;
+    let branch = git(&dir, &["symbolic-ref", "--short", "HEAD"])?;
+    let upstream = format!("origin/{}", branch.trim());
+    // an empty remote has no branch to follow yet
+    if git(&dir, &["rev-parse", "--verify", "-q", &upstream]).is_ok() {
+        git(&dir, &["reset", "-q", "--hard", &upstream])?;
+    }
+    Ok(dir)
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("running git (is it installed?)")?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
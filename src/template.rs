@@ -0,0 +1,112 @@
+use crate::config;
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Filled with piped stdin by `template run` when `--set` doesn't give it.
+pub const INPUT: &str = "input";
+
+fn placeholder() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| {
+        Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_-]*)\s*\}\}").expect("built-in template pattern")
+    })
+}
+
+/// `templates` in the global config directory, one `<name>.md` file per template.
+pub fn dir() -> Result<PathBuf> {
+    config::named_dir("templates")
+}
+
+fn path(name: &str) -> Result<PathBuf> {
+    config::named_file("templates", name, "md")
+}
+
+/// Save `text` as template `name`; an existing one is only replaced with `force`. Returns the
+/// file written.
+pub fn add(name: &str, text: &str, force: bool) -> Result<PathBuf> {
+    let path = path(name)?;
+    if !force && path.exists() {
+        return Err(anyhow!(
+            "there is a template named '{}' already; --force replaces it",
+            name
+        ));
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, text).with_context(|| format!("writing {}", path.display()))?;
+    Ok(path)
+}
+
+pub fn load(name: &str) -> Result<String> {
+    let path = path(name)?;
+    std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => {
+            anyhow!(
+                "no template named '{}' (see `mindlink template list`)",
+                name
+            )
+        }
+        _ => anyhow!("reading {}: {}", path.display(), e),
+    })
+}
+
+/// Every template's name and text, by name.
+pub fn list() -> Result<Vec<(String, String)>> {
+    let dir = dir()?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", dir.display())),
+    };
+    let mut templates = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(".md"))
+        else {
+            continue;
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        templates.push((name.to_string(), text));
+    }
+    templates.sort();
+    Ok(templates)
+}
+
+/// The names of the `{{placeholders}}` in `text`, each once, in the order they first appear.
+pub fn placeholders(text: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for c in placeholder().captures_iter(text) {
+        if !names.iter().any(|n| *n == c[1]) {
+            names.push(c[1].to_string());
+        }
+    }
+    names
+}
+
+/// `text` with its placeholders replaced by `values`; all of them have to be given.
+pub fn render(text: &str, values: &BTreeMap<String, String>) -> Result<String> {
+    let missing: Vec<String> = placeholders(text)
+        .into_iter()
+        .filter(|n| !values.contains_key(n))
+        .collect();
+    if !missing.is_empty() {
+        let flags: Vec<String> = missing.iter().map(|n| format!("--set {}=...", n)).collect();
+        let piped = if missing.iter().any(|n| n == INPUT) {
+            " (stdin piped in fills {{input}})"
+        } else {
+            ""
+        };
+        return Err(anyhow!("the template needs {}{}", flags.join(" "), piped));
+    }
+    Ok(placeholder()
+        .replace_all(text, |c: &regex::Captures| values[&c[1]].clone())
+        .into_owned())
+}
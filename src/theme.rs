@@ -0,0 +1,98 @@
+use std::env;
+use std::io::IsTerminal;
+
+const RESET: &str = "\x1b[0m";
+const NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+#[derive(Clone, Copy)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Whether colour may go to `stream`: it is a terminal and `NO_COLOR` isn't set (to anything but
+/// an empty string, as no-color.org has it). `--no-color` sets `NO_COLOR`.
+pub fn enabled(stream: Stream) -> bool {
+    let terminal = match stream {
+        Stream::Stdout => std::io::stdout().is_terminal(),
+        Stream::Stderr => std::io::stderr().is_terminal(),
+    };
+    terminal && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+/// The escape code for a colour: a name (`cyan`, `bright-red`), a number from the 256-colour
+/// palette or `#rrggbb`. `None` for `none`; `Err` for anything else.
+fn code(spec: &str) -> Result<Option<String>, ()> {
+    let spec = spec.trim().to_ascii_lowercase();
+    if spec.is_empty() || spec == "none" {
+        return Ok(None);
+    }
+    let (bright, name) = match spec.strip_prefix("bright-") {
+        Some(name) => (true, name),
+        None => (false, spec.as_str()),
+    };
+    if let Some(i) = NAMES.iter().position(|n| *n == name) {
+        return Ok(Some(format!("\x1b[{}m", i + if bright { 90 } else { 30 })));
+    }
+    if let Ok(n) = spec.parse::<u8>() {
+        return Ok(Some(format!("\x1b[38;5;{}m", n)));
+    }
+    let hex = spec.strip_prefix('#').filter(|h| h.len() == 6).ok_or(())?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| ());
+    Ok(Some(format!(
+        "\x1b[38;2;{};{};{}m",
+        channel(0)?,
+        channel(2)?,
+        channel(4)?
+    )))
+}
+
+/// The colour setting `var` asks for, else `default`, as an escape code; `None` when it is
+/// `none` or colour can't go to `stream`.
+fn color(var: &str, default: &str, stream: Stream) -> Option<String> {
+    if !enabled(stream) {
+        return None;
+    }
+    let spec = env::var(var).unwrap_or_else(|_| default.to_string());
+    code(&spec).unwrap_or_else(|()| {
+        note!(
+            "({}={:?} isn't a colour: use a name like cyan or bright-red, 0-255 or #rrggbb)",
+            var,
+            spec
+        );
+        code(default).ok().flatten()
+    })
+}
+
+/// `AI_PROMPT_COLOR` (cyan): the chat's `mindlink>` prompt.
+pub fn prompt() -> Option<String> {
+    color("AI_PROMPT_COLOR", "cyan", Stream::Stdout)
+}
+
+/// `AI_ASSISTANT_COLOR` (none): answers, under their markdown styling.
+pub fn assistant() -> Option<String> {
+    color("AI_ASSISTANT_COLOR", "none", Stream::Stdout)
+}
+
+/// `text` in `AI_ERROR_COLOR` (red), for an error on stderr.
+pub fn error(text: &str) -> String {
+    paint(
+        text,
+        color("AI_ERROR_COLOR", "red", Stream::Stderr).as_deref(),
+    )
+}
+
+/// `text` in `color`, which comes back after each reset in the text itself.
+pub fn paint(text: &str, color: Option<&str>) -> String {
+    match color {
+        Some(c) if !text.is_empty() => format!(
+            "{}{}{}",
+            c,
+            text.replace(RESET, &format!("{}{}", RESET, c)),
+            RESET
+        ),
+        _ => text.to_string(),
+    }
+}
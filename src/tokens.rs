@@ -0,0 +1,99 @@
+/// Rough token count, following how tiktoken pre-splits text: words, digit groups of up to
+/// three, punctuation runs and whitespace. Long words count as several tokens, and every
+/// non-ASCII character (CJK, emoji...) as one, so the estimate errs on the high side.
+pub fn estimate(text: &str) -> usize {
+    #[derive(PartialEq, Clone, Copy)]
+    enum Run {
+        Start,
+        Word,
+        Digits,
+        Punct,
+        Space,
+    }
+    let mut tokens = 0;
+    let mut run = Run::Start;
+    let mut len = 0;
+    let flush = |run: Run, len: usize| match run {
+        Run::Start => 0,
+        Run::Word => len.div_ceil(6),
+        Run::Digits => len.div_ceil(3),
+        Run::Punct => len.div_ceil(2),
+        // a single space joins the next word's token
+        Run::Space => usize::from(len > 1),
+    };
+    for c in text.chars() {
+        let kind = if !c.is_ascii() {
+            tokens += flush(run, len);
+            tokens += 1;
+            run = Run::Start;
+            len = 0;
+            continue;
+        } else if c.is_ascii_alphabetic() {
+            Run::Word
+        } else if c.is_ascii_digit() {
+            Run::Digits
+        } else if c.is_ascii_whitespace() {
+            Run::Space
+        } else {
+            Run::Punct
+        };
+        if kind != run {
+            tokens += flush(run, len);
+            run = kind;
+            len = 0;
+        }
+        len += 1;
+    }
+    tokens + flush(run, len)
+}
+
+/// Cut `text` to about `max` tokens, keeping its start and end, which usually hold the question
+/// and the conclusion of a long paste.
+pub fn truncate(text: &str, max: usize) -> String {
+    let tokens = estimate(text);
+    if tokens <= max {
+        return text.to_string();
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let keep = chars.len() * max / tokens;
+    let head: String = chars[..keep / 2].iter().collect();
+    let tail: String = chars[chars.len() - keep / 2..].iter().collect();
+    format!(
+        "{}\n[... {} tokens cut to fit the context budget ...]\n{}",
+        head,
+        tokens - max,
+        tail
+    )
+}
+
+/// Context window of well-known models, in tokens. Matched on a substring so routed names
+/// like `openai/gpt-4o` work; more specific names come first.
+pub fn context_window(model: &str) -> Option<usize> {
+    const WINDOWS: &[(&str, usize)] = &[
+        ("gpt-4.1", 1_047_576),
+        ("gpt-5", 400_000),
+        ("gpt-4o", 128_000),
+        ("gpt-4-turbo", 128_000),
+        ("gpt-4", 8_192),
+        ("gpt-3.5-turbo", 16_385),
+        ("o1", 200_000),
+        ("o3", 200_000),
+        ("o4", 200_000),
+        ("claude", 200_000),
+        ("gemini", 1_000_000),
+        ("mistral-large", 128_000),
+        ("mistral-small", 32_000),
+        ("codestral", 256_000),
+        ("llama-3.1", 128_000),
+        ("llama-3.2", 128_000),
+        ("llama-3.3", 128_000),
+        ("llama3", 8_192),
+        ("mixtral", 32_768),
+        ("qwen", 32_768),
+    ];
+    let model = model.to_ascii_lowercase();
+    WINDOWS
+        .iter()
+        .find(|(name, _)| model.contains(name))
+        .map(|&(_, n)| n)
+}
@@ -0,0 +1,171 @@
+use crate::providers::ToolSpec;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use std::sync::Arc;
+
+// keeps a stray huge file from blowing the context window
+const READ_FILE_LIMIT: usize = 64 * 1024;
+// mindlink's own settings and memory, which AI_MEMORY_DIR can put outside a dot-directory
+const PRIVATE_FILES: &[&str] = &["config.toml", "memory.db", "memory.jsonl"];
+
+/// A function the model can call during a turn.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn spec(&self) -> ToolSpec;
+    /// Run with the model's JSON arguments; the returned text is fed back to it.
+    async fn call(&self, args: Value) -> Result<String>;
+}
+
+/// Tools by name, offered to the model when `AI_TOOLS` is on.
+pub struct ToolRegistry {
+    tools: HashMap<String, Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn with_defaults() -> Self {
+        let mut reg = Self {
+            tools: HashMap::new(),
+        };
+        reg.register(Arc::new(CurrentTime));
+        reg.register(Arc::new(ReadFile));
+        reg
+    }
+
+    pub fn register(&mut self, tool: Arc<dyn Tool>) {
+        self.tools.insert(tool.spec().name, tool);
+    }
+
+    /// Specs of every tool, sorted by name so requests stay byte-identical.
+    pub fn specs(&self) -> Vec<ToolSpec> {
+        let mut specs: Vec<ToolSpec> = self.tools.values().map(|t| t.spec()).collect();
+        specs.sort_by(|a, b| a.name.cmp(&b.name));
+        specs
+    }
+
+    /// Run a call; failures are reported to the model as the result rather than aborting the turn.
+    pub async fn dispatch(&self, name: &str, arguments: &str) -> String {
+        let result = match self.tools.get(name) {
+            Some(tool) => match serde_json::from_str::<Value>(arguments) {
+                Ok(args) => tool.call(args).await,
+                Err(e) => Err(anyhow!("arguments are not valid JSON: {}", e)),
+            },
+            None => Err(anyhow!("no tool named '{}'", name)),
+        };
+        result.unwrap_or_else(|e| format!("error: {:#}", e))
+    }
+}
+
+struct CurrentTime;
+
+#[async_trait]
+impl Tool for CurrentTime {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "current_time".into(),
+            description: "Current local date and time (RFC 3339).".into(),
+            parameters: json!({"type": "object", "properties": {}}),
+        }
+    }
+
+    async fn call(&self, _args: Value) -> Result<String> {
+        Ok(chrono::Local::now().to_rfc3339())
+    }
+}
+
+/// Reads text files below the working directory, never outside it, and never hidden or private ones.
+struct ReadFile;
+
+/// Whether `rel`, relative to the working directory, is hidden (`.env`, anything in `.mindlink/`
+/// or `.git/`) or one of mindlink's own files, which may hold keys or other conversations.
+fn is_private(rel: &Path) -> bool {
+    let hidden = rel.components().any(|c| match c {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    });
+    let own = rel.file_name().is_some_and(|name| {
+        let name = name.to_string_lossy();
+        // with the database's -wal and -shm files
+        PRIVATE_FILES.iter().any(|p| {
+            name.strip_prefix(p)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+        })
+    });
+    hidden || own
+}
+
+#[async_trait]
+impl Tool for ReadFile {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "read_file".into(),
+            description: "Read a UTF-8 text file, by path relative to the current directory."
+                .into(),
+            parameters: json!({
+                "type": "object",
+                "properties": {"path": {"type": "string"}},
+                "required": ["path"],
+            }),
+        }
+    }
+
+    async fn call(&self, args: Value) -> Result<String> {
+        let path = args
+            .get("path")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("missing 'path'"))?;
+        let root = std::env::current_dir()?.canonicalize()?;
+        let full = root
+            .join(path)
+            .canonicalize()
+            .with_context(|| format!("cannot open {}", path))?;
+        // checked after resolving links, so a harmless-looking name can't lead into one
+        let rel = full
+            .strip_prefix(&root)
+            .map_err(|_| anyhow!("{} is outside the working directory", path))?;
+        if is_private(rel) {
+            return Err(anyhow!("{} is hidden or private; it can't be read", path));
+        }
+        let bytes = std::fs::read(&full)?;
+        let mut text = String::from_utf8(bytes).map_err(|_| anyhow!("{} is not text", path))?;
+        if text.len() > READ_FILE_LIMIT {
+            let mut cut = READ_FILE_LIMIT;
+            while !text.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            text.truncate(cut);
+            text.push_str("\n[truncated]");
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hidden_and_private_files_are_refused() {
+        for path in [
+            ".env",
+            ".mindlink/config.toml",
+            ".mindlink/memory.db",
+            "src/.git/config",
+            "config.toml",
+            "profiles/memory.db-wal",
+            "memory.jsonl",
+        ] {
+            assert!(is_private(Path::new(path)), "{} should be refused", path);
+        }
+        for path in [
+            "README.md",
+            "src/main.rs",
+            "memory.dbx",
+            "notes/config.toml.md",
+        ] {
+            assert!(!is_private(Path::new(path)), "{} should be readable", path);
+        }
+    }
+}
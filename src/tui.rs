@@ -0,0 +1,424 @@
+use crate::ai::AiAgent;
+use crate::ai_memory::TurnFilter;
+use crate::interrupt;
+use crate::theme::{self, Stream};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::io::IsTerminal;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// How many of a session's latest turns the conversation pane starts with.
+const LOADED_TURNS: usize = 200;
+const SIDEBAR_WIDTH: u16 = 24;
+// PageUp and PageDown
+const SCROLL_STEP: usize = 10;
+
+#[derive(PartialEq)]
+enum Focus {
+    Input,
+    Sessions,
+}
+
+/// What a key press asks of the agent.
+enum Action {
+    None,
+    Send(String),
+    Switch(String),
+    Quit,
+}
+
+struct App {
+    /// (role, text), oldest first; while an answer streams it is the last one, growing.
+    turns: Vec<(String, String)>,
+    input: String,
+    // in chars
+    cursor: usize,
+    // lines up from the bottom of the conversation; 0 follows the latest
+    scroll: usize,
+    focus: Focus,
+    sessions: Vec<String>,
+    selected: ListState,
+    session: String,
+    model: String,
+    context: usize,
+    used: (i64, i64),
+    streaming: bool,
+    // the last error or notice, shown in the status bar until the next key press
+    status: Option<String>,
+    began: DateTime<Utc>,
+}
+
+impl App {
+    /// The current session's turns and what the status bar shows, read again.
+    async fn reload(&mut self, agent: &AiAgent) -> Result<()> {
+        self.turns = agent
+            .memory_show(LOADED_TURNS, &TurnFilter::default())?
+            .into_iter()
+            .map(|t| (t.role, t.content))
+            .collect();
+        self.scroll = 0;
+        self.refresh(agent).await
+    }
+
+    async fn refresh(&mut self, agent: &AiAgent) -> Result<()> {
+        // the last exchange may still be on its way to memory
+        agent.flush_memory().await;
+        let mem = agent.memory();
+        self.session = mem.current_session()?;
+        self.sessions = mem.sessions()?.into_iter().map(|s| s.name).collect();
+        let current = self.sessions.iter().position(|s| *s == self.session);
+        self.selected.select(current);
+        self.model = agent.model();
+        self.context = agent.context_estimate()?;
+        let filter = TurnFilter {
+            since: Some(self.began),
+            ..Default::default()
+        };
+        let turns = agent.memory_show(100_000, &filter)?;
+        self.used = turns.iter().fold((0, 0), |(p, c), t| {
+            (
+                p + t.prompt_tokens.unwrap_or(0),
+                c + t.completion_tokens.unwrap_or(0),
+            )
+        });
+        Ok(())
+    }
+
+    fn key(&mut self, key: KeyEvent) -> Action {
+        self.status = None;
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if self.streaming {
+            match key.code {
+                KeyCode::Esc => interrupt::cancel_stream(),
+                KeyCode::Char('c') if ctrl => interrupt::cancel_stream(),
+                KeyCode::PageUp | KeyCode::PageDown => self.scroll(key.code),
+                _ => {}
+            }
+            return Action::None;
+        }
+        match key.code {
+            KeyCode::Char('c' | 'd') if ctrl => return Action::Quit,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Input => Focus::Sessions,
+                    Focus::Sessions => Focus::Input,
+                }
+            }
+            KeyCode::PageUp | KeyCode::PageDown => self.scroll(key.code),
+            _ if self.focus == Focus::Sessions => return self.sessions_key(key.code),
+            KeyCode::Enter => {
+                let prompt = self.input.trim().to_string();
+                self.input.clear();
+                self.cursor = 0;
+                if prompt == "exit" || prompt == "quit" {
+                    return Action::Quit;
+                }
+                if !prompt.is_empty() {
+                    return Action::Send(prompt);
+                }
+            }
+            KeyCode::Char('u') if ctrl => {
+                self.input.clear();
+                self.cursor = 0;
+            }
+            KeyCode::Char(c) if !ctrl => {
+                let at = self.byte_at(self.cursor);
+                self.input.insert(at, c);
+                self.cursor += 1;
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                let at = self.byte_at(self.cursor);
+                self.input.remove(at);
+            }
+            KeyCode::Delete if self.cursor < self.input.chars().count() => {
+                let at = self.byte_at(self.cursor);
+                self.input.remove(at);
+            }
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.input.chars().count()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.input.chars().count(),
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn sessions_key(&mut self, code: KeyCode) -> Action {
+        match code {
+            KeyCode::Up => self.selected.select_previous(),
+            KeyCode::Down => self.selected.select_next(),
+            KeyCode::Esc => self.focus = Focus::Input,
+            KeyCode::Enter => {
+                self.focus = Focus::Input;
+                let chosen = self.selected.selected().and_then(|i| self.sessions.get(i));
+                if let Some(name) = chosen.filter(|s| **s != self.session) {
+                    return Action::Switch(name.clone());
+                }
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn scroll(&mut self, code: KeyCode) {
+        self.scroll = match code {
+            KeyCode::PageUp => self.scroll + SCROLL_STEP,
+            _ => self.scroll.saturating_sub(SCROLL_STEP),
+        };
+    }
+
+    fn byte_at(&self, chars: usize) -> usize {
+        self.input
+            .char_indices()
+            .nth(chars)
+            .map_or(self.input.len(), |(i, _)| i)
+    }
+
+    fn draw(&mut self, f: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(f.area());
+        let [sidebar, right] =
+            Layout::horizontal([Constraint::Length(SIDEBAR_WIDTH), Constraint::Min(20)])
+                .areas(main);
+        let [conversation, input] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(3)]).areas(right);
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        // under NO_COLOR, bold has to do on its own
+        let coloured = theme::enabled(Stream::Stdout);
+        let fg = |style: Style, colour: Color| {
+            if coloured {
+                style.fg(colour)
+            } else {
+                style
+            }
+        };
+        let focused = |yes: bool| match yes {
+            true if coloured => Style::default().fg(Color::Cyan),
+            true => bold,
+            false => Style::default(),
+        };
+
+        let items: Vec<ListItem> = self
+            .sessions
+            .iter()
+            .map(|s| {
+                let mark = if *s == self.session { "* " } else { "  " };
+                ListItem::new(format!("{}{}", mark, s))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title(" sessions ")
+                    .border_style(focused(self.focus == Focus::Sessions)),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, sidebar, &mut self.selected);
+
+        let width = conversation.width.saturating_sub(2) as usize;
+        let height = conversation.height.saturating_sub(2) as usize;
+        let mut lines: Vec<Line> = Vec::new();
+        for (role, text) in &self.turns {
+            let (name, colour) = match role.as_str() {
+                "user" => ("you", Color::Cyan),
+                "assistant" => ("mindlink", Color::Green),
+                other => (other, Color::Yellow),
+            };
+            lines.push(Line::styled(name.to_string(), fg(bold, colour)));
+            lines.extend(wrap(text, width).into_iter().map(Line::raw));
+            lines.push(Line::raw(""));
+        }
+        // scrolled no further than the first line
+        self.scroll = self.scroll.min(lines.len().saturating_sub(height));
+        let end = lines.len() - self.scroll;
+        let visible: Vec<Line> = lines.drain(end.saturating_sub(height)..end).collect();
+        let title = match self.scroll {
+            0 => format!(" {} ", self.session),
+            n => format!(" {} (scrolled up {} lines) ", self.session, n),
+        };
+        f.render_widget(
+            Paragraph::new(visible).block(Block::bordered().title(title)),
+            conversation,
+        );
+
+        let title = if self.streaming {
+            " answering (Esc cancels) "
+        } else {
+            " prompt "
+        };
+        let room = input.width.saturating_sub(3) as usize;
+        // the end of a long line, so the cursor stays in sight
+        let skip = self.cursor.saturating_sub(room);
+        let shown: String = self.input.chars().skip(skip).collect();
+        f.render_widget(
+            Paragraph::new(shown).block(
+                Block::bordered()
+                    .title(title)
+                    .border_style(focused(self.focus == Focus::Input && !self.streaming)),
+            ),
+            input,
+        );
+        if self.focus == Focus::Input && !self.streaming {
+            let x = input.x + 1 + (self.cursor - skip) as u16;
+            f.set_cursor_position(Position::new(x, input.y + 1));
+        }
+
+        let line = match &self.status {
+            Some(message) => Line::styled(message.clone(), fg(Style::default(), Color::Red)),
+            None => Line::from(vec![
+                Span::styled(format!(" {} ", self.model), bold),
+                Span::raw(format!(
+                    "· ~{} tokens of context · this run {}+{} tokens · Tab sessions · PgUp/PgDn scroll · Ctrl-C quits",
+                    self.context, self.used.0, self.used.1
+                )),
+            ]),
+        };
+        f.render_widget(
+            Paragraph::new(line).style(Style::default().add_modifier(Modifier::DIM)),
+            status,
+        );
+    }
+}
+
+/// `text` in lines at most `width` columns wide, broken at spaces where it can be.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for raw in text.replace('\t', "    ").split('\n') {
+        let (mut line, mut len) = (String::new(), 0);
+        for (i, word) in raw.split(' ').enumerate() {
+            let n = word.chars().count();
+            if i > 0 {
+                if len > 0 && len + 1 + n > width {
+                    lines.push(std::mem::take(&mut line));
+                    len = 0;
+                } else {
+                    line.push(' ');
+                    len += 1;
+                }
+            }
+            // a word wider than the pane is split
+            for c in word.chars() {
+                if len >= width {
+                    lines.push(std::mem::take(&mut line));
+                    len = 0;
+                }
+                line.push(c);
+                len += 1;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// `mindlink tui`: the conversation, a prompt box, the sessions and a status bar, full screen.
+pub async fn run(agent: &mut AiAgent) -> Result<()> {
+    if !std::io::stdout().is_terminal() {
+        return Err(anyhow!(
+            "mindlink tui needs a terminal; `mindlink chat` reads piped input"
+        ));
+    }
+    let (sink, mut pieces) = mpsc::unbounded_channel();
+    agent.set_sink(sink);
+    // crossterm's reads block, so they get a thread of their own
+    let (keys_tx, mut keys) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(ev) = event::read() {
+            if keys_tx.send(ev).is_err() {
+                break;
+            }
+        }
+    });
+    let mut app = App {
+        turns: Vec::new(),
+        input: String::new(),
+        cursor: 0,
+        scroll: 0,
+        focus: Focus::Input,
+        sessions: Vec::new(),
+        selected: ListState::default(),
+        session: String::new(),
+        model: String::new(),
+        context: 0,
+        used: (0, 0),
+        streaming: false,
+        status: None,
+        began: Utc::now(),
+    };
+    app.reload(agent).await?;
+    let mut terminal = ratatui::init();
+    let res = event_loop(&mut terminal, &mut app, agent, &mut pieces, &mut keys).await;
+    ratatui::restore();
+    res
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    agent: &mut AiAgent,
+    pieces: &mut UnboundedReceiver<String>,
+    keys: &mut UnboundedReceiver<Event>,
+) -> Result<()> {
+    loop {
+        terminal.draw(|f| app.draw(f))?;
+        let Some(ev) = keys.recv().await else {
+            return Ok(());
+        };
+        let Event::Key(key) = ev else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match app.key(key) {
+            Action::None => {}
+            Action::Quit => return Ok(()),
+            Action::Switch(name) => {
+                agent.memory().session_switch(&name)?;
+                agent.set_context_start(None);
+                app.reload(agent).await?;
+            }
+            Action::Send(prompt) => {
+                app.turns.push(("user".into(), prompt.clone()));
+                app.turns.push(("assistant".into(), String::new()));
+                app.scroll = 0;
+                app.streaming = true;
+                let answer = agent.ask_streaming(&prompt, &[]);
+                tokio::pin!(answer);
+                let res = loop {
+                    terminal.draw(|f| app.draw(f))?;
+                    tokio::select! {
+                        res = &mut answer => break res,
+                        Some(piece) = pieces.recv() => push_piece(app, &piece),
+                        Some(ev) = keys.recv() => {
+                            if let Event::Key(key) = ev {
+                                if key.kind == KeyEventKind::Press {
+                                    app.key(key);
+                                }
+                            }
+                        }
+                    }
+                };
+                while let Ok(piece) = pieces.try_recv() {
+                    push_piece(app, &piece);
+                }
+                app.streaming = false;
+                if let Err(e) = res.and(app.refresh(agent).await) {
+                    app.status = Some(format!(" {:#}", e));
+                }
+            }
+        }
+    }
+}
+
+fn push_piece(app: &mut App, piece: &str) {
+    if let Some((_, text)) = app.turns.last_mut() {
+        text.push_str(piece);
+    }
+}